@@ -0,0 +1,143 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use futures_core::Stream;
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::decoder::Locate;
+use crate::error::GzTestError;
+use crate::push::{PushDecoder, PushLines};
+
+// 内側の入力から一度に読むバイト数。
+const READ_SIZE: usize = 64 * 1024;
+
+/// `AsyncRead` から読む gzip データをすべてのメンバーまで展開する、[`open_reader`](crate::open_reader) の非同期版。
+///
+/// 読んだ分を [`PushDecoder`] に渡すので、CRC32 と ISIZE の確認、エラーの種類と位置は同期版と同じ。
+/// エラーは [`GzTestError`] を包んだ `io::Error` で返し、[`GzTestError::from_io`] で取り出せる。エラーの後は入力の終わりを返す。
+pub struct AsyncDecoder<R> {
+    inner: R,
+    decoder: PushDecoder,
+    buf: Box<[u8]>,
+    // 展開したデータと、そのうちまだ返していない部分の先頭。
+    out: Vec<u8>,
+    pos: usize,
+    // 入力の終わりかエラーまで読んだ。
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> AsyncDecoder<R> {
+    /// `name` はエラーの位置を示すのに使う。
+    pub fn new(inner: R, name: &str) -> Self {
+        AsyncDecoder { inner, decoder: PushDecoder::new(name), buf: vec![0; READ_SIZE].into(), out: Vec::new(), pos: 0, done: false }
+    }
+
+    /// 内側のデコーダー。展開し終えたメンバーの情報は [`PushDecoder::take_members`] で取り出せる。
+    pub fn decoder(&mut self) -> &mut PushDecoder {
+        &mut self.decoder
+    }
+
+    // 入力を1回読んで展開する。
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), GzTestError>> {
+        let mut read = ReadBuf::new(&mut self.buf);
+        ready!(Pin::new(&mut self.inner).poll_read(cx, &mut read))
+            .map_err(|source| GzTestError::Read { at: self.decoder.location(), source })?;
+        self.out.clear();
+        self.pos = 0;
+        if read.filled().is_empty() {
+            self.done = true;
+            return Poll::Ready(self.decoder.finish());
+        }
+        Poll::Ready(self.decoder.feed(read.filled(), &mut self.out))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for AsyncDecoder<R> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, dst: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while this.pos == this.out.len() && !this.done && dst.remaining() > 0 {
+            if let Err(err) = ready!(this.poll_fill(cx)) {
+                this.done = true;
+                return Poll::Ready(Err(err.into()));
+            }
+        }
+        let n = dst.remaining().min(this.out.len() - this.pos);
+        dst.put_slice(&this.out[this.pos..this.pos + n]);
+        this.pos += n;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// `AsyncRead` から読む gzip のテキストを、[`Lines`](crate::lines::Lines) と同じく行ごとに返す `Stream`。
+///
+/// 読んだ分を [`PushLines`] に渡すので、行末の扱い、行番号、エラーは `lines()` と同じ。
+/// エラーを返した後は何も返さない。
+pub struct AsyncLines<R> {
+    inner: R,
+    lines: PushLines,
+    buf: Box<[u8]>,
+    // 揃っていてまだ返していない行と、その後に返すエラー。
+    ready: std::vec::IntoIter<String>,
+    error: Option<GzTestError>,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> AsyncLines<R> {
+    /// `name` はエラーの位置を示すのに使う。
+    pub fn new(inner: R, name: &str) -> Self {
+        AsyncLines {
+            inner,
+            lines: PushLines::new(name),
+            buf: vec![0; READ_SIZE].into(),
+            ready: Vec::new().into_iter(),
+            error: None,
+            done: false,
+        }
+    }
+
+    /// 次の行を返す。`StreamExt` を使わずに `while let Some(line) = lines.next_line().await` で読める。
+    pub async fn next_line(&mut self) -> Option<Result<String, GzTestError>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    // 入力を1回読み、揃った行を ready に入れる。エラーは揃った行の後に返すので error に入れておく。
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut read = ReadBuf::new(&mut self.buf);
+        let mut lines = Vec::new();
+        let result = match ready!(Pin::new(&mut self.inner).poll_read(cx, &mut read)) {
+            Err(source) => Err(GzTestError::Read { at: self.lines.decoder().location(), source }),
+            Ok(()) if read.filled().is_empty() => {
+                self.done = true;
+                self.lines.finish(&mut lines)
+            }
+            Ok(()) => self.lines.feed(read.filled(), &mut lines),
+        };
+        if let Err(err) = result {
+            self.done = true;
+            self.error = Some(err);
+        }
+        self.ready = lines.into_iter();
+        Poll::Ready(())
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for AsyncLines<R> {
+    type Item = Result<String, GzTestError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(line) = this.ready.next() {
+                return Poll::Ready(Some(Ok(line)));
+            }
+            if let Some(err) = this.error.take() {
+                return Poll::Ready(Some(Err(err)));
+            }
+            if this.done {
+                return Poll::Ready(None);
+            }
+            ready!(this.poll_fill(cx));
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+
+use flate2::{Compression, Crc};
+
+use crate::compress::{deflate_block, in_parallel, Blocks};
+use crate::decoder::{MemberInfo, MultiMemberDecoder};
+use crate::error::{GzTestError, Location};
+use crate::header::{Header, TRAILER_LEN};
+
+/// 1ブロックに入れる展開後のバイト数。圧縮できないデータでもブロックが 64 KiB に収まるよう、htslib と同じ値にする。
+pub const BLOCK_DATA_SIZE: u64 = 0xff00;
+
+// ブロック (メンバー) 全体の大きさの上限。BSIZE は u16 なのでこれを超えられない。
+const MAX_BLOCK_SIZE: usize = 1 << 16;
+
+/// ファイルの終わりを表す空のブロック (SAM/BAM 仕様 4.1.2)。
+pub const EOF_BLOCK: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00, 0x1b, 0x00, 0x03,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// ヘッダーの FEXTRA にある BGZF の `BC` サブフィールドから、ブロック全体のバイト数 (BSIZE + 1) を返す。
+/// BGZF のブロックでなければ `None` を返す。
+pub fn block_size(header: &Header) -> Option<u64> {
+    header
+        .extra_subfields()
+        .iter()
+        .find(|sub| sub.si1 == b'B' && sub.si2 == b'C' && sub.data.len() == 2)
+        .map(|sub| u64::from(u16::from_le_bytes([sub.data[0], sub.data[1]])) + 1)
+}
+
+/// すべてのメンバーが `BC` サブフィールドの大きさどおりの BGZF ブロックかを調べる。
+pub fn is_bgzf(members: &[MemberInfo]) -> bool {
+    !members.is_empty() && members.iter().all(|m| block_size(&m.header) == Some(m.compressed_len))
+}
+
+/// 最後のメンバーが EOF ブロックかを調べる。
+pub fn has_eof_block(members: &[MemberInfo]) -> bool {
+    members.last().is_some_and(|m| m.compressed_len == EOF_BLOCK.len() as u64 && m.uncompressed_len == 0)
+}
+
+/// ブロックの開始位置 (圧縮されたファイル上) とブロック内の展開後の位置から、仮想ファイルオフセットを作る。
+pub fn virtual_offset(block_offset: u64, within: u64) -> u64 {
+    block_offset << 16 | within
+}
+
+// BGZF のブロックのヘッダー。MTIME は 0、OS は不明 (255) で、FEXTRA には BSIZE だけを入れる。
+fn block_header(bsize: u16) -> io::Result<Vec<u8>> {
+    let mut extra = vec![b'B', b'C', 2, 0];
+    extra.extend_from_slice(&bsize.to_le_bytes());
+    Header { os: 255, extra: Some(extra), ..Header::default() }.to_bytes()
+}
+
+/// 展開後 [`BLOCK_DATA_SIZE`] バイトまでのデータを1つの BGZF ブロックに圧縮する。
+/// 圧縮しても 64 KiB に収まらない時は圧縮せずに格納する。
+pub fn compress_block(data: &[u8], level: Compression) -> io::Result<Vec<u8>> {
+    let header_len = block_header(0)?.len();
+    let mut deflated = deflate_block(data, level, true)?;
+    if header_len + deflated.len() + TRAILER_LEN > MAX_BLOCK_SIZE {
+        deflated = deflate_block(data, Compression::none(), true)?;
+    }
+    let total = header_len + deflated.len() + TRAILER_LEN;
+    let mut block = block_header((total - 1) as u16)?;
+    block.extend_from_slice(&deflated);
+    let mut crc = Crc::new();
+    crc.update(data);
+    block.extend_from_slice(&crc.sum().to_le_bytes());
+    block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    Ok(block)
+}
+
+/// 入力を BGZF のブロックに分けて `threads` 個のスレッドで圧縮し、書き出す。EOF ブロックは書かない。
+pub fn compress_bgzf<R: Read, W: Write>(input: R, out: &mut W, level: Compression, threads: usize) -> io::Result<()> {
+    let work = |data: Vec<u8>, _| {
+        // 空の入力からはデータのブロックを作らない。
+        if data.is_empty() {
+            return Ok(Vec::new());
+        }
+        compress_block(&data, level)
+    };
+    in_parallel(Blocks::new(input, BLOCK_DATA_SIZE)?, threads, work, |block| out.write_all(&block))
+}
+
+/// BGZF ファイルを読み、仮想ファイルオフセットで読む位置を表したり移したりできる `BufRead`。
+///
+/// 各ブロックは [`MultiMemberDecoder`] で1メンバーずつ展開するので、CRC32 と ISIZE も確かめる。
+/// BSIZE とメンバーの大きさが合わないブロックはエラーにする。
+pub struct BgzfReader<R> {
+    input: R,
+    name: String,
+    // 0から数えたブロックの番号。シークした後はシークした位置から数える。
+    member: u64,
+    block_offset: u64,
+    next_block: u64,
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: BufRead + Seek> BgzfReader<R> {
+    /// `input` は BGZF ファイルの先頭を指していること。
+    pub fn new(input: R, name: &str) -> Self {
+        BgzfReader {
+            input,
+            name: name.to_string(),
+            member: 0,
+            block_offset: 0,
+            next_block: 0,
+            data: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// 次に読むバイトの仮想ファイルオフセット。ブロックを読み終えていれば次のブロックの先頭を指す。
+    pub fn virtual_offset(&self) -> u64 {
+        if self.pos < self.data.len() {
+            virtual_offset(self.block_offset, self.pos as u64)
+        } else {
+            virtual_offset(self.next_block, 0)
+        }
+    }
+
+    /// 仮想ファイルオフセット `offset` に移る。
+    pub fn seek_virtual(&mut self, offset: u64) -> io::Result<()> {
+        let (block_offset, within) = (offset >> 16, (offset & 0xffff) as usize);
+        self.input.seek(SeekFrom::Start(block_offset))?;
+        self.member = 0;
+        self.next_block = block_offset;
+        self.data.clear();
+        self.pos = 0;
+        if !self.read_block()? && within == 0 {
+            return Ok(());
+        }
+        if within > self.data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("virtual offset {} is past the end of its block", offset),
+            ));
+        }
+        self.pos = within;
+        Ok(())
+    }
+
+    // next_block から1ブロックを展開する。入力が終わっていれば false を返す。
+    fn read_block(&mut self) -> io::Result<bool> {
+        if self.input.fill_buf()?.is_empty() {
+            return Ok(false);
+        }
+        let offset = self.next_block;
+        let mut decoder = MultiMemberDecoder::new(&mut self.input, &self.name).starting_at(offset, self.member).one_member();
+        self.data.clear();
+        let Some(info) = decoder.next_member(&mut self.data)? else {
+            return Ok(false);
+        };
+        let at = Location { file: self.name.clone(), member: self.member, offset };
+        if block_size(&info.header) != Some(info.compressed_len) {
+            return Err(GzTestError::Header { at, reason: "not a BGZF block (missing or wrong BSIZE)" }.into());
+        }
+        if self.data.len() > MAX_BLOCK_SIZE {
+            return Err(GzTestError::Header { at, reason: "BGZF block larger than 64 KiB when decoded" }.into());
+        }
+        self.member += 1;
+        self.block_offset = offset;
+        self.next_block = offset + info.compressed_len;
+        self.pos = 0;
+        Ok(true)
+    }
+}
+
+impl<R: BufRead + Seek> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead + Seek> BufRead for BgzfReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // EOF ブロックのような空のブロックは読み飛ばす。
+        while self.pos == self.data.len() {
+            if !self.read_block()? {
+                break;
+            }
+        }
+        Ok(&self.data[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
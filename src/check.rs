@@ -0,0 +1,38 @@
+use std::error::Error;
+
+use crate::cli::CheckArgs;
+use crate::error::Failed;
+use crate::inspect::read_members;
+
+/// `check --strict-single` で複数メンバーのファイルが見つかった時の終了コード。
+pub const EXIT_MULTI_MEMBER: u8 = 12;
+
+/// `check` コマンド: 各ファイルのメンバー数を数え、複数メンバーのファイルを警告する。
+///
+/// `GzDecoder` のように最初のメンバーしか展開しないデコーダーでは、
+/// 2つ目以降のメンバーのデータが何のエラーもなく失われる。
+pub fn check(args: &CheckArgs) -> Result<(), Box<dyn Error>> {
+    let mut multi_member_files = 0;
+    for filename in &args.inputs {
+        let members = read_members(filename)?;
+        println!("{}: {} member(s)", filename, members.len());
+        if members.len() > 1 {
+            multi_member_files += 1;
+            let total: u64 = members.iter().map(|m| m.uncompressed_len).sum();
+            eprintln!(
+                "gzip_test: warning: '{}' has {} members; a first-member-only decoder such as GzDecoder would stop after {} of {} bytes",
+                filename,
+                members.len(),
+                members[0].uncompressed_len,
+                total
+            );
+        }
+    }
+    if args.strict_single && multi_member_files > 0 {
+        return Err(Box::new(Failed {
+            code: EXIT_MULTI_MEMBER,
+            message: format!("{} multi-member file(s) found with --strict-single", multi_member_files),
+        }));
+    }
+    Ok(())
+}
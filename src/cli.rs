@@ -0,0 +1,809 @@
+use std::fmt;
+
+use crate::regex::{MatchOptions, Regex};
+
+// コマンドラインの使い方。`--help` で標準出力に、引数の誤りでは標準エラー出力に表示する。
+pub const USAGE: &str = "\
+Usage: gzip_test [OPTIONS] FILE...
+       gzip_test COMMAND [OPTIONS] FILE...
+
+Decode gzip FILEs (all members) and write the text to the standard output.
+zlib streams are detected from their header and decoded as well.
+Use '-' as FILE to read from the standard input; pipes are read as they arrive
+(only -j, --recover, concat and extract --virtual keep the whole input in memory).
+Short options can be combined, with a value right after the last one (-bj4 is -b -j 4).
+
+Options:
+  -o, --output PATH   write to PATH instead of the standard output; the data goes
+                      to a temporary file that is renamed to PATH when complete
+  -g, --gunzip        write every FILE.gz to FILE next to it, like gunzip
+                      (.gz, -gz, .z, -z, _z and .Z are removed, .tgz becomes .tar)
+  -b, --binary        write the decoded bytes unchanged, like zcat
+                      (no UTF-8 check, no line ending rewriting)
+      --recover       skip corrupt members and keep decoding from the next valid
+                      member header; skipped byte ranges are reported (implies -b);
+                      a member is written only once its CRC32 and ISIZE match, except
+                      for a truncated last member, whose partial data is written
+  -j, --jobs N        decode members on N threads (0: one per CPU core, default 1);
+                      the output and exit status are the same as with one thread
+      --raw           decode FILEs as raw deflate streams (no gzip or zlib header)
+  -f, --force         write input that is neither gzip nor zlib unchanged, like zcat -f,
+                      and overwrite existing output files (text that only looks like
+                      a zlib header, such as 'x^...', is written unchanged too)
+      --fsync         flush output files to the disk before renaming them
+      --delete        delete the input FILEs once their output is complete
+                      (needs -o or -g, and -o cannot be one of FILEs;
+                      never done when an error occurred)
+  -k, --keep          keep the input FILEs (the default)
+  -N, --name          with -g, name the output after the FNAME stored in the header
+                      (directories are stripped, so it stays next to FILE);
+                      with -g or -o, set its modification time from MTIME
+      --head N        write only the first N lines of each FILE and stop decoding there
+      --lines A..B    write only lines A to B (from 1, both included) of each FILE
+                      and stop decoding after line B
+      --tail N        write only the last N lines of each FILE; only N lines are kept
+                      in memory, and decoding starts near the end when FILE.gzidx exists
+                      and is up to date (a stale one is ignored with a warning)
+  -h, --help          print this help and exit
+  -V, --version       print the version and exit
+
+Commands:
+  list, inspect       print the header fields, offsets and sizes of every member,
+                      and the virtual offsets of the blocks of BGZF files
+      --json          print the result as JSON
+  check               count the members of each file and warn about multi-member
+                      files, which a first-member-only decoder (GzDecoder) truncates
+      --strict-single fail with exit status 12 if any file has more than one member
+  test                check the CRC32 and ISIZE of every member without writing output,
+                      like gzip -t; keeps going after a failure and prints a summary
+  -q, --quiet         print only the files that failed and the summary
+  compress            compress FILEs with gzip, one or more members per file
+  -o, --output PATH   write to PATH instead of the standard output, through a temporary
+                      file renamed to PATH when complete; PATH cannot be one of FILEs
+  -l, --level N       compression level from 0 (none) to 9 (best), default 6
+      --name NAME     store NAME as FNAME (default: the input file name)
+      --no-name       store neither FNAME nor MTIME
+      --mtime SECS    store SECS (seconds since 1970) as MTIME
+                      (default: the modification time of the input file)
+      --comment TEXT  store TEXT as FCOMMENT
+      --member-size N start a new member every N uncompressed bytes
+                      (N may end with K, M or G)
+  -j, --jobs N        compress on N threads (0: one per CPU core, default 1);
+                      with --member-size the members are compressed in parallel,
+                      otherwise one member is written from blocks compressed in
+                      parallel, like pigz
+      --block-size N  uncompressed bytes per block with --jobs, default 128K
+      --bgzf          write BGZF (blocked gzip, as used for BAM and tabix) with
+                      64K blocks and an end-of-file marker block; no FNAME or MTIME
+  concat              join gzip FILEs into one multi-member file without
+                      recompressing; every input is verified first
+  -o, --output PATH   write to PATH instead of the standard output, through a temporary
+                      file renamed to PATH when complete; PATH cannot be one of FILEs
+      --name NAME     rewrite the FNAME of every member to NAME
+      --no-name       remove FNAME from every member
+  split               write every member of FILEs to its own .gz file, named
+                      after its FNAME, or numbered when there is none
+  -d, --directory DIR write the files into DIR instead of the current directory
+  -f, --force         overwrite existing files
+  index               decode FILEs and save a random-access index next to each
+                      one as FILE.gzidx, with a checkpoint every N bytes
+  -s, --span N        bytes of decoded data between checkpoints, default 1M
+                      (N may end with K, M or G)
+  extract             write part of the decoded data of FILEs, starting from
+                      the nearest checkpoint of FILE.gzidx when it exists
+  -o, --output PATH   write to PATH instead of the standard output, through a temporary
+                      file renamed to PATH when complete; PATH cannot be one of FILEs
+      --bytes A..B    decoded bytes from offset A (from 0) up to, not including, B
+      --lines A..B    lines A to B (from 1, both included)
+      --virtual A..B  BGZF virtual offsets A up to, not including, B
+                      (block offset << 16 | offset within the block)
+                      (A or B may be left out to start at the beginning or run to the end)
+  grep PATTERN        print the decoded lines of FILEs that match PATTERN, an extended
+                      regular expression as in grep -E (plus \\d, \\w, \\s and \\b),
+                      prefixed with FILE and the member index (from 0) of the line;
+                      invalid UTF-8 is matched as U+FFFD and printed unchanged
+  -e PATTERN          use PATTERN, even if it starts with '-'
+  -F, --fixed-strings match PATTERN as a plain string
+  -i, --ignore-case   ignore case distinctions
+  -w, --word-regexp   select only matches that are whole words, with no letter, digit
+                      or '_' right before or after them
+  -v, --invert-match  select the lines that do not match
+  -n, --line-number   also print the line number (from 1, counted across members)
+  -c, --count         print only the number of selected lines of each FILE
+  -A, --after-context N   also print N lines after each selected line
+  -B, --before-context N  also print N lines before each selected line
+  -C, --context N     same as -A N -B N; groups of lines are separated by '--'
+  stats               print the compressed and uncompressed sizes, the compression
+                      ratio and the number of members of FILEs, with the line, word
+                      and byte counts of the decoded data (like zcat FILE | wc),
+                      and the same for every member
+      --json          print the result as JSON
+
+Exit status:
+  0   success
+  1   an error occurred while writing the output
+  2   invalid command-line arguments
+  3   an input file cannot be opened
+  4   corrupt gzip header or not in gzip format
+  5   CRC32 mismatch
+  6   ISIZE mismatch
+  7   unexpected end of file (truncated member)
+  8   trailing garbage after the last member
+  9   invalid UTF-8 in the decoded text
+  10  corrupt compressed (deflate) data
+  11  an input file cannot be read
+  12  a multi-member file was found with check --strict-single
+  13  --recover skipped corrupt or truncated data
+  14  the index file is invalid or older than the gzip file
+  15  grep selected no lines
+";
+
+/// コマンドライン引数を解析した結果。
+#[derive(Debug)]
+pub enum Command {
+    Help,
+    Version,
+    Decompress(DecompressArgs),
+    List(ListArgs),
+    Check(CheckArgs),
+    Test(TestArgs),
+    Compress(CompressArgs),
+    Concat(ConcatArgs),
+    Split(SplitArgs),
+    Index(IndexArgs),
+    Extract(ExtractArgs),
+    Grep(GrepArgs),
+    Stats(StatsArgs),
+}
+
+/// 展開して出力する時の引数。
+#[derive(Debug)]
+pub struct DecompressArgs {
+    /// 入力ファイルのパス。`-` は標準入力を表す。
+    pub inputs: Vec<String>,
+    /// 出力先のパス。`None` の時は標準出力に書き出す。
+    pub output: Option<String>,
+    /// 行ごとに処理せず、展開したバイト列をそのまま書き出す。
+    pub binary: bool,
+    /// 壊れたメンバーを飛ばして次のメンバーから展開を続ける。
+    pub recover: bool,
+    /// メンバーを展開するスレッドの数。
+    pub jobs: usize,
+    /// 入力を raw deflate (ヘッダーのない deflate ストリーム) として展開する。
+    pub raw: bool,
+    /// gzip でも zlib でもない入力をそのまま書き出し、既にある出力ファイルを上書きする。
+    pub force: bool,
+    /// 各入力 `FILE.gz` を gunzip と同じく隣の `FILE` に展開する。
+    pub gunzip: bool,
+    /// 出力ファイルの名前を付け替える前に fsync する。
+    pub fsync: bool,
+    /// 出力ファイルを確定した後に入力ファイルを消す。
+    pub delete: bool,
+    /// ヘッダーの FNAME を `--gunzip` の出力ファイルの名前に、MTIME を出力ファイルの更新時刻に使う。
+    pub restore_name: bool,
+    /// 各入力の、1から数えた行番号の範囲だけを書き出す。`--head N` は `1..N` と同じ。
+    pub lines: Option<Range>,
+    /// 各入力の最後のこの数の行だけを書き出す。
+    pub tail: Option<u64>,
+}
+
+/// `list` (`inspect`) の引数。
+#[derive(Debug)]
+pub struct ListArgs {
+    pub inputs: Vec<String>,
+    /// JSON で出力する。
+    pub json: bool,
+}
+
+/// `check` の引数。
+#[derive(Debug)]
+pub struct CheckArgs {
+    pub inputs: Vec<String>,
+    /// 複数のメンバーを持つファイルがあれば失敗とする。
+    pub strict_single: bool,
+}
+
+/// `test` の引数。
+#[derive(Debug)]
+pub struct TestArgs {
+    pub inputs: Vec<String>,
+    /// 問題のないファイルの結果を表示しない。
+    pub quiet: bool,
+}
+
+/// `compress` の引数。
+#[derive(Debug)]
+pub struct CompressArgs {
+    pub inputs: Vec<String>,
+    pub output: Option<String>,
+    /// 圧縮レベル (0-9)。
+    pub level: u32,
+    /// FNAME に入れる名前。`None` の時は入力ファイルの名前を使う。
+    pub name: Option<String>,
+    /// FNAME と MTIME を入れない。
+    pub no_name: bool,
+    /// MTIME に入れる値。`None` の時は入力ファイルの更新時刻を使う。
+    pub mtime: Option<u32>,
+    /// FCOMMENT に入れるコメント。
+    pub comment: Option<String>,
+    /// 1メンバーあたりの展開後のバイト数。`None` の時は1ファイルを1メンバーにする。
+    pub member_size: Option<u64>,
+    /// 圧縮するスレッドの数。
+    pub jobs: usize,
+    /// 複数のスレッドで1メンバーを圧縮する時の、1ブロックあたりの展開後のバイト数。
+    pub block_size: u64,
+    /// BGZF 形式 (BC サブフィールド付きの 64 KiB 以下のブロックと EOF ブロック) で書き出す。
+    pub bgzf: bool,
+}
+
+/// `concat` の引数。
+#[derive(Debug)]
+pub struct ConcatArgs {
+    pub inputs: Vec<String>,
+    pub output: Option<String>,
+    /// すべてのメンバーの FNAME をこの名前に書き換える。
+    pub name: Option<String>,
+    /// すべてのメンバーから FNAME を取り除く。
+    pub no_name: bool,
+}
+
+/// `split` の引数。
+#[derive(Debug)]
+pub struct SplitArgs {
+    pub inputs: Vec<String>,
+    /// 書き出すディレクトリ。`None` の時はカレントディレクトリ。
+    pub directory: Option<String>,
+    /// 既にあるファイルを上書きする。
+    pub force: bool,
+}
+
+/// `index` の引数。
+#[derive(Debug)]
+pub struct IndexArgs {
+    pub inputs: Vec<String>,
+    /// チェックポイントの間隔 (展開後のバイト数)。
+    pub span: u64,
+}
+
+/// `A..B` 形式の範囲。`end` が `None` の時は最後まで。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+/// `extract` の引数。
+#[derive(Debug)]
+pub struct ExtractArgs {
+    pub inputs: Vec<String>,
+    pub output: Option<String>,
+    /// 0から数えたバイト位置の範囲。終わりの位置は含まない。
+    pub bytes: Option<Range>,
+    /// 1から数えた行番号の範囲。終わりの行も含む。
+    pub lines: Option<Range>,
+    /// BGZF の仮想ファイルオフセットの範囲。終わりの位置は含まない。
+    pub virtual_offsets: Option<Range>,
+}
+
+/// `grep` の引数。
+#[derive(Debug)]
+pub struct GrepArgs {
+    pub inputs: Vec<String>,
+    /// 正規表現、または `fixed` の時は固定の文字列。
+    pub pattern: String,
+    pub fixed: bool,
+    pub ignore_case: bool,
+    /// 前後に単語の文字が続かない一致だけを探す。
+    pub whole_words: bool,
+    /// 一致しない行を選ぶ。
+    pub invert: bool,
+    /// 行番号も表示する。
+    pub line_number: bool,
+    /// 選んだ行の数だけを表示する。
+    pub count: bool,
+    /// 選んだ行の前に表示する行の数。
+    pub before: usize,
+    /// 選んだ行の後に表示する行の数。
+    pub after: usize,
+}
+
+/// `stats` の引数。
+#[derive(Debug)]
+pub struct StatsArgs {
+    pub inputs: Vec<String>,
+    /// JSON で出力する。
+    pub json: bool,
+}
+
+/// コマンドライン引数の誤り。
+#[derive(Debug)]
+pub struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+// 1つのオプション。`--name=value` 形式の時は値も持つ。
+struct Opt {
+    name: String,
+    inline: Option<String>,
+}
+
+// 引数を先頭から読み、オプションとファイル名に分ける。
+struct ArgParser<I> {
+    args: I,
+    inputs: Vec<String>,
+    // `-in` や `-C1` のように1つの引数にまとめた短いオプションの、まだ読んでいない残り。
+    bundle: String,
+}
+
+impl<I: Iterator<Item = String>> ArgParser<I> {
+    fn new(args: I) -> Self {
+        ArgParser { args, inputs: Vec::new(), bundle: String::new() }
+    }
+
+    // 短いオプションの残りの先頭の1文字を、1つのオプションとして取り出す。
+    fn next_short(&mut self) -> Opt {
+        let c = self.bundle.remove(0);
+        Opt { name: format!("-{}", c), inline: None }
+    }
+
+    // 次のオプションを返す。途中のファイル名は inputs に集める。
+    // getopt と同じく、`-in` は `-i -n` と同じで、値を取るオプションは `-C1` のように値を続けて書ける。
+    fn next_option(&mut self) -> Option<Opt> {
+        if !self.bundle.is_empty() {
+            return Some(self.next_short());
+        }
+        while let Some(arg) = self.args.next() {
+            // `--` 以降はすべてファイル名として扱う。
+            if arg == "--" {
+                self.inputs.extend(self.args.by_ref());
+                return None;
+            }
+            if !arg.starts_with('-') || arg == "-" {
+                self.inputs.push(arg);
+                continue;
+            }
+            if !arg.starts_with("--") {
+                self.bundle = arg[1..].to_string();
+                return Some(self.next_short());
+            }
+            return Some(match arg.split_once('=') {
+                Some((name, value)) => Opt { name: name.to_string(), inline: Some(value.to_string()) },
+                None => Opt { name: arg, inline: None },
+            });
+        }
+        None
+    }
+
+    // オプションの値を取り出す。`--name=value` の値や、短いオプションに続けて書いた値があればそれを使い、
+    // なければ次の引数を使う。
+    fn value(&mut self, opt: Opt) -> Result<String, UsageError> {
+        if let Some(value) = opt.inline {
+            return Ok(value);
+        }
+        if !self.bundle.is_empty() {
+            return Ok(std::mem::take(&mut self.bundle));
+        }
+        self.args.next().ok_or_else(|| UsageError(format!("option '{}' requires a value", opt.name)))
+    }
+
+    // どのコマンドにも共通するオプションを処理する。
+    fn common(&self, opt: Opt) -> Result<Command, UsageError> {
+        match opt.name.as_str() {
+            "-h" | "--help" => Ok(Command::Help),
+            "-V" | "--version" => Ok(Command::Version),
+            _ => Err(UsageError(format!("unknown option '{}'", opt.name))),
+        }
+    }
+
+    // 集めたファイル名を返す。1つもなければ誤りとする。
+    fn finish(self) -> Result<Vec<String>, UsageError> {
+        if self.inputs.is_empty() {
+            return Err(UsageError("no input file given".to_string()));
+        }
+        Ok(self.inputs)
+    }
+}
+
+// 数値のオプションを解析する。
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, UsageError> {
+    value
+        .parse()
+        .map_err(|_| UsageError(format!("invalid value '{}' for option '{}'", value, name)))
+}
+
+// バイト数を解析する。末尾の K, M, G はそれぞれ 2^10, 2^20, 2^30 倍を表す。
+fn parse_size(name: &str, value: &str) -> Result<u64, UsageError> {
+    let (digits, unit) = match value.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&value[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&value[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    let size = parse_number::<u64>(name, digits)?
+        .checked_mul(unit)
+        .filter(|&size| size > 0)
+        .ok_or_else(|| UsageError(format!("invalid value '{}' for option '{}'", value, name)))?;
+    Ok(size)
+}
+
+// `A..B` 形式の範囲を解析する。A を省くと first から、B を省くと最後までになる。
+fn parse_range(name: &str, value: &str, first: u64) -> Result<Range, UsageError> {
+    let invalid = || UsageError(format!("invalid range '{}' for option '{}' (expected A..B)", value, name));
+    let (start, end) = value.split_once("..").ok_or_else(invalid)?;
+    let start = if start.is_empty() { first } else { start.parse().map_err(|_| invalid())? };
+    let end = if end.is_empty() { None } else { Some(end.parse().map_err(|_| invalid())?) };
+    if start < first || end.is_some_and(|end| end < start) {
+        return Err(invalid());
+    }
+    Ok(Range { start, end })
+}
+
+// スレッドの数を解析する。0 は CPU のコア数を表す。
+fn parse_jobs(name: &str, value: &str) -> Result<usize, UsageError> {
+    match parse_number(name, value)? {
+        0 => Ok(std::thread::available_parallelism().map_or(1, |n| n.get())),
+        jobs => Ok(jobs),
+    }
+}
+
+fn parse_decompress<I: Iterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+    let mut parser = ArgParser::new(args);
+    let mut output = None;
+    let mut binary = false;
+    let mut recover = false;
+    let mut jobs = 1;
+    let mut raw = false;
+    let mut force = false;
+    let mut gunzip = false;
+    let mut fsync = false;
+    let mut delete = false;
+    let mut keep = false;
+    let mut restore_name = false;
+    let mut lines = None;
+    let mut tail = None;
+    let mut selections = 0;
+    while let Some(opt) = parser.next_option() {
+        let name = opt.name.clone();
+        match name.as_str() {
+            "-o" | "--output" => output = Some(parser.value(opt)?),
+            "-b" | "--binary" => binary = true,
+            "--recover" => recover = true,
+            "-j" | "--jobs" => jobs = parse_jobs(&name, &parser.value(opt)?)?,
+            "--raw" => raw = true,
+            "-f" | "--force" => force = true,
+            "-g" | "--gunzip" => gunzip = true,
+            "--fsync" => fsync = true,
+            "--delete" => delete = true,
+            "-k" | "--keep" => keep = true,
+            "-N" | "--name" => restore_name = true,
+            "--head" => {
+                lines = Some(Range { start: 1, end: Some(parse_number(&name, &parser.value(opt)?)?) });
+                selections += 1;
+            }
+            "--lines" => {
+                lines = Some(parse_range(&name, &parser.value(opt)?, 1)?);
+                selections += 1;
+            }
+            "--tail" => {
+                tail = Some(parse_number(&name, &parser.value(opt)?)?);
+                selections += 1;
+            }
+            _ => return parser.common(opt),
+        }
+    }
+    if recover && jobs > 1 {
+        return Err(UsageError("'--recover' cannot be used with '--jobs'".to_string()));
+    }
+    if recover && (raw || force) {
+        return Err(UsageError("'--recover' reads gzip only and cannot be used with '--raw' or '--force'".to_string()));
+    }
+    if selections > 1 {
+        return Err(UsageError("only one of '--head', '--lines' and '--tail' can be used".to_string()));
+    }
+    if selections > 0 && recover {
+        return Err(UsageError("'--head', '--lines' and '--tail' cannot be used with '--recover'".to_string()));
+    }
+    // 一部の行しか書き出さないので、入力を消すとデータを失う。
+    if selections > 0 && delete {
+        return Err(UsageError("'--head', '--lines' and '--tail' cannot be used with '--delete'".to_string()));
+    }
+    if gunzip && output.is_some() {
+        return Err(UsageError("'--gunzip' and '--output' cannot be used together".to_string()));
+    }
+    if delete && keep {
+        return Err(UsageError("'--delete' and '--keep' cannot be used together".to_string()));
+    }
+    if (fsync || delete || restore_name) && !gunzip && output.is_none() {
+        return Err(UsageError("'--fsync', '--delete' and '--name' need '--output' or '--gunzip'".to_string()));
+    }
+    let inputs = parser.finish()?;
+    if (gunzip || delete || restore_name) && inputs.iter().any(|input| input == "-") {
+        return Err(UsageError("'--gunzip', '--delete' and '--name' cannot be used with the standard input".to_string()));
+    }
+    Ok(Command::Decompress(DecompressArgs {
+        inputs,
+        output,
+        binary,
+        recover,
+        jobs,
+        raw,
+        force,
+        gunzip,
+        fsync,
+        delete,
+        restore_name,
+        lines,
+        tail,
+    }))
+}
+
+fn parse_list<I: Iterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+    let mut parser = ArgParser::new(args);
+    let mut json = false;
+    while let Some(opt) = parser.next_option() {
+        match opt.name.as_str() {
+            "--json" => json = true,
+            _ => return parser.common(opt),
+        }
+    }
+    let inputs = parser.finish()?;
+    Ok(Command::List(ListArgs { inputs, json }))
+}
+
+fn parse_check<I: Iterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+    let mut parser = ArgParser::new(args);
+    let mut strict_single = false;
+    while let Some(opt) = parser.next_option() {
+        match opt.name.as_str() {
+            "--strict-single" => strict_single = true,
+            _ => return parser.common(opt),
+        }
+    }
+    let inputs = parser.finish()?;
+    Ok(Command::Check(CheckArgs { inputs, strict_single }))
+}
+
+fn parse_test<I: Iterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+    let mut parser = ArgParser::new(args);
+    let mut quiet = false;
+    while let Some(opt) = parser.next_option() {
+        match opt.name.as_str() {
+            "-q" | "--quiet" => quiet = true,
+            _ => return parser.common(opt),
+        }
+    }
+    let inputs = parser.finish()?;
+    Ok(Command::Test(TestArgs { inputs, quiet }))
+}
+
+fn parse_compress<I: Iterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+    let mut parser = ArgParser::new(args);
+    let mut compress = CompressArgs {
+        inputs: Vec::new(),
+        output: None,
+        level: 6,
+        name: None,
+        no_name: false,
+        mtime: None,
+        comment: None,
+        member_size: None,
+        jobs: 1,
+        block_size: 128 << 10,
+        bgzf: false,
+    };
+    while let Some(opt) = parser.next_option() {
+        let name = opt.name.clone();
+        match name.as_str() {
+            "-o" | "--output" => compress.output = Some(parser.value(opt)?),
+            "-l" | "--level" => {
+                compress.level = parse_number(&name, &parser.value(opt)?)?;
+                if compress.level > 9 {
+                    return Err(UsageError(format!("compression level must be 0-9, not {}", compress.level)));
+                }
+            }
+            "--name" => compress.name = Some(parser.value(opt)?),
+            "--no-name" => compress.no_name = true,
+            "--mtime" => compress.mtime = Some(parse_number(&name, &parser.value(opt)?)?),
+            "--comment" => compress.comment = Some(parser.value(opt)?),
+            "--member-size" => compress.member_size = Some(parse_size(&name, &parser.value(opt)?)?),
+            "-j" | "--jobs" => compress.jobs = parse_jobs(&name, &parser.value(opt)?)?,
+            "--block-size" => compress.block_size = parse_size(&name, &parser.value(opt)?)?,
+            "--bgzf" => compress.bgzf = true,
+            _ => return parser.common(opt),
+        }
+    }
+    if compress.bgzf && compress.member_size.is_some() {
+        return Err(UsageError("'--bgzf' and '--member-size' cannot be used together".to_string()));
+    }
+    compress.inputs = parser.finish()?;
+    Ok(Command::Compress(compress))
+}
+
+fn parse_concat<I: Iterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+    let mut parser = ArgParser::new(args);
+    let mut output = None;
+    let mut name = None;
+    let mut no_name = false;
+    while let Some(opt) = parser.next_option() {
+        match opt.name.as_str() {
+            "-o" | "--output" => output = Some(parser.value(opt)?),
+            "--name" => name = Some(parser.value(opt)?),
+            "--no-name" => no_name = true,
+            _ => return parser.common(opt),
+        }
+    }
+    if name.is_some() && no_name {
+        return Err(UsageError("'--name' and '--no-name' cannot be used together".to_string()));
+    }
+    let inputs = parser.finish()?;
+    Ok(Command::Concat(ConcatArgs { inputs, output, name, no_name }))
+}
+
+fn parse_split<I: Iterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+    let mut parser = ArgParser::new(args);
+    let mut directory = None;
+    let mut force = false;
+    while let Some(opt) = parser.next_option() {
+        match opt.name.as_str() {
+            "-d" | "--directory" => directory = Some(parser.value(opt)?),
+            "-f" | "--force" => force = true,
+            _ => return parser.common(opt),
+        }
+    }
+    let inputs = parser.finish()?;
+    Ok(Command::Split(SplitArgs { inputs, directory, force }))
+}
+
+fn parse_index<I: Iterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+    let mut parser = ArgParser::new(args);
+    let mut span = 1 << 20;
+    while let Some(opt) = parser.next_option() {
+        let name = opt.name.clone();
+        match name.as_str() {
+            "-s" | "--span" => span = parse_size(&name, &parser.value(opt)?)?,
+            _ => return parser.common(opt),
+        }
+    }
+    let inputs = parser.finish()?;
+    // 索引は gzip ファイルの隣に置き、索引から読む時にはシークするので、標準入力には作れない。
+    if inputs.iter().any(|input| input == "-") {
+        return Err(UsageError("'index' cannot read from the standard input".to_string()));
+    }
+    Ok(Command::Index(IndexArgs { inputs, span }))
+}
+
+fn parse_extract<I: Iterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+    let mut parser = ArgParser::new(args);
+    let mut output = None;
+    let mut bytes = None;
+    let mut lines = None;
+    let mut virtual_offsets = None;
+    while let Some(opt) = parser.next_option() {
+        let name = opt.name.clone();
+        match name.as_str() {
+            "-o" | "--output" => output = Some(parser.value(opt)?),
+            "--bytes" => bytes = Some(parse_range(&name, &parser.value(opt)?, 0)?),
+            "--lines" => lines = Some(parse_range(&name, &parser.value(opt)?, 1)?),
+            "--virtual" => virtual_offsets = Some(parse_range(&name, &parser.value(opt)?, 0)?),
+            _ => return parser.common(opt),
+        }
+    }
+    if [bytes.is_some(), lines.is_some(), virtual_offsets.is_some()].iter().filter(|&&given| given).count() > 1 {
+        return Err(UsageError("only one of '--bytes', '--lines' and '--virtual' can be used".to_string()));
+    }
+    let inputs = parser.finish()?;
+    Ok(Command::Extract(ExtractArgs { inputs, output, bytes, lines, virtual_offsets }))
+}
+
+fn parse_grep<I: Iterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+    let mut parser = ArgParser::new(args);
+    let mut pattern = None;
+    let mut fixed = false;
+    let mut ignore_case = false;
+    let mut whole_words = false;
+    let mut invert = false;
+    let mut line_number = false;
+    let mut count = false;
+    let mut before = 0;
+    let mut after = 0;
+    while let Some(opt) = parser.next_option() {
+        let name = opt.name.clone();
+        match name.as_str() {
+            "-e" | "--regexp" => pattern = Some(parser.value(opt)?),
+            "-F" | "--fixed-strings" => fixed = true,
+            "-i" | "--ignore-case" => ignore_case = true,
+            "-w" | "--word-regexp" => whole_words = true,
+            "-v" | "--invert-match" => invert = true,
+            "-n" | "--line-number" => line_number = true,
+            "-c" | "--count" => count = true,
+            "-A" | "--after-context" => after = parse_number(&name, &parser.value(opt)?)?,
+            "-B" | "--before-context" => before = parse_number(&name, &parser.value(opt)?)?,
+            "-C" | "--context" => {
+                after = parse_number(&name, &parser.value(opt)?)?;
+                before = after;
+            }
+            _ => return parser.common(opt),
+        }
+    }
+    let mut inputs = parser.finish()?;
+    // -e がなければ、最初の引数がパターン。
+    let pattern = match pattern {
+        Some(pattern) => pattern,
+        None => inputs.remove(0),
+    };
+    if inputs.is_empty() {
+        return Err(UsageError("no input file given".to_string()));
+    }
+    let options = MatchOptions { ignore_case, whole_words };
+    let regex = if fixed { Regex::literal(&pattern, options) } else { Regex::new(&pattern, options) };
+    regex.map_err(|err| UsageError(format!("invalid pattern '{}': {}", pattern, err)))?;
+    Ok(Command::Grep(GrepArgs { inputs, pattern, fixed, ignore_case, whole_words, invert, line_number, count, before, after }))
+}
+
+fn parse_stats<I: Iterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+    let mut parser = ArgParser::new(args);
+    let mut json = false;
+    while let Some(opt) = parser.next_option() {
+        match opt.name.as_str() {
+            "--json" => json = true,
+            _ => return parser.common(opt),
+        }
+    }
+    let inputs = parser.finish()?;
+    Ok(Command::Stats(StatsArgs { inputs, json }))
+}
+
+/// プログラム名を除いたコマンドライン引数を解析する。
+/// 最初の引数がコマンド名でなければ、展開して出力する。
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+    let mut args = args.into_iter().peekable();
+    match args.peek().map(String::as_str) {
+        Some("list") | Some("inspect") => {
+            args.next();
+            parse_list(args)
+        }
+        Some("check") => {
+            args.next();
+            parse_check(args)
+        }
+        Some("test") => {
+            args.next();
+            parse_test(args)
+        }
+        Some("compress") => {
+            args.next();
+            parse_compress(args)
+        }
+        Some("concat") => {
+            args.next();
+            parse_concat(args)
+        }
+        Some("split") => {
+            args.next();
+            parse_split(args)
+        }
+        Some("index") => {
+            args.next();
+            parse_index(args)
+        }
+        Some("extract") => {
+            args.next();
+            parse_extract(args)
+        }
+        Some("grep") => {
+            args.next();
+            parse_grep(args)
+        }
+        Some("stats") => {
+            args.next();
+            parse_stats(args)
+        }
+        _ => parse_decompress(args),
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+use flate2::{Compress, Compression, Crc, FlushCompress, GzBuilder, Status};
+
+use crate::bgzf::{self, EOF_BLOCK};
+use crate::cli::CompressArgs;
+use crate::header::{Header, TRAILER_LEN};
+use crate::{create_output, open_input};
+
+// gzip と同じく Unix では OS に 3 を入れる。それ以外は flate2 の既定値 (255, 不明) のまま。
+const OS_UNIX: u8 = 3;
+const OS_UNKNOWN: u8 = 255;
+
+/// 各メンバーのヘッダーに入れる値。
+#[derive(Debug, Clone, Default)]
+pub struct HeaderFields {
+    pub filename: Option<Vec<u8>>,
+    pub mtime: u32,
+    pub comment: Option<Vec<u8>>,
+}
+
+impl HeaderFields {
+    /// この値を持つヘッダーを書き出す `GzBuilder` を作る。
+    pub fn builder(&self) -> GzBuilder {
+        let mut builder = GzBuilder::new().mtime(self.mtime);
+        if cfg!(unix) {
+            builder = builder.operating_system(OS_UNIX);
+        }
+        if let Some(filename) = &self.filename {
+            builder = builder.filename(filename.clone());
+        }
+        if let Some(comment) = &self.comment {
+            builder = builder.comment(comment.clone());
+        }
+        builder
+    }
+
+    /// `builder` が書き出すのと同じヘッダー。XFL は flate2 と同じく圧縮レベルから決める。
+    pub fn header(&self, level: Compression) -> Header {
+        let xfl = if level.level() >= Compression::best().level() {
+            2
+        } else if level.level() <= Compression::fast().level() {
+            4
+        } else {
+            0
+        };
+        Header {
+            mtime: self.mtime,
+            xfl,
+            os: if cfg!(unix) { OS_UNIX } else { OS_UNKNOWN },
+            filename: self.filename.clone(),
+            comment: self.comment.clone(),
+            ..Header::default()
+        }
+    }
+}
+
+// 入力ファイルとオプションからヘッダーの値を決める。
+fn header_fields(args: &CompressArgs, filename: &str) -> HeaderFields {
+    let from_file = filename != "-" && !args.no_name;
+    let name = match &args.name {
+        Some(name) => Some(name.clone()),
+        None if from_file => Path::new(filename).file_name().map(|name| name.to_string_lossy().into_owned()),
+        None => None,
+    };
+    let mtime = args.mtime.unwrap_or_else(|| {
+        if !from_file {
+            return 0;
+        }
+        fs::metadata(filename)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |elapsed| u32::try_from(elapsed.as_secs()).unwrap_or(0))
+    });
+    HeaderFields {
+        filename: if args.no_name { None } else { name.map(String::into_bytes) },
+        mtime,
+        comment: args.comment.clone().map(String::into_bytes),
+    }
+}
+
+/// 入力を圧縮して書き出し、書き出したメンバー数を返す。
+///
+/// `member_size` を指定すると、展開後のサイズでそのバイト数ごとに新しいメンバーを始める。
+/// 空の入力からも空のメンバーを1つ書き出す。
+pub fn compress_stream<R: BufRead, W: Write>(
+    input: &mut R,
+    out: &mut W,
+    fields: &HeaderFields,
+    level: Compression,
+    member_size: Option<u64>,
+) -> io::Result<u64> {
+    let limit = member_size.unwrap_or(u64::MAX);
+    let mut members = 0;
+    loop {
+        let mut encoder = fields.builder().write(&mut *out, level);
+        io::copy(&mut input.by_ref().take(limit), &mut encoder)?;
+        encoder.finish()?;
+        members += 1;
+        if input.fill_buf()?.is_empty() {
+            return Ok(members);
+        }
+    }
+}
+
+/// 入力を `block_size` バイトずつ読む。空の入力からは空のブロックを1つ返す。
+/// 1つ先のブロックまで読んでおくので、各ブロックが最後かどうかも分かる。
+pub struct Blocks<R> {
+    input: R,
+    block_size: u64,
+    next: Option<Vec<u8>>,
+}
+
+impl<R: Read> Blocks<R> {
+    pub fn new(mut input: R, block_size: u64) -> io::Result<Self> {
+        let first = read_block(&mut input, block_size)?;
+        Ok(Blocks { input, block_size, next: Some(first) })
+    }
+
+    // 次のブロックと、それが最後のブロックかどうかを返す。
+    fn next_block(&mut self) -> io::Result<Option<(Vec<u8>, bool)>> {
+        let Some(block) = self.next.take() else {
+            return Ok(None);
+        };
+        let following = read_block(&mut self.input, self.block_size)?;
+        let last = following.is_empty();
+        if !last {
+            self.next = Some(following);
+        }
+        Ok(Some((block, last)))
+    }
+}
+
+fn read_block<R: Read>(input: &mut R, block_size: u64) -> io::Result<Vec<u8>> {
+    let mut block = Vec::new();
+    input.take(block_size).read_to_end(&mut block)?;
+    Ok(block)
+}
+
+/// ブロックを `threads` 個のスレッドで `work` に渡し、結果を入力の順番どおりに `emit` に渡す。
+/// 読み込んで結果を待っているブロックは `threads` の2倍までにする。
+pub fn in_parallel<R, T, F, E>(mut blocks: Blocks<R>, threads: usize, work: F, mut emit: E) -> io::Result<()>
+where
+    R: Read,
+    T: Send,
+    F: Fn(Vec<u8>, bool) -> io::Result<T> + Sync,
+    E: FnMut(T) -> io::Result<()>,
+{
+    let (jobs, job_receiver) = mpsc::channel::<(u64, Vec<u8>, bool)>();
+    let job_receiver = Mutex::new(job_receiver);
+    thread::scope(|scope| {
+        // 途中で戻った時も送り口が閉じて各スレッドが止まるよう、送り口はこのクロージャーに持たせる。
+        let jobs = jobs;
+        let (result_sender, results) = mpsc::channel();
+        for _ in 0..threads {
+            let (job_receiver, result_sender, work) = (&job_receiver, result_sender.clone(), &work);
+            scope.spawn(move || loop {
+                let job = job_receiver.lock().ok().and_then(|jobs| jobs.recv().ok());
+                let Some((seq, block, last)) = job else {
+                    return;
+                };
+                if result_sender.send((seq, work(block, last))).is_err() {
+                    return;
+                }
+            });
+        }
+        drop(result_sender);
+        let mut done = HashMap::new();
+        let (mut sent, mut emitted) = (0, 0);
+        let mut reading = true;
+        while reading || emitted < sent {
+            while reading && sent - emitted < 2 * threads as u64 {
+                match blocks.next_block()? {
+                    Some((block, last)) => {
+                        jobs.send((sent, block, last)).map_err(|_| io::Error::other("a compression thread stopped"))?;
+                        sent += 1;
+                    }
+                    None => reading = false,
+                }
+            }
+            while let Some(result) = done.remove(&emitted) {
+                emit(result?)?;
+                emitted += 1;
+            }
+            if emitted < sent {
+                let (seq, result) = results.recv().map_err(|_| io::Error::other("a compression thread stopped"))?;
+                done.insert(seq, result);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// ブロックを raw deflate で圧縮する。最後のブロック以外は sync flush でバイト境界に揃えて終えるので、
+/// 圧縮したブロックをつなげると1つの deflate ストリームになる。
+pub fn deflate_block(data: &[u8], level: Compression, last: bool) -> io::Result<Vec<u8>> {
+    let mut compress = Compress::new(level, false);
+    let flush = if last { FlushCompress::Finish } else { FlushCompress::Sync };
+    let mut out = Vec::with_capacity(data.len() / 2 + 64);
+    loop {
+        if out.capacity() - out.len() < 64 {
+            out.reserve(out.capacity());
+        }
+        let consumed = compress.total_in() as usize;
+        let status = compress.compress_vec(&data[consumed..], &mut out, flush).map_err(io::Error::other)?;
+        let finished = match flush {
+            FlushCompress::Finish => status == Status::StreamEnd,
+            // 出力に空きが残っていれば、入力も sync flush の印もすべて書き出してある。
+            _ => compress.total_in() as usize == data.len() && out.len() < out.capacity(),
+        };
+        if finished {
+            return Ok(out);
+        }
+    }
+}
+
+/// 入力を `block_size` バイトのブロックに分けて `threads` 個のスレッドで圧縮し、1つのメンバーとして書き出す。
+///
+/// pigz と同じく各ブロックを独立した raw deflate として圧縮し、sync flush でつなぐ。
+/// CRC32 はブロックごとに計算して `Crc::combine` でまとめる。
+/// ブロックをまたぐ後方参照がないぶん、1スレッドで圧縮するよりわずかに大きくなる。
+pub fn compress_blocks<R: Read, W: Write>(
+    input: R,
+    out: &mut W,
+    fields: &HeaderFields,
+    level: Compression,
+    block_size: u64,
+    threads: usize,
+) -> io::Result<()> {
+    out.write_all(&fields.header(level).to_bytes()?)?;
+    let mut crc = Crc::new();
+    let work = |block: Vec<u8>, last| {
+        let mut block_crc = Crc::new();
+        block_crc.update(&block);
+        Ok((deflate_block(&block, level, last)?, block_crc))
+    };
+    in_parallel(Blocks::new(input, block_size)?, threads, work, |(compressed, block_crc)| {
+        crc.combine(&block_crc);
+        out.write_all(&compressed)
+    })?;
+    let mut trailer = Vec::with_capacity(TRAILER_LEN);
+    trailer.extend_from_slice(&crc.sum().to_le_bytes());
+    trailer.extend_from_slice(&crc.amount().to_le_bytes());
+    out.write_all(&trailer)
+}
+
+/// `member_size` バイトごとのメンバーを `threads` 個のスレッドで圧縮して書き出し、書き出したメンバー数を返す。
+/// 出力は [`compress_stream`] と同じになる。
+pub fn compress_members<R: Read, W: Write>(
+    input: R,
+    out: &mut W,
+    fields: &HeaderFields,
+    level: Compression,
+    member_size: u64,
+    threads: usize,
+) -> io::Result<u64> {
+    let mut members = 0;
+    let work = |block: Vec<u8>, _| {
+        let mut encoder = fields.builder().write(Vec::new(), level);
+        encoder.write_all(&block)?;
+        encoder.finish()
+    };
+    in_parallel(Blocks::new(input, member_size)?, threads, work, |member| {
+        members += 1;
+        out.write_all(&member)
+    })?;
+    Ok(members)
+}
+
+/// `compress` コマンド: 各入力ファイルを gzip で圧縮して1つの出力に書き出す。
+/// 入力ファイルごとに新しいメンバーを始めるので、複数のファイルを渡すと複数メンバーになる。
+///
+/// `--jobs` で2つ以上のスレッドを使う時、`--member-size` があればメンバーごとに、
+/// なければ `--block-size` のブロックごとに並列に圧縮する。
+/// `--bgzf` では全入力を BGZF のブロックに続けて書き、最後に EOF ブロックを1つだけ書く。
+pub fn compress(args: &CompressArgs) -> Result<(), Box<dyn Error>> {
+    let mut writer = create_output(args.output.as_deref(), &args.inputs)?;
+    let level = Compression::new(args.level);
+    if args.bgzf {
+        for filename in &args.inputs {
+            bgzf::compress_bgzf(open_input(filename)?, &mut writer, level, args.jobs.max(1))?;
+        }
+        writer.write_all(&EOF_BLOCK)?;
+        return writer.finish();
+    }
+    for filename in &args.inputs {
+        let mut input = BufReader::new(open_input(filename)?);
+        let fields = header_fields(args, filename);
+        match args.member_size {
+            _ if args.jobs <= 1 => {
+                compress_stream(&mut input, &mut writer, &fields, level, args.member_size)?;
+            }
+            Some(member_size) => {
+                compress_members(input, &mut writer, &fields, level, member_size, args.jobs)?;
+            }
+            None => compress_blocks(input, &mut writer, &fields, level, args.block_size, args.jobs)?,
+        }
+    }
+    writer.finish()
+}
@@ -0,0 +1,56 @@
+use std::error::Error;
+use std::io::{self, BufReader, Read, SeekFrom, Write};
+
+use crate::cli::ConcatArgs;
+use crate::decoder::MemberInfo;
+use crate::inspect::read_members_from;
+use crate::{create_output, open_seekable, ReadSeek};
+
+/// 入力の `start` から `len` バイトをそのまま書き写す。
+pub fn copy_range<W: Write>(input: &mut dyn ReadSeek, start: u64, len: u64, out: &mut W) -> io::Result<()> {
+    input.seek(SeekFrom::Start(start))?;
+    let copied = io::copy(&mut input.take(len), out)?;
+    if copied != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+// メンバーの圧縮データとトレーラーをそのまま書き写す。
+// ヘッダーは FNAME を書き換える時だけ作り直し、それ以外は元のバイト列を使う。
+fn copy_member<W: Write>(
+    input: &mut dyn ReadSeek,
+    member: &MemberInfo,
+    args: &ConcatArgs,
+    out: &mut W,
+) -> io::Result<()> {
+    let mut start = member.offset;
+    if args.no_name || args.name.is_some() {
+        let mut header = member.header.clone();
+        header.filename = if args.no_name { None } else { args.name.clone().map(String::into_bytes) };
+        out.write_all(&header.to_bytes()?)?;
+        start += member.header.len;
+    }
+    copy_range(input, start, member.offset + member.compressed_len - start, out)
+}
+
+/// `concat` コマンド: gzipファイルのメンバーを再圧縮せずにつなげ、1つの複数メンバーのファイルにする。
+///
+/// 書き出す前にすべての入力を展開してヘッダーとトレーラーを確かめるので、
+/// gzip ではない入力や壊れた入力が1つでもあれば何も書き出さない。
+/// 入力はメモリーにマップしていることがあるので、出力先が入力と同じファイルなら読む前に断る。
+pub fn concat(args: &ConcatArgs) -> Result<(), Box<dyn Error>> {
+    let mut writer = create_output(args.output.as_deref(), &args.inputs)?;
+    let mut sources = Vec::new();
+    for filename in &args.inputs {
+        let mut input = open_seekable(filename)?;
+        let members = read_members_from(BufReader::new(&mut input), filename)?;
+        sources.push((input, members));
+    }
+    for (input, members) in &mut sources {
+        for member in members.iter() {
+            copy_member(input.as_mut(), member, args, &mut writer)?;
+        }
+    }
+    writer.finish()
+}
@@ -0,0 +1,521 @@
+use std::io::{self, BufRead, Read, Write};
+
+use flate2::{Crc, Decompress, FlushDecompress, Status};
+
+use crate::error::{GzTestError, Location};
+use crate::header::{self, Header, HeaderError, Trailer, ID1};
+
+/// 読み進めたバイト数を数える `BufRead`。
+/// エラーの位置やメンバーの境界を圧縮された入力上のバイト位置で表すために使う。
+pub struct CountingReader<R> {
+    inner: R,
+    position: u64,
+}
+
+impl<R> CountingReader<R> {
+    pub fn new(inner: R) -> Self {
+        CountingReader { inner, position: 0 }
+    }
+
+    /// 先頭から読み進めたバイト数。
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+}
+
+impl<R: BufRead> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.position += amt as u64;
+    }
+}
+
+/// 先のバイト列を読み進めずに覗ける `BufRead`。
+/// パイプのように1回の読み込みで少しずつしか届かない入力でも、形式やメンバーの先頭を判別できるように、
+/// 必要なバイト数がそろうまで読み込んでおく。シークはしない。
+pub struct LookAhead<R> {
+    inner: R,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> LookAhead<R> {
+    pub fn new(inner: R) -> Self {
+        LookAhead { inner, buf: Vec::new(), pos: 0 }
+    }
+
+    /// 次の `n` バイトを読み進めずに返す。入力がそれより早く終われば残りのすべてを返す。
+    pub fn peek(&mut self, n: usize) -> io::Result<&[u8]> {
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        let mut chunk = [0u8; 64];
+        while self.buf.len() < n {
+            let want = (n - self.buf.len()).min(chunk.len());
+            match self.inner.read(&mut chunk[..want]) {
+                Ok(0) => break,
+                Ok(len) => self.buf.extend_from_slice(&chunk[..len]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(&self.buf[..n.min(self.buf.len())])
+    }
+}
+
+impl<R: BufRead> Read for LookAhead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for LookAhead<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // 覗いたバイト列を返し終えたら、元の入力のバッファーをそのまま使う。
+        if self.pos < self.buf.len() {
+            return Ok(&self.buf[self.pos..]);
+        }
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.pos < self.buf.len() {
+            self.pos += amt;
+            if self.pos >= self.buf.len() {
+                self.buf.clear();
+                self.pos = 0;
+            }
+        } else {
+            self.inner.consume(amt);
+        }
+    }
+}
+
+/// 展開し終えたメンバーの情報。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberInfo {
+    /// 0から数えたメンバーの番号。
+    pub index: u64,
+    /// 圧縮された入力の先頭からのメンバーの開始位置。
+    pub offset: u64,
+    /// ヘッダーとトレーラーを含むメンバー全体のバイト数。
+    pub compressed_len: u64,
+    /// メンバーのヘッダー。
+    pub header: Header,
+    /// トレーラーの値。展開したデータと一致することは確認済み。
+    pub trailer: Trailer,
+    /// 実際に展開されたバイト数 (ISIZE と違い 2^32 で切り詰めない)。
+    pub uncompressed_len: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Header,
+    Body,
+    Trailer,
+    Done,
+}
+
+/// すべてのメンバーを順に展開する `MultiGzDecoder` 相当のデコーダー。
+///
+/// `MultiGzDecoder` と違い、メンバーごとに CRC32 と ISIZE を確かめ、
+/// 失敗した時にはメンバーの番号とバイト位置を持つ [`GzTestError`] を
+/// `io::Error` に包んで返す。
+pub struct MultiMemberDecoder<R> {
+    input: CountingReader<R>,
+    name: String,
+    state: State,
+    member: u64,
+    member_start: u64,
+    header: Header,
+    inflate: Decompress,
+    crc: Crc,
+    member_out: u64,
+    // この番号のメンバーに来たら、残りの入力を読まずに終わる。
+    end_member: Option<u64>,
+}
+
+impl<R: BufRead> MultiMemberDecoder<R> {
+    /// `name` はエラーメッセージに使うファイル名。
+    pub fn new(inner: R, name: &str) -> Self {
+        MultiMemberDecoder {
+            input: CountingReader::new(inner),
+            name: name.to_string(),
+            state: State::Header,
+            member: 0,
+            member_start: 0,
+            header: Header::default(),
+            inflate: Decompress::new(false),
+            crc: Crc::new(),
+            member_out: 0,
+            end_member: None,
+        }
+    }
+
+    /// 入力が元のファイルの `offset` バイト目から始まり、最初のメンバーの番号が `member` であるとして
+    /// 位置と番号を数える。途中から読み直す時に使う。
+    pub fn starting_at(mut self, offset: u64, member: u64) -> Self {
+        self.input.position = offset;
+        self.member_start = offset;
+        self.member = member;
+        self
+    }
+
+    /// 最初のメンバーだけを展開し、その後に続く入力は読まない。
+    pub fn one_member(mut self) -> Self {
+        self.end_member = Some(self.member + 1);
+        self
+    }
+
+    /// 元の入力への参照。
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.input.inner
+    }
+
+    /// 元の入力を返す。まだ読み始めていなければ、入力は読み進めていない。
+    pub fn into_inner(self) -> R {
+        self.input.inner
+    }
+
+    /// 現在の (またはエラーが起きた) メンバーの開始位置。
+    pub fn member_start(&self) -> u64 {
+        self.member_start
+    }
+
+
+    fn location_at(&self, offset: u64) -> Location {
+        Location { file: self.name.clone(), member: self.member, offset }
+    }
+
+    fn read_error(&self, source: io::Error) -> GzTestError {
+        if source.kind() == io::ErrorKind::UnexpectedEof {
+            GzTestError::Truncated { at: self.location() }
+        } else {
+            GzTestError::Read { at: self.location(), source }
+        }
+    }
+
+    // 次のメンバーのヘッダーを読む。入力が終わっていれば false を返す。
+    fn start_member(&mut self) -> Result<bool, GzTestError> {
+        let start = self.input.position();
+        self.member_start = start;
+        if self.end_member == Some(self.member) {
+            return Ok(false);
+        }
+        let first = match self.input.fill_buf() {
+            Ok(buf) => buf.first().copied(),
+            Err(err) => return Err(self.read_error(err)),
+        };
+        match first {
+            // 空の入力は gzip として不完全。
+            None if self.member == 0 => return Err(GzTestError::Truncated { at: self.location() }),
+            None => return Ok(false),
+            Some(byte) if byte != ID1 && self.member > 0 => {
+                return Err(GzTestError::TrailingGarbage { at: self.location() });
+            }
+            Some(_) => {}
+        }
+        self.header = match header::read_header(&mut self.input) {
+            Ok(header) => header,
+            Err(HeaderError::Eof) => return Err(GzTestError::Truncated { at: self.location() }),
+            Err(HeaderError::NotGzip) if self.member > 0 => {
+                return Err(GzTestError::TrailingGarbage { at: self.location_at(start) });
+            }
+            Err(HeaderError::NotGzip) => {
+                return Err(GzTestError::Header { at: self.location_at(start), reason: "not in gzip format" });
+            }
+            Err(HeaderError::Invalid(reason)) => {
+                return Err(GzTestError::Header { at: self.location_at(start), reason });
+            }
+            Err(HeaderError::Io(err)) => return Err(self.read_error(err)),
+        };
+        self.inflate.reset(false);
+        self.crc.reset();
+        self.member_out = 0;
+        self.state = State::Body;
+        Ok(true)
+    }
+
+    // 圧縮データを展開して buf に書き込み、書き込んだバイト数を返す。
+    fn read_body(&mut self, buf: &mut [u8]) -> Result<usize, GzTestError> {
+        let input = match self.input.fill_buf() {
+            Ok(input) => input,
+            Err(err) => return Err(self.read_error(err)),
+        };
+        if input.is_empty() {
+            return Err(GzTestError::Truncated { at: self.location() });
+        }
+        let before_in = self.inflate.total_in();
+        let before_out = self.inflate.total_out();
+        let status = self.inflate.decompress(input, buf, FlushDecompress::None);
+        let consumed = (self.inflate.total_in() - before_in) as usize;
+        let produced = (self.inflate.total_out() - before_out) as usize;
+        self.input.consume(consumed);
+        let status = status.map_err(|err| GzTestError::Corrupt {
+            at: self.location(),
+            reason: err.message().unwrap_or("invalid deflate data").to_string(),
+        })?;
+        self.crc.update(&buf[..produced]);
+        self.member_out += produced as u64;
+        match status {
+            Status::StreamEnd => self.state = State::Trailer,
+            // 入力も出力も進まない時は壊れたデータとして扱い、無限ループを避ける。
+            _ if consumed == 0 && produced == 0 => {
+                return Err(GzTestError::Corrupt {
+                    at: self.location(),
+                    reason: "no progress in deflate stream".to_string(),
+                });
+            }
+            _ => {}
+        }
+        Ok(produced)
+    }
+
+    // トレーラーを読み、展開したデータの CRC32 と ISIZE を確かめる。
+    fn finish_member(&mut self) -> Result<MemberInfo, GzTestError> {
+        let at = self.location();
+        let trailer = header::read_trailer(&mut self.input).map_err(|err| self.read_error(err))?;
+        if trailer.crc32 != self.crc.sum() {
+            return Err(GzTestError::Crc { at, expected: trailer.crc32, actual: self.crc.sum() });
+        }
+        if trailer.isize != self.member_out as u32 {
+            return Err(GzTestError::Isize { at, expected: trailer.isize, actual: self.member_out as u32 });
+        }
+        let info = MemberInfo {
+            index: self.member,
+            offset: self.member_start,
+            compressed_len: self.input.position() - self.member_start,
+            header: std::mem::take(&mut self.header),
+            trailer,
+            uncompressed_len: self.member_out,
+        };
+        self.member += 1;
+        self.state = State::Header;
+        Ok(info)
+    }
+
+    // エラーの後は読み込みを続けない。
+    fn fail(&mut self, err: GzTestError) -> io::Error {
+        self.state = State::Done;
+        err.into()
+    }
+
+    /// 次のメンバーを最後まで展開して `out` に書き出し、その情報を返す。
+    /// すべてのメンバーを読み終えていれば `None` を返す。
+    pub fn next_member<W: Write>(&mut self, out: &mut W) -> io::Result<Option<MemberInfo>> {
+        let mut buf = [0u8; 32 * 1024];
+        loop {
+            match self.state {
+                State::Header => match self.start_member() {
+                    Ok(true) => {}
+                    Ok(false) => self.state = State::Done,
+                    Err(err) => return Err(self.fail(err)),
+                },
+                State::Body => {
+                    let n = self.read_body(&mut buf).map_err(|err| self.fail(err))?;
+                    out.write_all(&buf[..n])?;
+                }
+                State::Trailer => return self.finish_member().map(Some).map_err(|err| self.fail(err)),
+                State::Done => return Ok(None),
+            }
+        }
+    }
+
+    fn step(&mut self, buf: &mut [u8]) -> Result<usize, GzTestError> {
+        loop {
+            match self.state {
+                State::Header => {
+                    if !self.start_member()? {
+                        self.state = State::Done;
+                    }
+                }
+                State::Body => {
+                    let n = self.read_body(buf)?;
+                    if n > 0 {
+                        return Ok(n);
+                    }
+                }
+                State::Trailer => {
+                    self.finish_member()?;
+                }
+                State::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+/// 展開中の入力の位置を返せるデコーダー。展開したデータの誤りを報告する時に使う。
+pub trait Locate {
+    /// 現在の読み込み位置。
+    fn location(&self) -> Location;
+}
+
+impl<D: Locate> Locate for io::BufReader<D> {
+    /// 内側のデコーダーの位置。バッファに残っているデータはその手前で展開したもの。
+    fn location(&self) -> Location {
+        self.get_ref().location()
+    }
+}
+
+impl<R: BufRead> Locate for MultiMemberDecoder<R> {
+    /// 現在の読み込み位置。
+    fn location(&self) -> Location {
+        self.location_at(self.input.position())
+    }
+}
+
+impl<R: BufRead> Read for MultiMemberDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.step(buf).map_err(|err| self.fail(err))
+    }
+}
+
+/// gzip のメンバーを1つずつ取り出す。
+///
+/// [`Members::next_member`] が返す [`Member`] はヘッダーと圧縮データ上の開始位置を持ち、
+/// `Read` でそのメンバーの展開したデータだけを読める。読み終えると確かめた CRC32 と ISIZE を
+/// [`Member::info`] で返す。`Member` は入力を借りるので `Iterator` にはできず、
+/// `while let Some(member) = members.next_member()` のように使う。
+pub struct Members<R> {
+    decoder: MultiMemberDecoder<R>,
+}
+
+impl<R: BufRead> Members<R> {
+    /// `name` はエラーメッセージに使うファイル名。
+    pub fn new(inner: R, name: &str) -> Self {
+        Members { decoder: MultiMemberDecoder::new(inner, name) }
+    }
+
+    /// 次のメンバーのヘッダーを読む。入力が終わっていれば `None` を返す。
+    /// 前のメンバーを読み残していれば、残りを展開して確かめてから次に進む。エラーの後は `None` を返す。
+    pub fn next_member(&mut self) -> Option<Result<Member<'_, R>, GzTestError>> {
+        let decoder = &mut self.decoder;
+        let mut buf = [0u8; 32 * 1024];
+        loop {
+            let result = match decoder.state {
+                State::Header => match decoder.start_member() {
+                    Ok(true) => return Some(Ok(Member { decoder, info: None })),
+                    Ok(false) => {
+                        decoder.state = State::Done;
+                        return None;
+                    }
+                    Err(err) => Err(err),
+                },
+                // 前のメンバーの残り。
+                State::Body => decoder.read_body(&mut buf).map(drop),
+                State::Trailer => decoder.finish_member().map(drop),
+                State::Done => return None,
+            };
+            if let Err(err) = result {
+                decoder.state = State::Done;
+                return Some(Err(err));
+            }
+        }
+    }
+
+    /// 元の入力を返す。
+    pub fn into_inner(self) -> R {
+        self.decoder.into_inner()
+    }
+}
+
+/// [`Members`] が返す1つのメンバー。`Read` でこのメンバーの展開したデータを読む。
+pub struct Member<'a, R> {
+    decoder: &'a mut MultiMemberDecoder<R>,
+    // 読み終えてトレーラーを確かめた後の情報。
+    info: Option<MemberInfo>,
+}
+
+impl<R: BufRead> Member<'_, R> {
+    /// 0から数えたメンバーの番号。
+    pub fn index(&self) -> u64 {
+        self.info.as_ref().map_or(self.decoder.member, |info| info.index)
+    }
+
+    /// メンバーのヘッダー。FEXTRA のサブフィールドは [`Header::extra_subfields`] で取り出す。
+    pub fn header(&self) -> &Header {
+        self.info.as_ref().map_or(&self.decoder.header, |info| &info.header)
+    }
+
+    /// 圧縮された入力の先頭からのメンバーの開始位置。
+    pub fn offset(&self) -> u64 {
+        self.info.as_ref().map_or(self.decoder.member_start, |info| info.offset)
+    }
+
+    /// 圧縮データ上のメンバーの範囲 (ヘッダーとトレーラーを含む)。終わりは読み終えるまで分からないので `None` を返す。
+    pub fn compressed_range(&self) -> Option<std::ops::Range<u64>> {
+        self.info.as_ref().map(|info| info.offset..info.offset + info.compressed_len)
+    }
+
+    /// 読み終えた後の、CRC32 と ISIZE を確かめたトレーラーを含む情報。読み終えるまでは `None` を返す。
+    pub fn info(&self) -> Option<&MemberInfo> {
+        self.info.as_ref()
+    }
+
+    /// 残りのデータを展開して確かめ、メンバーの情報を返す。
+    pub fn finish(mut self) -> Result<MemberInfo, GzTestError> {
+        io::copy(&mut self, &mut io::sink()).map_err(|err| {
+            GzTestError::from_io(err).unwrap_or_else(|source| GzTestError::Read { at: self.decoder.location(), source })
+        })?;
+        Ok(self.info.take().expect("drained"))
+    }
+
+    fn step(&mut self, buf: &mut [u8]) -> Result<usize, GzTestError> {
+        loop {
+            match self.decoder.state {
+                State::Body => {
+                    let n = self.decoder.read_body(buf)?;
+                    if n > 0 {
+                        return Ok(n);
+                    }
+                }
+                State::Trailer => {
+                    self.info = Some(self.decoder.finish_member()?);
+                    return Ok(0);
+                }
+                // エラーの後は読み込みを続けない。
+                State::Header | State::Done => {
+                    return Err(GzTestError::Read {
+                        at: self.decoder.location(),
+                        source: io::Error::other("cannot read after an earlier error"),
+                    })
+                }
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for Member<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.info.is_some() {
+            return Ok(0);
+        }
+        self.step(buf).map_err(|err| self.decoder.fail(err))
+    }
+}
+
+impl<R: BufRead> Locate for Member<'_, R> {
+    fn location(&self) -> Location {
+        self.decoder.location()
+    }
+}
@@ -0,0 +1,161 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// エラーが起きた場所。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// 入力ファイルのパス (標準入力の時は `-`)。
+    pub file: String,
+    /// 0から数えたメンバーの番号。
+    pub member: u64,
+    /// エラーを検出した時点の、圧縮された入力の先頭からのバイト位置。
+    pub offset: u64,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' (member {}, byte offset {})", self.file, self.member, self.offset)
+    }
+}
+
+/// gzipファイルの読み込みで起きるエラー。
+///
+/// 種類ごとに [`GzTestError::exit_code`] で異なる終了コードを返す。
+#[derive(Debug)]
+pub enum GzTestError {
+    /// ファイルを開けなかった。
+    Open { at: Location, source: io::Error },
+    /// ヘッダーが壊れている、または gzip 形式ではない。
+    Header { at: Location, reason: &'static str },
+    /// トレーラーの CRC32 と展開したデータの CRC32 が一致しない。
+    Crc { at: Location, expected: u32, actual: u32 },
+    /// トレーラーの ISIZE と展開したデータのサイズが一致しない。
+    Isize { at: Location, expected: u32, actual: u32 },
+    /// メンバーの途中で入力が終わった。
+    Truncated { at: Location },
+    /// 最後のメンバーの後に gzip ではないデータが続いている。
+    TrailingGarbage { at: Location },
+    /// 展開した行が UTF-8 として正しくない。`line` は1から数えた行番号。
+    Utf8 { at: Location, line: u64 },
+    /// 圧縮データ (deflate) が壊れている。
+    Corrupt { at: Location, reason: String },
+    /// 入力の読み込みに失敗した。
+    Read { at: Location, source: io::Error },
+}
+
+impl GzTestError {
+    /// エラーが起きた場所を返す。
+    pub fn location(&self) -> &Location {
+        match self {
+            GzTestError::Open { at, .. }
+            | GzTestError::Header { at, .. }
+            | GzTestError::Crc { at, .. }
+            | GzTestError::Isize { at, .. }
+            | GzTestError::Truncated { at }
+            | GzTestError::TrailingGarbage { at }
+            | GzTestError::Utf8 { at, .. }
+            | GzTestError::Corrupt { at, .. }
+            | GzTestError::Read { at, .. } => at,
+        }
+    }
+
+    /// プロセスの終了コード。スクリプトから判別できるように値は変えない。
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            GzTestError::Open { .. } => 3,
+            GzTestError::Header { .. } => 4,
+            GzTestError::Crc { .. } => 5,
+            GzTestError::Isize { .. } => 6,
+            GzTestError::Truncated { .. } => 7,
+            GzTestError::TrailingGarbage { .. } => 8,
+            GzTestError::Utf8 { .. } => 9,
+            GzTestError::Corrupt { .. } => 10,
+            GzTestError::Read { .. } => 11,
+        }
+    }
+
+    /// `io::Error` に包まれた `GzTestError` を取り出す。含まれていなければ元のエラーを返す。
+    pub fn from_io(err: io::Error) -> Result<GzTestError, io::Error> {
+        if err.get_ref().is_some_and(|inner| inner.is::<GzTestError>()) {
+            let inner = err.into_inner().expect("checked above");
+            Ok(*inner.downcast::<GzTestError>().expect("checked above"))
+        } else {
+            Err(err)
+        }
+    }
+}
+
+impl fmt::Display for GzTestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GzTestError::Open { at, source } => {
+                write!(f, "Cannot open file '{}', Error: {}", at.file, source)
+            }
+            GzTestError::Header { at, reason } => write!(f, "Invalid gzip header in {}: {}", at, reason),
+            GzTestError::Crc { at, expected, actual } => write!(
+                f,
+                "CRC32 mismatch in {}: trailer has {:08x}, data has {:08x}",
+                at, expected, actual
+            ),
+            GzTestError::Isize { at, expected, actual } => write!(
+                f,
+                "ISIZE mismatch in {}: trailer has {}, data has {}",
+                at, expected, actual
+            ),
+            GzTestError::Truncated { at } => write!(f, "Unexpected end of file in {}", at),
+            GzTestError::TrailingGarbage { at } => write!(f, "Trailing garbage after the last member in {}", at),
+            GzTestError::Utf8 { at, line } => {
+                write!(f, "Invalid UTF-8 in the {}th line of {}", line, at)
+            }
+            GzTestError::Corrupt { at, reason } => write!(f, "Corrupt compressed data in {}: {}", at, reason),
+            GzTestError::Read { at, source } => write!(f, "Cannot read {}, Error: {}", at, source),
+        }
+    }
+}
+
+impl Error for GzTestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GzTestError::Open { source, .. } | GzTestError::Read { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<GzTestError> for io::Error {
+    fn from(err: GzTestError) -> Self {
+        let kind = match err {
+            GzTestError::Open { ref source, .. } | GzTestError::Read { ref source, .. } => source.kind(),
+            GzTestError::Truncated { .. } => io::ErrorKind::UnexpectedEof,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// 個々の結果はすでに表示してあり、まとめの理由と終了コードだけを呼び出し元に伝えるエラー。
+#[derive(Debug)]
+pub struct Failed {
+    pub code: u8,
+    pub message: String,
+}
+
+impl fmt::Display for Failed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for Failed {}
+
+/// エラーに対応するプロセスの終了コード。
+pub fn exit_code(err: &(dyn Error + 'static)) -> u8 {
+    if let Some(err) = err.downcast_ref::<GzTestError>() {
+        err.exit_code()
+    } else if let Some(err) = err.downcast_ref::<Failed>() {
+        err.code
+    } else {
+        1
+    }
+}
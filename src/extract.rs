@@ -0,0 +1,103 @@
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+
+use crate::bgzf::BgzfReader;
+use crate::cli::{ExtractArgs, Range};
+use crate::index::{self, IfStale, IndexedReader};
+use crate::{create_output, open_input, open_seekable, reading_error, ReadSeek};
+
+// 前にしか読み進めない入力。索引のない入力から先頭から順に展開する時は巻き戻さないので、
+// 標準入力をメモリーに読み込まずに `IndexedReader` に渡せる。
+struct ForwardOnly<R>(R);
+
+impl<R: Read> Read for ForwardOnly<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<R> Seek for ForwardOnly<R> {
+    fn seek(&mut self, _: SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "the standard input cannot be rewound"))
+    }
+}
+
+// 現在の位置から、1から数えて first 行目から last 行目まで (last が None なら最後まで) を書き出す。
+fn write_line_range<R: BufRead, W: Write>(
+    reader: &mut R,
+    first: u64,
+    last: Option<u64>,
+    out: &mut W,
+) -> Result<(), Box<dyn Error>> {
+    let mut line = first;
+    while last.is_none_or(|last| line <= last) {
+        let buf = reader.fill_buf().map_err(reading_error)?;
+        if buf.is_empty() {
+            break;
+        }
+        let n = match buf.iter().position(|&b| b == b'\n') {
+            Some(i) => {
+                line += 1;
+                i + 1
+            }
+            None => buf.len(),
+        };
+        out.write_all(&buf[..n])?;
+        reader.consume(n);
+    }
+    Ok(())
+}
+
+// BGZF ファイルの仮想ファイルオフセットの範囲を書き出す。索引は使わない。
+fn write_virtual_range<W: Write>(filename: &str, range: &Range, out: &mut W) -> Result<(), Box<dyn Error>> {
+    let mut reader = BgzfReader::new(BufReader::new(open_seekable(filename)?), filename);
+    reader.seek_virtual(range.start).map_err(reading_error)?;
+    loop {
+        let end = match range.end {
+            Some(end) if reader.virtual_offset() >= end => break,
+            Some(end) if reader.virtual_offset() >> 16 == end >> 16 => Some((end & 0xffff) - (reader.virtual_offset() & 0xffff)),
+            _ => None,
+        };
+        let buf = reader.fill_buf().map_err(reading_error)?;
+        if buf.is_empty() {
+            break;
+        }
+        let n = end.map_or(buf.len(), |end| buf.len().min(end as usize));
+        out.write_all(&buf[..n])?;
+        reader.consume(n);
+    }
+    Ok(())
+}
+
+/// `extract` コマンド: 展開後のバイト範囲または行範囲だけを書き出す。
+///
+/// gzip ファイルの隣に `index` コマンドで作った索引があれば、範囲の手前のチェックポイントから展開するので、
+/// 大きなファイルでも先頭から展開し直さずに済む。索引がなければ先頭から展開する。
+/// `--virtual` では BGZF のブロックの位置から直接展開する。
+/// 標準入力は `--virtual` の時を除いてメモリーに読み込まず、先頭から順に展開する。
+/// `-o` のファイルは書き終えてから置き換えるので、読んでいる入力を途中で切り詰めることはない。
+pub fn extract(args: &ExtractArgs) -> Result<(), Box<dyn Error>> {
+    let mut writer = create_output(args.output.as_deref(), &args.inputs)?;
+    for filename in &args.inputs {
+        if let Some(range) = &args.virtual_offsets {
+            write_virtual_range(filename, range, &mut writer)?;
+            continue;
+        }
+        let index = index::load(filename, IfStale::Fail)?;
+        let input: Box<dyn ReadSeek> = match filename.as_str() {
+            "-" => Box::new(ForwardOnly(open_input(filename)?)),
+            _ => open_seekable(filename)?,
+        };
+        let mut reader = IndexedReader::new(input, filename, index);
+        if let Some(lines) = &args.lines {
+            reader.seek_line(lines.start).map_err(reading_error)?;
+            write_line_range(&mut reader, lines.start, lines.end, &mut writer)?;
+            continue;
+        }
+        let (start, end) = args.bytes.as_ref().map_or((0, None), |bytes| (bytes.start, bytes.end));
+        reader.seek(SeekFrom::Start(start))?;
+        let len = end.map_or(u64::MAX, |end| end.saturating_sub(start));
+        io::copy(&mut reader.by_ref().take(len), &mut writer).map_err(reading_error)?;
+    }
+    writer.finish()
+}
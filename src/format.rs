@@ -0,0 +1,220 @@
+use std::io::{self, BufRead, Read};
+
+use flate2::{Decompress, FlushDecompress, Status};
+
+use crate::decoder::{CountingReader, LookAhead, Locate, MultiMemberDecoder};
+use crate::error::{GzTestError, Location};
+use crate::header::{ID1, ID2};
+use crate::open_buffered;
+use crate::parallel::ParallelDecoder;
+
+/// 先頭のバイト列から判別できる入力の形式。ヘッダーのない raw deflate は判別できないので `--raw` で指定する。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Gzip,
+    /// zlib (RFC 1950) のストリーム。
+    Zlib,
+    /// 圧縮されていないデータ。
+    Plain,
+}
+
+/// 入力の先頭のバイト列から形式を判別する。
+pub fn sniff(head: &[u8]) -> Format {
+    match *head {
+        [ID1, ID2, ..] => Format::Gzip,
+        // CMF の CM が 8 (deflate) で CINFO が 7 以下、CMF と FLG を合わせて 31 の倍数、FDICT が立っていない。
+        [cmf, flg, ..] if cmf & 0x0f == 8 && cmf >> 4 <= 7 && (u16::from(cmf) << 8 | u16::from(flg)) % 31 == 0 && flg & 0x20 == 0 => {
+            Format::Zlib
+        }
+        _ => Format::Plain,
+    }
+}
+
+// `plain` の時に、zlib のヘッダーに見える入力を本当に展開できるかを確かめるために覗くバイト数。
+const ZLIB_PROBE_LEN: usize = 64 * 1024;
+
+// 入力の先頭 `head` を zlib として展開してみて、誤りがなければ true を返す。
+// "HK" や "x^" で始まるテキストも zlib のヘッダーに見えるので、`-f` ではこれで確かめてからそのまま書き出すかを決める。
+// `complete` は `head` が入力のすべての時に true で、その時はストリームが過不足なく終わることも確かめる。
+fn decodes_as_zlib(head: &[u8], complete: bool) -> bool {
+    let mut inflate = Decompress::new(true);
+    let mut out = vec![0u8; 32 * 1024];
+    loop {
+        let before_in = inflate.total_in();
+        let before_out = inflate.total_out();
+        match inflate.decompress(&head[before_in as usize..], &mut out, FlushDecompress::None) {
+            Err(_) => return false,
+            // 覗いた範囲でストリームが終わったのに入力が続くなら、余分なデータがある。
+            Ok(Status::StreamEnd) => return complete && inflate.total_in() as usize == head.len(),
+            Ok(_) if inflate.total_in() == before_in && inflate.total_out() == before_out => return !complete,
+            Ok(_) => {}
+        }
+    }
+}
+
+/// zlib または raw deflate のストリームを1つ展開する `Read`。
+///
+/// flate2 の `ZlibDecoder` と `DeflateDecoder` はストリームの途中で入力が終わっても
+/// そこまでのデータを返して正常に終わるので、[`MultiMemberDecoder`] と同じく `Decompress` を直接使い、
+/// 途中で終わった入力、壊れたデータ (zlib では Adler-32 の不一致も含む)、ストリームの後に続くデータを
+/// 位置を持つ [`GzTestError`] として返す。
+pub struct StreamDecoder<R> {
+    input: CountingReader<R>,
+    name: String,
+    inflate: Decompress,
+    done: bool,
+}
+
+impl<R: BufRead> StreamDecoder<R> {
+    /// `zlib` が false の時は raw deflate として展開する。
+    pub fn new(inner: R, name: &str, zlib: bool) -> Self {
+        StreamDecoder { input: CountingReader::new(inner), name: name.to_string(), inflate: Decompress::new(zlib), done: false }
+    }
+
+    fn read_error(&self, source: io::Error) -> GzTestError {
+        GzTestError::Read { at: self.location(), source }
+    }
+
+    fn step(&mut self, buf: &mut [u8]) -> Result<usize, GzTestError> {
+        while !self.done {
+            let input = match self.input.fill_buf() {
+                Ok(input) => input,
+                Err(err) => return Err(self.read_error(err)),
+            };
+            if input.is_empty() {
+                return Err(GzTestError::Truncated { at: self.location() });
+            }
+            let before_in = self.inflate.total_in();
+            let before_out = self.inflate.total_out();
+            let status = self.inflate.decompress(input, buf, FlushDecompress::None);
+            let consumed = (self.inflate.total_in() - before_in) as usize;
+            let produced = (self.inflate.total_out() - before_out) as usize;
+            self.input.consume(consumed);
+            let status = status.map_err(|err| GzTestError::Corrupt {
+                at: self.location(),
+                reason: err.message().unwrap_or("invalid deflate data").to_string(),
+            })?;
+            match status {
+                Status::StreamEnd => self.done = true,
+                _ if consumed == 0 && produced == 0 => {
+                    return Err(GzTestError::Corrupt {
+                        at: self.location(),
+                        reason: "no progress in deflate stream".to_string(),
+                    });
+                }
+                _ => {}
+            }
+            if produced > 0 {
+                return Ok(produced);
+            }
+        }
+        // gzip と違って続けて展開できるストリームはないので、残りのデータはすべて余分なデータ。
+        let at_end = match self.input.fill_buf() {
+            Ok(rest) => rest.is_empty(),
+            Err(err) => return Err(self.read_error(err)),
+        };
+        if !at_end {
+            return Err(GzTestError::TrailingGarbage { at: self.location() });
+        }
+        Ok(0)
+    }
+}
+
+impl<R: BufRead> Locate for StreamDecoder<R> {
+    fn location(&self) -> Location {
+        Location { file: self.name.clone(), member: 0, offset: self.input.position() }
+    }
+}
+
+impl<R: BufRead> Read for StreamDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // エラーの後は読み込みを続けない。
+        self.step(buf).map_err(|err| {
+            self.done = true;
+            err.into()
+        })
+    }
+}
+
+/// 入力の形式に合わせて選んだデコーダー。
+pub enum AnyDecoder<R> {
+    Gzip(MultiMemberDecoder<R>),
+    Stream(StreamDecoder<R>),
+    /// gzip のメンバーをいくつものスレッドで展開する。
+    Parallel(Box<ParallelDecoder>),
+    /// 圧縮されていない入力をそのまま返す (`zcat -f` と同じ)。
+    Plain(CountingReader<R>, String),
+}
+
+impl<R: BufRead> Locate for AnyDecoder<R> {
+    fn location(&self) -> Location {
+        match self {
+            AnyDecoder::Gzip(decoder) => decoder.location(),
+            AnyDecoder::Stream(decoder) => decoder.location(),
+            AnyDecoder::Parallel(decoder) => decoder.location(),
+            AnyDecoder::Plain(input, name) => Location { file: name.clone(), member: 0, offset: input.position() },
+        }
+    }
+}
+
+impl<R: BufRead> Read for AnyDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            AnyDecoder::Gzip(decoder) => decoder.read(buf),
+            AnyDecoder::Stream(decoder) => decoder.read(buf),
+            AnyDecoder::Parallel(decoder) => decoder.read(buf),
+            AnyDecoder::Plain(input, _) => input.read(buf),
+        }
+    }
+}
+
+/// 入力を読み始め、先頭のバイト列から形式を判別して展開するデコーダーを準備する。
+///
+/// 入力はシークしないので、パイプからでも読める。先頭は [`LookAhead`] で読み進めずに覗くので、
+/// 1回の読み込みで1バイトずつしか届かない入力でも判別を誤らない。
+/// `raw` の時は raw deflate として展開する。gzip でも zlib でもない入力は、`plain` の時はそのまま返し、
+/// そうでなければ gzip として読んで "not in gzip format" のエラーにする。
+/// `plain` の時は、zlib のヘッダーに見えても先頭の 64 KiB を zlib として展開できなければそのまま返す。
+pub fn from_reader<R: BufRead>(input: R, filename: &str, raw: bool, plain: bool) -> Result<AnyDecoder<LookAhead<R>>, GzTestError> {
+    let mut input = LookAhead::new(input);
+    if raw {
+        return Ok(AnyDecoder::Stream(StreamDecoder::new(input, filename, false)));
+    }
+    let read_error = |source| GzTestError::Read { at: Location { file: filename.to_string(), member: 0, offset: 0 }, source };
+    let mut format = sniff(input.peek(2).map_err(read_error)?);
+    if format == Format::Zlib && plain {
+        let head = input.peek(ZLIB_PROBE_LEN).map_err(read_error)?;
+        if !decodes_as_zlib(head, head.len() < ZLIB_PROBE_LEN) {
+            format = Format::Plain;
+        }
+    }
+    Ok(match format {
+        Format::Zlib => AnyDecoder::Stream(StreamDecoder::new(input, filename, true)),
+        Format::Plain if plain => AnyDecoder::Plain(CountingReader::new(input), filename.to_string()),
+        _ => AnyDecoder::Gzip(MultiMemberDecoder::new(input, filename)),
+    })
+}
+
+/// 開いた入力ファイルを形式に合わせて展開するデコーダー。
+pub type InputDecoder = AnyDecoder<LookAhead<Box<dyn BufRead>>>;
+
+/// 入力ファイルを開き、[`from_reader`] で形式に合わせたデコーダーを準備する。
+/// gzip の入力は `threads` が2以上なら [`ParallelDecoder`] で展開する。
+pub fn open_any(
+    filename: &str,
+    raw: bool,
+    plain: bool,
+    threads: usize,
+) -> Result<InputDecoder, GzTestError> {
+    match from_reader(open_buffered(filename)?, filename, raw, plain)? {
+        // 標準入力は読み始めてしまったので、読んだ分も含めてメモリーに読み込む。
+        AnyDecoder::Gzip(decoder) if threads > 1 && filename == "-" => {
+            Ok(AnyDecoder::Parallel(Box::new(ParallelDecoder::from_reader(decoder.into_inner(), filename, threads)?)))
+        }
+        AnyDecoder::Gzip(_) if threads > 1 => Ok(AnyDecoder::Parallel(Box::new(ParallelDecoder::open(filename, threads)?))),
+        decoder => Ok(decoder),
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io::{stdout, BufWriter, Read, Write};
+
+use crate::cli::GrepArgs;
+use crate::decoder::Locate;
+use crate::error::Failed;
+use crate::format;
+use crate::reading_error;
+use crate::regex::{MatchOptions, Matcher, Regex};
+
+/// `grep` で選んだ行が1つもなかった時の終了コード。
+pub const EXIT_NO_MATCH: u8 = 15;
+
+// 展開したデータを行に分ける。BufReader を挟むとどのメンバーから読んだデータか分からなくなるので、
+// デコーダーから直接読む。デコーダーは1回の read で1つのメンバーのデータしか返さない。
+struct Lines<D> {
+    decoder: D,
+    buf: Vec<u8>,
+    start: usize,
+    end: usize,
+    // buf[start..end] を展開したメンバーの番号。
+    member: u64,
+}
+
+impl<D: Read + Locate> Lines<D> {
+    fn new(decoder: D) -> Self {
+        Lines { decoder, buf: vec![0; 64 * 1024], start: 0, end: 0, member: 0 }
+    }
+
+    // 次の行を末尾の "\n" も含めて line に読み込み、行が始まったメンバーの番号を返す。最後まで読んだら None を返す。
+    fn next_line(&mut self, line: &mut Vec<u8>) -> Result<Option<u64>, Box<dyn Error>> {
+        line.clear();
+        let mut member = None;
+        loop {
+            if self.start == self.end {
+                let len = self.decoder.read(&mut self.buf).map_err(reading_error)?;
+                if len == 0 {
+                    // 最後の行が "\n" で終わっていなければ、その行を返す。
+                    return Ok(member);
+                }
+                self.start = 0;
+                self.end = len;
+                self.member = self.decoder.location().member;
+            }
+            member.get_or_insert(self.member);
+            let chunk = &self.buf[self.start..self.end];
+            match chunk.iter().position(|&b| b == b'\n') {
+                Some(i) => {
+                    line.extend_from_slice(&chunk[..=i]);
+                    self.start += i + 1;
+                    return Ok(member);
+                }
+                None => {
+                    line.extend_from_slice(chunk);
+                    self.start = self.end;
+                }
+            }
+        }
+    }
+}
+
+// 表示する1行。
+struct Line {
+    number: u64,
+    member: u64,
+    text: Vec<u8>,
+}
+
+// 行を `FILE:MEMBER:[LINE:]TEXT` の形で書き出す。前後の行では ':' の代わりに '-' で区切る。
+fn write_line<W: Write>(out: &mut W, args: &GrepArgs, filename: &str, line: &Line, separator: char) -> Result<(), Box<dyn Error>> {
+    write!(out, "{}{}{}{}", filename, separator, line.member, separator)?;
+    if args.line_number {
+        write!(out, "{}{}", line.number, separator)?;
+    }
+    out.write_all(&line.text)?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+// 1つの入力の行を調べ、選んだ行の数を返す。printed は前の入力も含めて何かを表示したかを表す。
+fn grep_file<W: Write>(
+    args: &GrepArgs,
+    matcher: &mut Matcher,
+    filename: &str,
+    out: &mut W,
+    printed: &mut bool,
+) -> Result<u64, Box<dyn Error>> {
+    let mut lines = Lines::new(format::open_any(filename, false, false, 1)?);
+    let context = args.before > 0 || args.after > 0;
+    let mut before: VecDeque<Line> = VecDeque::with_capacity(args.before);
+    let mut after_left = 0;
+    let mut last_printed = None;
+    let mut selected_lines = 0;
+    let mut text = Vec::new();
+    for number in 1_u64.. {
+        let Some(member) = lines.next_line(&mut text)? else {
+            break;
+        };
+        // write_lines と同じく行末の "\n" と "\r\n" を取り除く。
+        if text.last() == Some(&b'\n') {
+            text.pop();
+            if text.last() == Some(&b'\r') {
+                text.pop();
+            }
+        }
+        let selected = matcher.is_match(&text) != args.invert;
+        if args.count {
+            selected_lines += u64::from(selected);
+            continue;
+        }
+        // 表示も前の行として覚えもしない行は、バッファーをそのまま次の行に使う。
+        if !selected && after_left == 0 && args.before == 0 {
+            continue;
+        }
+        let line = Line { number, member, text: std::mem::take(&mut text) };
+        if selected {
+            selected_lines += 1;
+            // 前に表示した行と続いていなければ、grep と同じく "--" で区切る。
+            let first = before.front().map_or(number, |line| line.number);
+            if context && *printed && last_printed.is_none_or(|last: u64| last + 1 < first) {
+                writeln!(out, "--")?;
+            }
+            for line in before.drain(..) {
+                write_line(out, args, filename, &line, '-')?;
+            }
+            write_line(out, args, filename, &line, ':')?;
+            last_printed = Some(number);
+            *printed = true;
+            after_left = args.after;
+        } else if after_left > 0 {
+            write_line(out, args, filename, &line, '-')?;
+            last_printed = Some(number);
+            after_left -= 1;
+        } else if args.before > 0 {
+            if before.len() == args.before {
+                before.pop_front();
+            }
+            before.push_back(line);
+        }
+    }
+    Ok(selected_lines)
+}
+
+/// `grep` コマンド: 展開した各行をパターンと照らし合わせ、選んだ行をファイル名とメンバーの番号を付けて表示する。
+///
+/// 行は `zcat | grep` と同じくメンバーをまたいで数える。行が2つのメンバーにまたがる時は、行が始まったメンバーの番号を表示する。
+pub fn grep(args: &GrepArgs) -> Result<(), Box<dyn Error>> {
+    let options = MatchOptions { ignore_case: args.ignore_case, whole_words: args.whole_words };
+    let regex = if args.fixed { Regex::literal(&args.pattern, options)? } else { Regex::new(&args.pattern, options)? };
+    let mut matcher = regex.matcher();
+    let mut out = BufWriter::new(stdout().lock());
+    let mut printed = false;
+    let mut selected_lines = 0;
+    for filename in &args.inputs {
+        let selected = grep_file(args, &mut matcher, filename, &mut out, &mut printed)?;
+        if args.count {
+            writeln!(out, "{}:{}", filename, selected)?;
+        }
+        selected_lines += selected;
+    }
+    out.flush()?;
+    if selected_lines == 0 {
+        return Err(Box::new(Failed { code: EXIT_NO_MATCH, message: "no lines selected".to_string() }));
+    }
+    Ok(())
+}
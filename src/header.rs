@@ -0,0 +1,286 @@
+use std::io::{self, BufRead, Read};
+
+use flate2::Crc;
+
+// RFC 1952 2.3.1 で定められた値。
+pub const ID1: u8 = 0x1f;
+pub const ID2: u8 = 0x8b;
+pub const CM_DEFLATE: u8 = 8;
+
+// FLG の各ビット。
+pub const FTEXT: u8 = 1 << 0;
+pub const FHCRC: u8 = 1 << 1;
+pub const FEXTRA: u8 = 1 << 2;
+pub const FNAME: u8 = 1 << 3;
+pub const FCOMMENT: u8 = 1 << 4;
+// 予約ビット。立っていたら不正なヘッダーとして扱う。
+const FRESERVED: u8 = 0xe0;
+
+// ヘッダーの固定長部分 (ID1 から OS まで) とトレーラーの長さ。
+pub const FIXED_HEADER_LEN: usize = 10;
+pub const TRAILER_LEN: usize = 8;
+
+/// gzipメンバーのヘッダー (RFC 1952 2.3.1)。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Header {
+    /// FLG のビット (`FTEXT` から `FCOMMENT`)。
+    pub flags: u8,
+    /// 1970-01-01 からの秒数。0 は時刻がないことを表す。
+    pub mtime: u32,
+    /// XFL (deflate では 2 が最大圧縮、4 が最速)。
+    pub xfl: u8,
+    /// RFC 1952 2.3.1 の OS の値 (3 が Unix)。
+    pub os: u8,
+    /// FEXTRA のデータ (XLEN を除いた本体)。
+    pub extra: Option<Vec<u8>>,
+    /// FNAME の値 (終端の0を除く)。
+    pub filename: Option<Vec<u8>>,
+    /// FCOMMENT の値 (終端の0を除く)。
+    pub comment: Option<Vec<u8>>,
+    /// FHCRC の値。
+    pub header_crc: Option<u16>,
+    /// ヘッダー全体のバイト数。
+    pub len: u64,
+}
+
+/// FEXTRA のサブフィールド (RFC 1952 2.3.1.1)。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtraSubfield<'a> {
+    pub si1: u8,
+    pub si2: u8,
+    pub data: &'a [u8],
+}
+
+impl Header {
+    /// ヘッダーをバイト列にする。
+    /// FLG の FEXTRA, FNAME, FCOMMENT, FHCRC は各フィールドの有無に合わせ、FHCRC は計算し直す。
+    /// FEXTRA が XLEN に収まらない (65535 バイトより長い) 時や、FNAME か FCOMMENT が 0 を含む時は
+    /// 読み直すと別のヘッダーになるので、`InvalidInput` のエラーを返す。
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let mut flags = self.flags & FTEXT;
+        let mut bytes = vec![ID1, ID2, CM_DEFLATE, 0];
+        bytes.extend_from_slice(&self.mtime.to_le_bytes());
+        bytes.push(self.xfl);
+        bytes.push(self.os);
+        if let Some(extra) = &self.extra {
+            flags |= FEXTRA;
+            let xlen = u16::try_from(extra.len()).map_err(|_| invalid("FEXTRA is longer than 65535 bytes"))?;
+            bytes.extend_from_slice(&xlen.to_le_bytes());
+            bytes.extend_from_slice(extra);
+        }
+        if let Some(filename) = &self.filename {
+            if filename.contains(&0) {
+                return Err(invalid("FNAME contains a zero byte"));
+            }
+            flags |= FNAME;
+            bytes.extend_from_slice(filename);
+            bytes.push(0);
+        }
+        if let Some(comment) = &self.comment {
+            if comment.contains(&0) {
+                return Err(invalid("FCOMMENT contains a zero byte"));
+            }
+            flags |= FCOMMENT;
+            bytes.extend_from_slice(comment);
+            bytes.push(0);
+        }
+        if self.header_crc.is_some() {
+            flags |= FHCRC;
+        }
+        bytes[3] = flags;
+        if self.header_crc.is_some() {
+            let mut crc = Crc::new();
+            crc.update(&bytes);
+            bytes.extend_from_slice(&(crc.sum() as u16).to_le_bytes());
+        }
+        Ok(bytes)
+    }
+
+    /// FEXTRA をサブフィールドに分ける。長さが合わない部分は無視する。
+    pub fn extra_subfields(&self) -> Vec<ExtraSubfield<'_>> {
+        let mut subfields = Vec::new();
+        let mut rest = self.extra.as_deref().unwrap_or_default();
+        while rest.len() >= 4 {
+            let len = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+            if rest.len() < 4 + len {
+                break;
+            }
+            subfields.push(ExtraSubfield { si1: rest[0], si2: rest[1], data: &rest[4..4 + len] });
+            rest = &rest[4 + len..];
+        }
+        subfields
+    }
+}
+
+/// gzipメンバーのトレーラー。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trailer {
+    /// 展開後のデータの CRC32。
+    pub crc32: u32,
+    /// 展開後のサイズを 2^32 で割った余り。
+    pub isize: u32,
+}
+
+/// ヘッダーの読み取りに失敗した理由。
+#[derive(Debug)]
+pub enum HeaderError {
+    /// ヘッダーの途中で入力が終わった。
+    Eof,
+    /// 先頭が gzip のマジックナンバー (1f 8b) ではない。
+    NotGzip,
+    /// ヘッダーの内容が RFC 1952 に合わない。
+    Invalid(&'static str),
+    Io(io::Error),
+}
+
+impl From<io::Error> for HeaderError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            HeaderError::Eof
+        } else {
+            HeaderError::Io(err)
+        }
+    }
+}
+
+// 読み取ったバイトをヘッダーの CRC と長さに加えながら読み進める。
+struct HeaderInput<'a, R> {
+    inner: &'a mut R,
+    crc: Crc,
+    len: u64,
+}
+
+impl<R: BufRead> HeaderInput<'_, R> {
+    fn read_bytes(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.inner.read_exact(buf)?;
+        self.crc.update(buf);
+        self.len += buf.len() as u64;
+        Ok(())
+    }
+
+    // 0終端の文字列を読み取る。終端の0は結果に含めない。
+    fn read_zero_terminated(&mut self) -> io::Result<Vec<u8>> {
+        let mut value = Vec::new();
+        self.inner.read_until(0, &mut value)?;
+        if value.last() != Some(&0) {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.crc.update(&value);
+        self.len += value.len() as u64;
+        value.pop();
+        Ok(value)
+    }
+}
+
+/// 入力の先頭が gzip のマジックナンバーで始まっているかを調べる。
+pub fn starts_with_magic(bytes: &[u8]) -> bool {
+    bytes.len() >= 2 && bytes[0] == ID1 && bytes[1] == ID2
+}
+
+/// バイト列がメンバーのヘッダーの先頭 (ID1, ID2, CM と予約ビットの立っていない FLG) に見えるかを調べる。
+pub fn looks_like_header(bytes: &[u8]) -> bool {
+    bytes.len() >= 4 && starts_with_magic(bytes) && bytes[2] == CM_DEFLATE && bytes[3] & FRESERVED == 0
+}
+
+/// メモリー上のデータの中でメンバーのヘッダーの先頭に見える位置を、前から順に `found` に渡す。
+/// [`scan_headers`] と同じだが、データをコピーせずに調べる。`found` が false を返した時はそこでやめる。
+pub fn scan_headers_in(data: &[u8], base: u64, mut found: impl FnMut(u64) -> bool) {
+    let mut i = 0;
+    while let Some(j) = data[i..].windows(4).position(looks_like_header) {
+        if !found(base + (i + j) as u64) {
+            return;
+        }
+        i += j + 1;
+    }
+}
+
+/// 入力の中でメンバーのヘッダーの先頭に見える位置を、前から順に `found` に渡す。
+/// `base` は入力の先頭の位置。`found` が false を返した時はそこでやめる。
+pub fn scan_headers<R: Read>(mut input: R, base: u64, mut found: impl FnMut(u64) -> bool) -> io::Result<()> {
+    let mut chunk = vec![0u8; 64 * 1024];
+    // 読み込みの区切りをまたぐヘッダーを見逃さないよう、まだ調べていない末尾3バイトを持ち越す。
+    let mut data = Vec::new();
+    let mut start = base;
+    loop {
+        let n = match input.read(&mut chunk) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        data.extend_from_slice(&chunk[..n]);
+        let mut i = 0;
+        while let Some(j) = data[i..].windows(4).position(looks_like_header) {
+            if !found(start + (i + j) as u64) {
+                return Ok(());
+            }
+            i += j + 1;
+        }
+        let checked = data.len() - data.len().min(3);
+        data.drain(..checked);
+        start += checked as u64;
+    }
+}
+
+/// 1メンバー分のヘッダーを読み取る。
+/// 読み終わった時には入力は圧縮データの先頭を指している。
+pub fn read_header<R: BufRead>(r: &mut R) -> Result<Header, HeaderError> {
+    let mut input = HeaderInput { inner: r, crc: Crc::new(), len: 0 };
+    let mut fixed = [0u8; FIXED_HEADER_LEN];
+    input.read_bytes(&mut fixed)?;
+    if !starts_with_magic(&fixed) {
+        return Err(HeaderError::NotGzip);
+    }
+    if fixed[2] != CM_DEFLATE {
+        return Err(HeaderError::Invalid("unknown compression method"));
+    }
+    let flags = fixed[3];
+    if flags & FRESERVED != 0 {
+        return Err(HeaderError::Invalid("reserved flag bits are set"));
+    }
+    let mut header = Header {
+        flags,
+        mtime: u32::from_le_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]),
+        xfl: fixed[8],
+        os: fixed[9],
+        ..Header::default()
+    };
+    if flags & FEXTRA != 0 {
+        let mut xlen = [0u8; 2];
+        input.read_bytes(&mut xlen)?;
+        let mut extra = vec![0u8; u16::from_le_bytes(xlen) as usize];
+        input.read_bytes(&mut extra)?;
+        header.extra = Some(extra);
+    }
+    if flags & FNAME != 0 {
+        header.filename = Some(input.read_zero_terminated()?);
+    }
+    if flags & FCOMMENT != 0 {
+        header.comment = Some(input.read_zero_terminated()?);
+    }
+    if flags & FHCRC != 0 {
+        // FHCRC はここまでのヘッダーの CRC32 の下位16ビット。
+        let expected = input.crc.sum() as u16;
+        let mut hcrc = [0u8; 2];
+        input.inner.read_exact(&mut hcrc)?;
+        input.len += 2;
+        let hcrc = u16::from_le_bytes(hcrc);
+        if hcrc != expected {
+            return Err(HeaderError::Invalid("header CRC16 mismatch"));
+        }
+        header.header_crc = Some(hcrc);
+    }
+    header.len = input.len;
+    Ok(header)
+}
+
+/// 圧縮データに続く8バイトのトレーラーを読み取る。
+pub fn read_trailer<R: Read>(r: &mut R) -> io::Result<Trailer> {
+    let mut bytes = [0u8; TRAILER_LEN];
+    r.read_exact(&mut bytes)?;
+    Ok(Trailer {
+        crc32: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        isize: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+    })
+}
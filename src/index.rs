@@ -0,0 +1,768 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+
+use crate::cli::IndexArgs;
+use crate::decoder::Locate;
+use crate::error::{Failed, GzTestError, Location};
+use crate::header::{self, HeaderError, ID1};
+use crate::inflate::{BitReader, InflateError, Inflater, Progress};
+use crate::open_input;
+use crate::output::{AtomicFile, OutputOptions};
+
+/// 索引ファイルが壊れている、または gzip ファイルより古い時の終了コード。
+pub const EXIT_BAD_INDEX: u8 = 14;
+
+// 索引ファイルの先頭に置く識別子。形式を変えたら末尾の番号を上げる。
+const MAGIC: &[u8; 8] = b"GZTIDX01";
+
+/// 展開を途中から再開できる位置。deflate のブロックの境界に置く。
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    /// 展開後のデータの先頭からの位置。
+    pub out: u64,
+    /// 次に読むビットを含むバイトの、圧縮された入力の先頭からの位置。
+    pub input: u64,
+    /// そのバイトのうち読み終えた下位ビットの数 (0-7)。
+    pub bits: u8,
+    /// 0から数えたメンバーの番号。
+    pub member: u64,
+    /// `out` より前にある改行の数。
+    pub lines: u64,
+    /// `out` までに展開したデータの末尾 (最大 32 KiB)。
+    pub window: Vec<u8>,
+}
+
+/// gzipファイルのランダムアクセス用の索引。
+#[derive(Debug, Clone, Default)]
+pub struct Index {
+    /// 索引を作った時の gzip ファイルのサイズと更新時刻。索引が古くなっていないかを確かめるのに使う。
+    pub gz_len: u64,
+    pub gz_mtime: u64,
+    /// チェックポイントの間隔 (展開後のバイト数)。
+    pub span: u64,
+    /// 展開後の全体のバイト数と改行の数。
+    pub total_out: u64,
+    pub total_lines: u64,
+    /// `out` の昇順に並んだチェックポイント。
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+impl Index {
+    /// 展開後の位置 `out` に最も近い、その手前のチェックポイント。
+    pub fn checkpoint_before(&self, out: u64) -> Option<&Checkpoint> {
+        let i = self.checkpoints.partition_point(|cp| cp.out <= out);
+        i.checked_sub(1).map(|i| &self.checkpoints[i])
+    }
+
+    /// 1から数えた `line` 行目の先頭に最も近い、その手前のチェックポイント。
+    /// チェックポイントはブロックの境界にあり、たいてい `cp.lines + 1` 行目の途中なので、`line` 行目の中にあるものは返さない。
+    pub fn checkpoint_before_line(&self, line: u64) -> Option<&Checkpoint> {
+        let i = self.checkpoints.partition_point(|cp| cp.lines + 1 < line);
+        i.checked_sub(1).map(|i| &self.checkpoints[i])
+    }
+}
+
+/// gzipファイルの索引ファイルのパス (gzip ファイルの隣に置く)。
+pub fn index_path(filename: &str) -> String {
+    format!("{}.gzidx", filename)
+}
+
+// gzip ファイルのサイズと更新時刻 (秒)。
+fn file_stamp(filename: &str) -> io::Result<(u64, u64)> {
+    let meta = fs::metadata(filename)?;
+    let mtime = meta.modified()?.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    Ok((meta.len(), mtime))
+}
+
+fn write_u64<W: Write>(w: &mut W, value: u64) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+// 索引をファイルに書き出す。一時ファイルに書いてから付け替えるので、途中で止まっても壊れた索引は残らない。
+fn save(index: &Index, path: &str) -> Result<(), Box<dyn Error>> {
+    let mut file = AtomicFile::create(Path::new(path), OutputOptions { force: true, fsync: false })?;
+    write_index(&mut file, index).map_err(|err| format!("Cannot write file '{}', Error: {}", path, err))?;
+    file.commit()
+}
+
+// 索引を書き出す。ウィンドウは raw deflate で圧縮して入れる。
+fn write_index<W: Write>(mut w: W, index: &Index) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    for value in [index.gz_len, index.gz_mtime, index.span, index.total_out, index.total_lines] {
+        write_u64(&mut w, value)?;
+    }
+    write_u64(&mut w, index.checkpoints.len() as u64)?;
+    for cp in &index.checkpoints {
+        for value in [cp.out, cp.input, cp.member, cp.lines] {
+            write_u64(&mut w, value)?;
+        }
+        w.write_all(&[cp.bits])?;
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&cp.window)?;
+        let window = encoder.finish()?;
+        write_u64(&mut w, cp.window.len() as u64)?;
+        write_u64(&mut w, window.len() as u64)?;
+        w.write_all(&window)?;
+    }
+    Ok(())
+}
+
+fn read_index<R: Read>(r: &mut R) -> io::Result<Index> {
+    let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a gzip_test index file"));
+    }
+    let mut index = Index {
+        gz_len: read_u64(r)?,
+        gz_mtime: read_u64(r)?,
+        span: read_u64(r)?,
+        total_out: read_u64(r)?,
+        total_lines: read_u64(r)?,
+        checkpoints: Vec::new(),
+    };
+    let count = read_u64(r)?;
+    for _ in 0..count {
+        let (out, input, member, lines) = (read_u64(r)?, read_u64(r)?, read_u64(r)?, read_u64(r)?);
+        let mut bits = [0u8; 1];
+        r.read_exact(&mut bits)?;
+        let window_len = read_u64(r)?;
+        let compressed_len = read_u64(r)?;
+        if bits[0] > 7 || window_len > crate::inflate::WINDOW_SIZE as u64 {
+            return Err(invalid("corrupt checkpoint"));
+        }
+        let mut window = Vec::with_capacity(window_len as usize);
+        // 小さな圧縮データが大きく展開されることもあるので、ウィンドウより長い分は読まずに誤りにする。
+        DeflateDecoder::new(r.by_ref().take(compressed_len)).take(crate::inflate::WINDOW_SIZE as u64 + 1).read_to_end(&mut window)?;
+        if window.len() as u64 != window_len {
+            return Err(invalid("corrupt checkpoint window"));
+        }
+        index.checkpoints.push(Checkpoint { out, input, bits: bits[0], member, lines, window });
+    }
+    Ok(index)
+}
+
+/// 索引を作った後に gzip ファイルが変わっていた時の、[`load`] の扱い。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IfStale {
+    /// エラーにする。
+    Fail,
+    /// 警告を表示し、索引がない時と同じく `None` を返す。索引がなくても最初から展開すれば済む時に使う。
+    Ignore,
+}
+
+/// gzip ファイルの隣にある索引を読み込む。索引がなければ `None` を返す。
+///
+/// 索引が壊れている時はエラーにする。索引を作った後に gzip ファイルが変わっている時は `if_stale` に従う。
+pub fn load(filename: &str, if_stale: IfStale) -> Result<Option<Index>, Box<dyn Error>> {
+    if filename == "-" {
+        return Ok(None);
+    }
+    let path = index_path(filename);
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("Cannot open file '{}', Error: {}", path, err).into()),
+    };
+    let bad_index = |message: String| Failed { code: EXIT_BAD_INDEX, message };
+    let index = read_index(&mut BufReader::new(file))
+        .map_err(|err| bad_index(format!("invalid index file '{}': {}", path, err)))?;
+    let stamp = file_stamp(filename).map_err(|source| GzTestError::Open {
+        at: Location { file: filename.to_string(), member: 0, offset: 0 },
+        source,
+    })?;
+    if stamp != (index.gz_len, index.gz_mtime) {
+        let message = format!("index file '{}' is out of date; rebuild it with 'gzip_test index {}'", path, filename);
+        if if_stale == IfStale::Ignore {
+            eprintln!("gzip_test: warning: {}; decoding from the beginning", message);
+            return Ok(None);
+        }
+        return Err(Box::new(bad_index(message)));
+    }
+    Ok(Some(index))
+}
+
+// Inflating::step が返す、展開したデータの後ろの状態。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Event {
+    Data,
+    // deflate のブロックの境界 (またはメンバーの圧縮データの先頭)。
+    Boundary,
+    End,
+}
+
+// メンバーをまたいで展開を続ける。索引を作る時と、索引から読む時に使う。
+struct Inflating<R> {
+    input: BitReader<R>,
+    inflater: Inflater,
+    name: String,
+    member: u64,
+    in_member: bool,
+    // メンバーの先頭から展開している時だけ CRC32 と ISIZE を確かめられる。
+    verify: bool,
+    crc: Crc,
+    member_out: u64,
+}
+
+impl<R: BufRead> Inflating<R> {
+    // input の先頭 (offset バイト目) から、メンバーのヘッダーを読むところから始める。
+    fn new(input: R, name: &str, offset: u64) -> Self {
+        Inflating {
+            input: BitReader::new(input, offset),
+            inflater: Inflater::new(),
+            name: name.to_string(),
+            member: 0,
+            in_member: false,
+            verify: true,
+            crc: Crc::new(),
+            member_out: 0,
+        }
+    }
+
+    // チェックポイントから展開を再開する。input はチェックポイントのバイトから始まっている。
+    fn resume(input: R, name: &str, cp: &Checkpoint) -> Result<Self, GzTestError> {
+        let mut stream = Inflating::new(input, name, cp.input);
+        stream.member = cp.member;
+        stream.in_member = true;
+        stream.verify = false;
+        stream.inflater = Inflater::with_window(&cp.window);
+        stream.input.bits(u32::from(cp.bits)).map_err(|err| stream.inflate_error(err))?;
+        Ok(stream)
+    }
+
+    fn into_inner(self) -> R {
+        self.input.into_inner()
+    }
+
+    fn location(&self) -> Location {
+        self.location_at(self.input.bit_position() / 8)
+    }
+
+    fn location_at(&self, offset: u64) -> Location {
+        Location { file: self.name.clone(), member: self.member, offset }
+    }
+
+    fn read_error(&self, source: io::Error) -> GzTestError {
+        if source.kind() == io::ErrorKind::UnexpectedEof {
+            GzTestError::Truncated { at: self.location() }
+        } else {
+            GzTestError::Read { at: self.location(), source }
+        }
+    }
+
+    fn inflate_error(&self, err: InflateError) -> GzTestError {
+        match err {
+            InflateError::Eof => GzTestError::Truncated { at: self.location() },
+            InflateError::Invalid(reason) => GzTestError::Corrupt { at: self.location(), reason: reason.to_string() },
+            InflateError::Io(source) => self.read_error(source),
+        }
+    }
+
+    // 次のメンバーのヘッダーを読む。入力が終わっていれば false を返す。
+    fn start_member(&mut self) -> Result<bool, GzTestError> {
+        let start = self.input.bit_position() / 8;
+        let first = match self.input.fill_buf() {
+            Ok(buf) => buf.first().copied(),
+            Err(err) => return Err(self.read_error(err)),
+        };
+        match first {
+            None if self.member == 0 => return Err(GzTestError::Truncated { at: self.location() }),
+            None => return Ok(false),
+            Some(byte) if byte != ID1 && self.member > 0 => {
+                return Err(GzTestError::TrailingGarbage { at: self.location() });
+            }
+            Some(_) => {}
+        }
+        match header::read_header(&mut self.input) {
+            Ok(_) => {}
+            Err(HeaderError::Eof) => return Err(GzTestError::Truncated { at: self.location() }),
+            Err(HeaderError::NotGzip) if self.member > 0 => {
+                return Err(GzTestError::TrailingGarbage { at: self.location_at(start) });
+            }
+            Err(HeaderError::NotGzip) => {
+                return Err(GzTestError::Header { at: self.location_at(start), reason: "not in gzip format" });
+            }
+            Err(HeaderError::Invalid(reason)) => {
+                return Err(GzTestError::Header { at: self.location_at(start), reason });
+            }
+            Err(HeaderError::Io(err)) => return Err(self.read_error(err)),
+        }
+        self.inflater = Inflater::new();
+        self.in_member = true;
+        self.verify = true;
+        self.crc.reset();
+        self.member_out = 0;
+        Ok(true)
+    }
+
+    // トレーラーを読み、メンバーの先頭から展開していれば CRC32 と ISIZE を確かめる。
+    fn finish_member(&mut self) -> Result<(), GzTestError> {
+        self.input.align();
+        let at = self.location();
+        let trailer = header::read_trailer(&mut self.input).map_err(|err| self.read_error(err))?;
+        if self.verify {
+            if trailer.crc32 != self.crc.sum() {
+                return Err(GzTestError::Crc { at, expected: trailer.crc32, actual: self.crc.sum() });
+            }
+            if trailer.isize != self.member_out as u32 {
+                return Err(GzTestError::Isize { at, expected: trailer.isize, actual: self.member_out as u32 });
+            }
+        }
+        self.member += 1;
+        self.in_member = false;
+        Ok(())
+    }
+
+    // 展開を少し進め、その間に展開したデータを返す。
+    fn step(&mut self) -> Result<(Event, &[u8]), GzTestError> {
+        if !self.in_member {
+            let event = if self.start_member()? { Event::Boundary } else { Event::End };
+            return Ok((event, &[]));
+        }
+        let progress = self.inflater.inflate(&mut self.input).map_err(|err| self.inflate_error(err))?;
+        let data = self.inflater.output();
+        self.crc.update(data);
+        self.member_out += data.len() as u64;
+        let event = match progress {
+            Progress::Output => Event::Data,
+            Progress::BlockEnd => Event::Boundary,
+            Progress::StreamEnd => {
+                self.finish_member()?;
+                Event::Data
+            }
+        };
+        Ok((event, self.inflater.output()))
+    }
+}
+
+/// gzip ファイルを最後まで展開し、展開後の `span` バイトごとにチェックポイントを記録した索引を作る。
+/// 展開しながら各メンバーの CRC32 と ISIZE も確かめる。
+pub fn build(filename: &str, span: u64) -> Result<Index, Box<dyn Error>> {
+    let (gz_len, gz_mtime) = file_stamp(filename).map_err(|source| GzTestError::Open {
+        at: Location { file: filename.to_string(), member: 0, offset: 0 },
+        source,
+    })?;
+    let input = BufReader::with_capacity(64 * 1024, open_input(filename)?);
+    let mut stream = Inflating::new(input, filename, 0);
+    let mut index = Index { gz_len, gz_mtime, span, ..Index::default() };
+    let mut last = 0;
+    loop {
+        let (event, data) = stream.step()?;
+        index.total_out += data.len() as u64;
+        index.total_lines += data.iter().filter(|&&b| b == b'\n').count() as u64;
+        match event {
+            Event::Boundary if index.total_out - last >= span => {
+                let position = stream.input.bit_position();
+                index.checkpoints.push(Checkpoint {
+                    out: index.total_out,
+                    input: position / 8,
+                    bits: (position % 8) as u8,
+                    member: stream.member,
+                    lines: index.total_lines,
+                    window: stream.inflater.window().to_vec(),
+                });
+                last = index.total_out;
+            }
+            Event::End => return Ok(index),
+            _ => {}
+        }
+    }
+}
+
+/// 索引を使って、展開後のデータの好きな位置から読める `BufRead + Seek`。
+///
+/// 読む位置の手前のチェックポイントから展開を再開するので、先頭から展開し直すのはそのチェックポイントがない時だけ。
+/// 索引がなくても使えるが、後ろに戻るたびに先頭から展開し直す。
+/// チェックポイントから読み始めたメンバーの CRC32 と ISIZE は確かめない。
+pub struct IndexedReader<R> {
+    // エラーの後は None になり、それ以上読めない。
+    stream: Option<Inflating<BufReader<R>>>,
+    index: Option<Index>,
+    name: String,
+    // 最後に展開したデータと、その先頭の展開後の位置。
+    buf: Vec<u8>,
+    buf_start: u64,
+    position: u64,
+}
+
+impl<R: Read + Seek> IndexedReader<R> {
+    /// `input` は gzip ファイルの先頭を指していること。
+    pub fn new(input: R, name: &str, index: Option<Index>) -> Self {
+        IndexedReader {
+            stream: Some(Inflating::new(BufReader::new(input), name, 0)),
+            index,
+            name: name.to_string(),
+            buf: Vec::new(),
+            buf_start: 0,
+            position: 0,
+        }
+    }
+
+    // チェックポイント (なければファイルの先頭) から展開をやり直す。
+    fn restart(&mut self, cp: Option<Checkpoint>) -> io::Result<()> {
+        let stream = self.stream.take().ok_or_else(stopped)?;
+        let mut input = stream.into_inner().into_inner();
+        input.seek(SeekFrom::Start(cp.as_ref().map_or(0, |cp| cp.input)))?;
+        let input = BufReader::new(input);
+        let stream = match &cp {
+            Some(cp) => Inflating::resume(input, &self.name, cp)?,
+            None => Inflating::new(input, &self.name, 0),
+        };
+        self.stream = Some(stream);
+        self.buf.clear();
+        self.buf_start = cp.map_or(0, |cp| cp.out);
+        Ok(())
+    }
+
+    /// 1から数えた `line` 行目の先頭に移る。ファイルの行数より大きければ最後に移る。
+    pub fn seek_line(&mut self, line: u64) -> io::Result<()> {
+        let cp = self.index.as_ref().and_then(|index| index.checkpoint_before_line(line));
+        // チェックポイントから始める時は cp.lines + 1 行目の途中にいるので、次の改行の後から数える。
+        let (out, mut current) = cp.map_or((0, 1), |cp| (cp.out, cp.lines + 1));
+        self.position = out;
+        while current < line {
+            let buf = self.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            match buf.iter().position(|&b| b == b'\n') {
+                Some(i) => {
+                    self.consume(i + 1);
+                    current += 1;
+                }
+                None => {
+                    let len = buf.len();
+                    self.consume(len);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Locate for IndexedReader<R> {
+    /// 展開している入力の位置。エラーで止まった後はファイルの先頭を返す。
+    fn location(&self) -> Location {
+        match &self.stream {
+            Some(stream) => stream.location(),
+            None => Location { file: self.name.clone(), member: 0, offset: 0 },
+        }
+    }
+}
+
+fn stopped() -> io::Error {
+    io::Error::other("cannot read after an earlier error")
+}
+
+impl<R: Read + Seek> BufRead for IndexedReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        loop {
+            let end = self.buf_start + self.buf.len() as u64;
+            if self.position >= self.buf_start && self.position < end {
+                break;
+            }
+            // 展開済みの位置より前に戻る時や、読む位置の手前にもっと近いチェックポイントがある時はやり直す。
+            let cp = self.index.as_ref().and_then(|index| index.checkpoint_before(self.position));
+            if self.position < self.buf_start || cp.is_some_and(|cp| cp.out > end) {
+                let cp = cp.cloned();
+                self.restart(cp)?;
+                continue;
+            }
+            let stream = self.stream.as_mut().ok_or_else(stopped)?;
+            match stream.step() {
+                Ok((Event::End, _)) => break,
+                Ok((_, data)) => {
+                    self.buf.clear();
+                    self.buf.extend_from_slice(data);
+                    self.buf_start = end;
+                }
+                Err(err) => {
+                    self.stream = None;
+                    return Err(err.into());
+                }
+            }
+        }
+        let start = (self.position.saturating_sub(self.buf_start) as usize).min(self.buf.len());
+        Ok(&self.buf[start..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position += amt as u64;
+    }
+}
+
+impl<R: Read + Seek> Read for IndexedReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let buf = self.fill_buf()?;
+        let n = buf.len().min(out.len());
+        out[..n].copy_from_slice(&buf[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for IndexedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(delta) => match &self.index {
+                Some(index) => index.total_out.checked_add_signed(delta),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "the uncompressed size is not known without an index",
+                    ))
+                }
+            },
+        };
+        self.position = target
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position"))?;
+        Ok(self.position)
+    }
+}
+
+/// `index` コマンド: gzip ファイルを展開してチェックポイントを記録し、ファイルの隣に索引を書き出す。
+pub fn index(args: &IndexArgs) -> Result<(), Box<dyn Error>> {
+    for filename in &args.inputs {
+        let index = build(filename, args.span)?;
+        let path = index_path(filename);
+        save(&index, &path)?;
+        println!(
+            "{}: {} checkpoint(s), {} bytes, {} lines -> {}",
+            filename,
+            index.checkpoints.len(),
+            index.total_out,
+            index.total_lines,
+            path
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use flate2::write::GzEncoder;
+
+    use super::*;
+
+    // テストごとの gzip ファイル。落とす時に索引ごと消す。
+    struct Scratch {
+        path: PathBuf,
+    }
+
+    impl Scratch {
+        fn new(data: &[u8]) -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+            let path = std::env::temp_dir().join(format!("gzip_test-index-{}-{}.gz", std::process::id(), n));
+            fs::write(&path, data).unwrap();
+            Scratch { path }
+        }
+
+        fn name(&self) -> &str {
+            self.path.to_str().unwrap()
+        }
+
+        fn index_path(&self) -> String {
+            index_path(self.name())
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+            let _ = fs::remove_file(self.index_path());
+        }
+    }
+
+    fn text() -> Vec<u8> {
+        (0..60_000).map(|i| format!("line {} {}\n", i, "x".repeat(i % 40))).collect::<String>().into_bytes()
+    }
+
+    // テキストを3つのメンバーに分けて圧縮する。
+    fn gzip_members(data: &[u8]) -> Vec<u8> {
+        let third = data.len() / 3;
+        [&data[..third], &data[third..2 * third], &data[2 * third..]]
+            .iter()
+            .flat_map(|part| {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(part).unwrap();
+                encoder.finish().unwrap()
+            })
+            .collect()
+    }
+
+    // 索引を作って書き出す。
+    fn indexed() -> (Vec<u8>, Scratch) {
+        let data = text();
+        let file = Scratch::new(&gzip_members(&data));
+        save(&build(file.name(), 64 * 1024).unwrap(), &file.index_path()).unwrap();
+        (data, file)
+    }
+
+    fn reader(file: &Scratch, index: Option<Index>) -> IndexedReader<File> {
+        IndexedReader::new(File::open(&file.path).unwrap(), file.name(), index)
+    }
+
+    fn exit_code(err: &(dyn Error + 'static)) -> Option<u8> {
+        err.downcast_ref::<Failed>().map(|failed| failed.code)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (data, file) = indexed();
+        let built = build(file.name(), 64 * 1024).unwrap();
+        let loaded = load(file.name(), IfStale::Fail).unwrap().unwrap();
+        assert_eq!(loaded.total_out, data.len() as u64);
+        assert_eq!(loaded.total_lines, 60_000);
+        assert_eq!((loaded.gz_len, loaded.gz_mtime, loaded.span), (built.gz_len, built.gz_mtime, 64 * 1024));
+        assert_eq!(loaded.checkpoints.len(), built.checkpoints.len());
+        assert!(loaded.checkpoints.iter().any(|cp| cp.member == 2), "{} checkpoints", loaded.checkpoints.len());
+        for (loaded, built) in loaded.checkpoints.iter().zip(&built.checkpoints) {
+            assert_eq!(
+                (loaded.out, loaded.input, loaded.bits, loaded.member, loaded.lines),
+                (built.out, built.input, built.bits, built.member, built.lines)
+            );
+            assert!(loaded.window == built.window);
+            let out = loaded.out as usize;
+            assert!(loaded.window[..] == data[out - loaded.window.len()..out]);
+            assert_eq!(loaded.lines, data[..out].iter().filter(|&&b| b == b'\n').count() as u64);
+        }
+    }
+
+    #[test]
+    fn missing_index() {
+        let file = Scratch::new(&gzip_members(b"a\nb\n"));
+        assert!(load(file.name(), IfStale::Fail).unwrap().is_none());
+        assert!(load("-", IfStale::Fail).unwrap().is_none());
+    }
+
+    #[test]
+    fn seek_line_with_and_without_index() {
+        let (data, file) = indexed();
+        let lines: Vec<&[u8]> = data.split_inclusive(|&b| b == b'\n').collect();
+        let mut with_index = reader(&file, load(file.name(), IfStale::Fail).unwrap());
+        let mut without = reader(&file, None);
+        // 後ろに戻る移動も混ぜる。
+        for line in [1, 59_999, 60_000, 2, 30_001, 12_345, 45_000, 1] {
+            for reader in [&mut with_index, &mut without] {
+                reader.seek_line(line).unwrap();
+                let mut text = Vec::new();
+                reader.read_until(b'\n', &mut text).unwrap();
+                assert!(text == lines[line as usize - 1], "line {}", line);
+            }
+        }
+        with_index.seek_line(60_001).unwrap();
+        assert!(with_index.fill_buf().unwrap().is_empty());
+    }
+
+    // チェックポイントはたいてい行の途中にあるので、チェックポイントの中の行や直後の行の先頭にも移れることを確かめる。
+    #[test]
+    fn seek_line_next_to_every_checkpoint() {
+        let (data, file) = indexed();
+        let lines: Vec<&[u8]> = data.split_inclusive(|&b| b == b'\n').collect();
+        let index = load(file.name(), IfStale::Fail).unwrap().unwrap();
+        assert!(index.checkpoints.len() > 1, "{}", index.checkpoints.len());
+        let mut reader = reader(&file, Some(index.clone()));
+        for cp in &index.checkpoints {
+            for line in [cp.lines, cp.lines + 1, cp.lines + 2].into_iter().filter(|&line| line >= 1 && line <= lines.len() as u64) {
+                reader.seek_line(line).unwrap();
+                let mut text = Vec::new();
+                reader.read_until(b'\n', &mut text).unwrap();
+                assert!(text == lines[line as usize - 1], "line {} (checkpoint at {} lines)", line, cp.lines);
+            }
+        }
+    }
+
+    #[test]
+    fn seek_bytes() {
+        let (data, file) = indexed();
+        let mut reader = reader(&file, load(file.name(), IfStale::Fail).unwrap());
+        for offset in [0, 700_000, 5, 123_456, data.len() as u64 - 10] {
+            reader.seek(SeekFrom::Start(offset)).unwrap();
+            let mut buf = [0u8; 100];
+            let n = reader.read(&mut buf).unwrap();
+            assert!(n > 0 && buf[..n] == data[offset as usize..offset as usize + n], "offset {}", offset);
+        }
+        assert_eq!(reader.seek(SeekFrom::End(-3)).unwrap(), data.len() as u64 - 3);
+        let mut tail = Vec::new();
+        reader.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, &data[data.len() - 3..]);
+    }
+
+    // 小さな圧縮データがウィンドウより大きく展開される索引。
+    #[test]
+    fn oversized_window() {
+        let mut data = MAGIC.to_vec();
+        for value in [0, 0, 0, 0, 0, 1, 0, 0, 0, 0] {
+            data.extend_from_slice(&u64::to_le_bytes(value));
+        }
+        data.push(0);
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![0; 64 * 1024 * 1024]).unwrap();
+        let window = encoder.finish().unwrap();
+        for value in [crate::inflate::WINDOW_SIZE as u64, window.len() as u64] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&window);
+        let err = read_index(&mut &data[..]).unwrap_err();
+        assert_eq!(err.to_string(), "corrupt checkpoint window");
+    }
+
+    #[test]
+    fn corrupt_index() {
+        let (_, file) = indexed();
+        let good = fs::read(file.index_path()).unwrap();
+        // 識別子の後に 6 個の u64、最初のチェックポイントは 4 個の u64 の後に bits、ウィンドウの長さが続く。
+        let bits = 8 + 6 * 8 + 4 * 8;
+        let window_len = bits + 1;
+        let mut cases: Vec<Vec<u8>> = vec![b"GZTIDX00".to_vec(), Vec::new()];
+        for len in [4, 40, bits, window_len + 3, good.len() - 1] {
+            cases.push(good[..len].to_vec());
+        }
+        let mut bad_bits = good.clone();
+        bad_bits[bits] = 8;
+        cases.push(bad_bits);
+        let mut bad_window = good.clone();
+        bad_window[window_len] ^= 1;
+        cases.push(bad_window);
+        for (i, corrupt) in cases.iter().enumerate() {
+            fs::write(file.index_path(), corrupt).unwrap();
+            let err = load(file.name(), IfStale::Fail).unwrap_err();
+            assert_eq!(exit_code(err.as_ref()), Some(EXIT_BAD_INDEX), "case {}: {}", i, err);
+            assert!(err.to_string().starts_with("invalid index file"), "case {}: {}", i, err);
+            // 壊れた索引は、変わっていた時に無視する場合もエラーにする。
+            assert!(load(file.name(), IfStale::Ignore).is_err(), "case {}", i);
+        }
+    }
+
+    #[test]
+    fn stale_index() {
+        let (data, file) = indexed();
+        let mut changed = gzip_members(&data);
+        changed.extend(gzip_members(b"more\n"));
+        fs::write(&file.path, changed).unwrap();
+        let err = load(file.name(), IfStale::Fail).unwrap_err();
+        assert_eq!(exit_code(err.as_ref()), Some(EXIT_BAD_INDEX));
+        assert!(err.to_string().contains("is out of date"), "{}", err);
+        assert!(load(file.name(), IfStale::Ignore).unwrap().is_none());
+    }
+}
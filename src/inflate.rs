@@ -0,0 +1,577 @@
+use std::io::{self, BufRead, Read};
+
+/// 後方参照で使える距離の上限。展開を途中から再開するにはこの長さの直前のデータが要る。
+pub const WINDOW_SIZE: usize = 32 * 1024;
+
+// inflate の1回の呼び出しで返す展開後のデータの目安。
+const OUTPUT_CHUNK: usize = 32 * 1024;
+
+// Huffman 符号の最大の長さ。
+const MAX_BITS: u32 = 15;
+
+// RFC 1951 3.2.5 の長さと距離の基数と追加ビット数。
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// 動的 Huffman ブロックで符号長の符号の長さが並ぶ順番。
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// 圧縮データの展開に失敗した理由。
+#[derive(Debug)]
+pub enum InflateError {
+    /// 圧縮データの途中で入力が終わった。
+    Eof,
+    /// 圧縮データが RFC 1951 に合わない。
+    Invalid(&'static str),
+    Io(io::Error),
+}
+
+impl From<io::Error> for InflateError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            InflateError::Eof
+        } else {
+            InflateError::Io(err)
+        }
+    }
+}
+
+/// 圧縮データをビット単位で読む。deflate のビット列は各バイトの下位ビットから詰められている。
+///
+/// バイト境界にある時 (ブロックの外やトレーラーの前) は `BufRead` としても読める。
+/// 先読みしたバイトも取りこぼさないので、圧縮データに続くトレーラーや次のヘッダーをそのまま読める。
+pub struct BitReader<R> {
+    inner: R,
+    bits: u64,
+    count: u32,
+    // inner から読み取ったバイト数 (bits に残っている分を含む)。
+    position: u64,
+    scratch: [u8; 8],
+}
+
+impl<R: BufRead> BitReader<R> {
+    /// `position` は `inner` の先頭の、元のファイル上のバイト位置。
+    pub fn new(inner: R, position: u64) -> Self {
+        BitReader { inner, bits: 0, count: 0, position, scratch: [0; 8] }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// 次に読むビットの、ファイルの先頭からのビット位置。
+    pub fn bit_position(&self) -> u64 {
+        self.position * 8 - u64::from(self.count)
+    }
+
+    // 少なくとも n ビットを bits に読み込む。入力が先に終わった時は false を返す。
+    // 必要な分しか読まないので、bits に残るのは n + 7 ビットまで。
+    fn fill(&mut self, n: u32) -> io::Result<bool> {
+        while self.count < n {
+            let buf = self.inner.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(false);
+            }
+            let take = ((n - self.count).div_ceil(8) as usize).min(buf.len());
+            for &byte in &buf[..take] {
+                self.bits |= u64::from(byte) << self.count;
+                self.count += 8;
+            }
+            self.inner.consume(take);
+            self.position += take as u64;
+        }
+        Ok(true)
+    }
+
+    /// n ビット (16 以下) を読み、下位ビットから詰めた値を返す。
+    pub fn bits(&mut self, n: u32) -> Result<u32, InflateError> {
+        if !self.fill(n)? {
+            return Err(InflateError::Eof);
+        }
+        let value = (self.bits & ((1 << n) - 1)) as u32;
+        self.bits >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    // 読み進めずに最大 n ビットを見る。入力の終わりでは足りない分を0で埋め、実際に読めたビット数も返す。
+    fn peek(&mut self, n: u32) -> io::Result<(u32, u32)> {
+        self.fill(n)?;
+        Ok(((self.bits & ((1 << n) - 1)) as u32, self.count.min(n)))
+    }
+
+    fn drop_bits(&mut self, n: u32) {
+        self.bits >>= n;
+        self.count -= n;
+    }
+
+    /// 次のバイト境界まで読み飛ばす。
+    pub fn align(&mut self) {
+        let rest = self.count % 8;
+        self.drop_bits(rest);
+    }
+}
+
+impl<R: BufRead> Read for BitReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+// バイト境界にある時だけ使う。
+impl<R: BufRead> BufRead for BitReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        debug_assert_eq!(self.count % 8, 0);
+        if self.count > 0 {
+            self.scratch = self.bits.to_le_bytes();
+            return Ok(&self.scratch[..(self.count / 8) as usize]);
+        }
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.count > 0 {
+            self.drop_bits(amt as u32 * 8);
+        } else {
+            self.inner.consume(amt);
+            self.position += amt as u64;
+        }
+    }
+}
+
+// 正準 Huffman 符号の復号表。最も長い符号の長さのビットを添字にして、記号と符号の長さを引く。
+struct Huffman {
+    table: Vec<u16>,
+    max_len: u32,
+}
+
+impl Huffman {
+    // 各記号の符号の長さ (0 は使われない記号) から表を作る。
+    fn new(lengths: &[u8]) -> Result<Self, InflateError> {
+        let mut count = [0u16; MAX_BITS as usize + 1];
+        for &len in lengths {
+            count[len as usize] += 1;
+        }
+        count[0] = 0;
+        // 符号の数が長さに対して多すぎないかを確かめる。足りない (不完全な) 符号は使われない限り許す。
+        let mut left = 1i32;
+        for &n in &count[1..] {
+            left = (left << 1) - i32::from(n);
+            if left < 0 {
+                return Err(InflateError::Invalid("over-subscribed Huffman code"));
+            }
+        }
+        let max_len = (1..=MAX_BITS).rev().find(|&len| count[len as usize] > 0).unwrap_or(0);
+        let mut next = [0u32; MAX_BITS as usize + 1];
+        let mut code = 0;
+        for len in 1..=MAX_BITS as usize {
+            code = (code + u32::from(count[len - 1])) << 1;
+            next[len] = code;
+        }
+        let mut table = vec![0u16; 1 << max_len];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len == 0 {
+                continue;
+            }
+            let len = u32::from(len);
+            let code = next[len as usize];
+            next[len as usize] += 1;
+            // 符号は上位ビットから詰められているので、ビットを逆順にして添字にする。
+            let mut i = (code.reverse_bits() >> (32 - len)) as usize;
+            let entry = (symbol as u16) << 4 | len as u16;
+            while i < table.len() {
+                table[i] = entry;
+                i += 1 << len;
+            }
+        }
+        Ok(Huffman { table, max_len })
+    }
+
+    fn decode<R: BufRead>(&self, input: &mut BitReader<R>) -> Result<u16, InflateError> {
+        let (index, available) = input.peek(self.max_len)?;
+        let entry = self.table[index as usize];
+        let len = u32::from(entry & 0xf);
+        if len == 0 && available == self.max_len {
+            return Err(InflateError::Invalid("invalid Huffman code"));
+        }
+        if len == 0 || len > available {
+            return Err(InflateError::Eof);
+        }
+        input.drop_bits(len);
+        Ok(entry >> 4)
+    }
+}
+
+enum Block {
+    // 次のブロックのヘッダーを読む。
+    Header,
+    // 非圧縮ブロックの残りのバイト数。
+    Stored(usize),
+    Codes { literal: Huffman, distance: Huffman },
+    Done,
+}
+
+/// [`Inflater::inflate`] が戻った理由。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    /// ブロックの途中で、ある程度のデータを展開した。
+    Output,
+    /// ブロックの終わりまで展開した。ここから先は直前のウィンドウだけで展開を再開できる。
+    BlockEnd,
+    /// 最後のブロックの終わりまで展開した。
+    StreamEnd,
+}
+
+/// ブロックの境界で止まれる raw deflate の展開器。
+///
+/// `flate2::Decompress` はビット単位の位置を教えず、途中から展開を再開することもできないので、
+/// ランダムアクセスの索引を作る時と索引から読む時にはこちらを使う。
+pub struct Inflater {
+    // 展開したデータ。後方参照のために少なくとも直前のウィンドウ分は残しておく。
+    out: Vec<u8>,
+    // out のうち直前の inflate() で展開した部分の先頭。
+    pending: usize,
+    block: Block,
+    last: bool,
+}
+
+impl Inflater {
+    pub fn new() -> Self {
+        Inflater::with_window(&[])
+    }
+
+    /// ブロックの境界から展開を再開する。`window` はその位置までに展開したデータの末尾 (最大 [`WINDOW_SIZE`] バイト)。
+    pub fn with_window(window: &[u8]) -> Self {
+        Inflater { out: window.to_vec(), pending: window.len(), block: Block::Header, last: false }
+    }
+
+    /// 展開したデータの末尾 (最大 [`WINDOW_SIZE`] バイト)。
+    pub fn window(&self) -> &[u8] {
+        &self.out[self.out.len().saturating_sub(WINDOW_SIZE)..]
+    }
+
+    /// 直前の [`Inflater::inflate`] で展開したデータ。
+    pub fn output(&self) -> &[u8] {
+        &self.out[self.pending..]
+    }
+
+    /// データを展開する。展開したデータは次の呼び出しまで [`Inflater::output`] で読める。
+    pub fn inflate<R: BufRead>(&mut self, input: &mut BitReader<R>) -> Result<Progress, InflateError> {
+        // 前回のデータは返し終えたので、後方参照に使うウィンドウ分を残して捨てる。
+        if self.out.len() > 4 * WINDOW_SIZE {
+            self.out.drain(..self.out.len() - WINDOW_SIZE);
+        }
+        self.pending = self.out.len();
+        loop {
+            match &mut self.block {
+                Block::Header => self.read_block_header(input)?,
+                Block::Stored(remaining) => {
+                    if *remaining == 0 {
+                        return Ok(self.end_block());
+                    }
+                    if self.out.len() - self.pending >= OUTPUT_CHUNK {
+                        return Ok(Progress::Output);
+                    }
+                    let buf = input.fill_buf()?;
+                    if buf.is_empty() {
+                        return Err(InflateError::Eof);
+                    }
+                    let n = buf.len().min(*remaining);
+                    self.out.extend_from_slice(&buf[..n]);
+                    input.consume(n);
+                    *remaining -= n;
+                }
+                Block::Codes { literal, distance } => {
+                    if self.out.len() - self.pending >= OUTPUT_CHUNK {
+                        return Ok(Progress::Output);
+                    }
+                    if !inflate_codes(&mut self.out, self.pending + OUTPUT_CHUNK, literal, distance, input)? {
+                        return Ok(self.end_block());
+                    }
+                }
+                Block::Done => return Ok(Progress::StreamEnd),
+            }
+        }
+    }
+
+    fn end_block(&mut self) -> Progress {
+        if self.last {
+            self.block = Block::Done;
+            Progress::StreamEnd
+        } else {
+            self.block = Block::Header;
+            Progress::BlockEnd
+        }
+    }
+
+    fn read_block_header<R: BufRead>(&mut self, input: &mut BitReader<R>) -> Result<(), InflateError> {
+        self.last = input.bits(1)? == 1;
+        self.block = match input.bits(2)? {
+            0 => {
+                input.align();
+                let len = input.bits(16)?;
+                let nlen = input.bits(16)?;
+                if len != !nlen & 0xffff {
+                    return Err(InflateError::Invalid("invalid stored block lengths"));
+                }
+                Block::Stored(len as usize)
+            }
+            1 => {
+                let mut lengths = [0u8; 288 + 32];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..288].fill(8);
+                lengths[288..].fill(5);
+                Block::Codes { literal: Huffman::new(&lengths[..288])?, distance: Huffman::new(&lengths[288..])? }
+            }
+            2 => read_dynamic_codes(input)?,
+            _ => return Err(InflateError::Invalid("invalid block type")),
+        };
+        Ok(())
+    }
+}
+
+// 動的 Huffman ブロックのヘッダーから符号を読む。
+fn read_dynamic_codes<R: BufRead>(input: &mut BitReader<R>) -> Result<Block, InflateError> {
+    let literals = input.bits(5)? as usize + 257;
+    let distances = input.bits(5)? as usize + 1;
+    let code_lengths = input.bits(4)? as usize + 4;
+    if literals > 286 || distances > 30 {
+        return Err(InflateError::Invalid("too many length or distance symbols"));
+    }
+    let mut lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[symbol] = input.bits(3)? as u8;
+    }
+    let code = Huffman::new(&lengths)?;
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (value, repeat) = match code.decode(input)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => match lengths.last() {
+                Some(&previous) => (previous, 3 + input.bits(2)?),
+                None => return Err(InflateError::Invalid("repeated length with no first length")),
+            },
+            17 => (0, 3 + input.bits(3)?),
+            _ => (0, 11 + input.bits(7)?),
+        };
+        if lengths.len() + repeat as usize > literals + distances {
+            return Err(InflateError::Invalid("too many code lengths"));
+        }
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths[256] == 0 {
+        return Err(InflateError::Invalid("missing end-of-block code"));
+    }
+    Ok(Block::Codes { literal: Huffman::new(&lengths[..literals])?, distance: Huffman::new(&lengths[literals..])? })
+}
+
+// out の長さが limit に達するか、ブロックが終わるまで展開する。ブロックが終わった時は false を返す。
+fn inflate_codes<R: BufRead>(
+    out: &mut Vec<u8>,
+    limit: usize,
+    literal: &Huffman,
+    distance: &Huffman,
+    input: &mut BitReader<R>,
+) -> Result<bool, InflateError> {
+    while out.len() < limit {
+        let symbol = literal.decode(input)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(false);
+        }
+        let i = symbol - 257;
+        if i >= LENGTH_BASE.len() {
+            return Err(InflateError::Invalid("invalid literal/length code"));
+        }
+        let len = usize::from(LENGTH_BASE[i]) + input.bits(u32::from(LENGTH_EXTRA[i]))? as usize;
+        let d = distance.decode(input)? as usize;
+        if d >= DISTANCE_BASE.len() {
+            return Err(InflateError::Invalid("invalid distance code"));
+        }
+        let dist = usize::from(DISTANCE_BASE[d]) + input.bits(u32::from(DISTANCE_EXTRA[d]))? as usize;
+        if dist > out.len() {
+            return Err(InflateError::Invalid("invalid distance too far back"));
+        }
+        let start = out.len() - dist;
+        if dist >= len {
+            out.extend_from_within(start..start + len);
+        } else {
+            // 重なりのある参照は、書いたばかりのバイトを繰り返す。
+            for k in 0..len {
+                out.push(out[start + k]);
+            }
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use flate2::read::DeflateDecoder;
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    fn deflate(data: &[u8], level: u32) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::new(level));
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    // Inflater で最後まで展開する。
+    fn inflate_all(compressed: &[u8]) -> Result<Vec<u8>, InflateError> {
+        let mut input = BitReader::new(compressed, 0);
+        let mut inflater = Inflater::new();
+        let mut out = Vec::new();
+        loop {
+            let progress = inflater.inflate(&mut input)?;
+            out.extend_from_slice(inflater.output());
+            if progress == Progress::StreamEnd {
+                return Ok(out);
+            }
+        }
+    }
+
+    // 最初のブロックの種類 (0: 非圧縮, 1: 固定 Huffman, 2: 動的 Huffman)。
+    fn first_block_type(compressed: &[u8]) -> u8 {
+        (compressed[0] >> 1) & 3
+    }
+
+    // 再現できる疑似乱数のバイト列。
+    fn noise(len: usize, mut seed: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect()
+    }
+
+    fn text(lines: usize) -> Vec<u8> {
+        (0..lines).map(|i| format!("{} line {} of the sample text\n", i % 7, i)).collect::<String>().into_bytes()
+    }
+
+    fn samples() -> Vec<Vec<u8>> {
+        vec![Vec::new(), b"a".to_vec(), b"hello hello hello hello".to_vec(), text(20_000), noise(100_000, 1), vec![b'z'; 300_000]]
+    }
+
+    #[test]
+    fn matches_flate2_at_levels_0_1_9() {
+        for sample in samples() {
+            for level in [0, 1, 9] {
+                let compressed = deflate(&sample, level);
+                let mut expected = Vec::new();
+                DeflateDecoder::new(&compressed[..]).read_to_end(&mut expected).unwrap();
+                assert_eq!(expected, sample);
+                let actual = inflate_all(&compressed).unwrap();
+                assert!(actual == sample, "level {}, {} bytes", level, sample.len());
+            }
+        }
+    }
+
+    #[test]
+    fn stored_fixed_and_dynamic_blocks() {
+        let cases = [(0, b"stored block".to_vec(), 0), (6, b"hello hello hello hello".to_vec(), 1), (9, text(5_000), 2)];
+        for (level, data, block_type) in cases {
+            let compressed = deflate(&data, level);
+            assert_eq!(first_block_type(&compressed), block_type);
+            assert_eq!(inflate_all(&compressed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn resumes_at_every_block_boundary() {
+        let data = [text(40_000), noise(50_000, 7), text(40_000)].concat();
+        let compressed = deflate(&data, 6);
+        let mut input = BitReader::new(&compressed[..], 0);
+        let mut inflater = Inflater::new();
+        let mut out = 0;
+        let mut boundaries = 0;
+        loop {
+            let progress = inflater.inflate(&mut input).unwrap();
+            out += inflater.output().len();
+            if progress == Progress::StreamEnd {
+                break;
+            }
+            if progress != Progress::BlockEnd {
+                continue;
+            }
+            boundaries += 1;
+            // 索引のチェックポイントと同じく、ビット位置と直前のウィンドウだけから再開する。
+            let position = input.bit_position();
+            let mut resumed_input = BitReader::new(&compressed[(position / 8) as usize..], position / 8);
+            resumed_input.bits((position % 8) as u32).unwrap();
+            let mut resumed = Inflater::with_window(inflater.window());
+            let mut rest = Vec::new();
+            while resumed.inflate(&mut resumed_input).unwrap() != Progress::StreamEnd {
+                rest.extend_from_slice(resumed.output());
+            }
+            rest.extend_from_slice(resumed.output());
+            assert!(rest == data[out..], "resumed at bit {}", position);
+        }
+        assert_eq!(out, data.len());
+        assert!(boundaries > 1, "{} block boundaries", boundaries);
+    }
+
+    #[test]
+    fn truncated_input_is_eof() {
+        let compressed = deflate(&text(200), 9);
+        for len in 0..compressed.len() {
+            assert!(matches!(inflate_all(&compressed[..len]), Err(InflateError::Eof)), "{} of {} bytes", len, compressed.len());
+        }
+        let stored = deflate(b"stored", 0);
+        assert!(matches!(inflate_all(&stored[..stored.len() - 1]), Err(InflateError::Eof)));
+    }
+
+    #[test]
+    fn invalid_data() {
+        // BFINAL = 1, BTYPE = 11
+        assert!(matches!(inflate_all(&[0x07]), Err(InflateError::Invalid("invalid block type"))));
+        // 非圧縮ブロックの LEN と NLEN が合わない。
+        assert!(matches!(
+            inflate_all(&[0x01, 0x05, 0x00, 0x00, 0x00]),
+            Err(InflateError::Invalid("invalid stored block lengths"))
+        ));
+        // 固定 Huffman で、先頭から距離 1 の参照 (長さ 3)。
+        assert!(matches!(inflate_all(&[0x03, 0x02]), Err(InflateError::Invalid("invalid distance too far back"))));
+    }
+
+    #[test]
+    fn corrupt_streams_agree_with_flate2() {
+        let compressed = deflate(&text(2_000), 9);
+        let positions = noise(2_000, 3);
+        for (i, pair) in positions.chunks(2).enumerate() {
+            let mut corrupt = compressed.clone();
+            let at = (usize::from(pair[0]) << 8 | usize::from(pair[1])) % corrupt.len();
+            corrupt[at] ^= 1 << (i % 8);
+            let mut expected = Vec::new();
+            let expected = DeflateDecoder::new(&corrupt[..]).read_to_end(&mut expected).map(|_| expected);
+            match (expected, inflate_all(&corrupt)) {
+                (Ok(expected), Ok(actual)) => assert!(expected == actual, "byte {}", at),
+                (Err(_), Err(_)) => {}
+                // miniz_oxide は出力の先頭より前を指す距離を 0 で埋めて通すが、zlib と同じくエラーにする。
+                (Ok(_), Err(InflateError::Invalid("invalid distance too far back"))) => {}
+                (expected, actual) => panic!("byte {}: flate2 {:?}, inflate {:?}", at, expected.map(|data| data.len()), actual.map(|data| data.len())),
+            }
+        }
+    }
+}
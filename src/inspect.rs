@@ -0,0 +1,240 @@
+use std::error::Error;
+use std::io::{self, stdout, BufRead, BufWriter, Write};
+
+use crate::bgzf;
+use crate::cli::ListArgs;
+use crate::decoder::MemberInfo;
+use crate::header::{Header, FCOMMENT, FEXTRA, FHCRC, FNAME, FTEXT, ID1, ID2, CM_DEFLATE};
+use crate::{json, open_buffered};
+
+// RFC 1952 2.3.1 の OS の値と名前。
+fn os_name(os: u8) -> &'static str {
+    match os {
+        0 => "FAT",
+        1 => "Amiga",
+        2 => "VMS",
+        3 => "Unix",
+        4 => "VM/CMS",
+        5 => "Atari TOS",
+        6 => "HPFS",
+        7 => "Macintosh",
+        8 => "Z-System",
+        9 => "CP/M",
+        10 => "TOPS-20",
+        11 => "NTFS",
+        12 => "QDOS",
+        13 => "Acorn RISCOS",
+        255 => "unknown",
+        _ => "undefined",
+    }
+}
+
+// XFL の値の意味 (deflate の場合)。
+fn xfl_name(xfl: u8) -> &'static str {
+    match xfl {
+        2 => "maximum compression",
+        4 => "fastest compression",
+        _ => "none",
+    }
+}
+
+// FLG で立っているビットの名前を並べる。
+fn flag_names(flags: u8) -> String {
+    let bits = [(FTEXT, "FTEXT"), (FHCRC, "FHCRC"), (FEXTRA, "FEXTRA"), (FNAME, "FNAME"), (FCOMMENT, "FCOMMENT")];
+    let names: Vec<&str> = bits
+        .iter()
+        .filter(|(bit, _)| flags & bit != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(" ")
+    }
+}
+
+// 1970-01-01 からの秒数を UTC の日時にする。日付の計算は H. Hinnant の civil_from_days による。
+fn format_unix_time(secs: u32) -> String {
+    let days = (secs / 86400) as i64;
+    let rest = secs % 86400;
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year, month, day, rest / 3600, rest / 60 % 60, rest % 60
+    )
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+fn lossy(bytes: &Option<Vec<u8>>) -> Option<String> {
+    bytes.as_ref().map(|b| String::from_utf8_lossy(b).into_owned())
+}
+
+// ファイルのすべてのメンバーを展開し、各メンバーの情報を集める。
+pub fn read_members(filename: &str) -> Result<Vec<MemberInfo>, Box<dyn Error>> {
+    Ok(gzip_test::verify(open_buffered(filename)?, filename)?)
+}
+
+// 開いてある入力のすべてのメンバーを展開し、各メンバーの情報を集める。
+pub fn read_members_from<R: BufRead>(input: R, filename: &str) -> Result<Vec<MemberInfo>, Box<dyn Error>> {
+    Ok(gzip_test::verify(input, filename)?)
+}
+
+fn write_header_text<W: Write>(w: &mut W, header: &Header) -> io::Result<()> {
+    writeln!(w, "    ID1 ID2            {:02x} {:02x}", ID1, ID2)?;
+    writeln!(w, "    CM                 {} (deflate)", CM_DEFLATE)?;
+    writeln!(w, "    FLG                0x{:02x} ({})", header.flags, flag_names(header.flags))?;
+    if header.mtime == 0 {
+        writeln!(w, "    MTIME              0 (not set)")?;
+    } else {
+        writeln!(w, "    MTIME              {} ({})", header.mtime, format_unix_time(header.mtime))?;
+    }
+    writeln!(w, "    XFL                {} ({})", header.xfl, xfl_name(header.xfl))?;
+    writeln!(w, "    OS                 {} ({})", header.os, os_name(header.os))?;
+    if let Some(extra) = &header.extra {
+        writeln!(w, "    FEXTRA             {} bytes", extra.len())?;
+        for sub in header.extra_subfields() {
+            writeln!(
+                w,
+                "      subfield {}{}      {}",
+                sub.si1 as char,
+                sub.si2 as char,
+                hex_bytes(sub.data)
+            )?;
+        }
+    }
+    if let Some(name) = lossy(&header.filename) {
+        writeln!(w, "    FNAME              {}", name)?;
+    }
+    if let Some(comment) = lossy(&header.comment) {
+        writeln!(w, "    FCOMMENT           {}", comment)?;
+    }
+    if let Some(hcrc) = header.header_crc {
+        writeln!(w, "    FHCRC              {:04x}", hcrc)?;
+    }
+    Ok(())
+}
+
+fn write_text<W: Write>(w: &mut W, filename: &str, members: &[MemberInfo]) -> io::Result<()> {
+    let is_bgzf = bgzf::is_bgzf(members);
+    writeln!(w, "{}", filename)?;
+    for member in members {
+        writeln!(w, "  member {}", member.index)?;
+        writeln!(w, "    offset             {}", member.offset)?;
+        if is_bgzf {
+            writeln!(w, "    virtual offset     {}", bgzf::virtual_offset(member.offset, 0))?;
+        }
+        writeln!(w, "    compressed size    {} bytes (header {} bytes)", member.compressed_len, member.header.len)?;
+        write_header_text(w, &member.header)?;
+        writeln!(w, "    CRC32              {:08x}", member.trailer.crc32)?;
+        writeln!(w, "    ISIZE              {}", member.trailer.isize)?;
+        writeln!(w, "    uncompressed size  {} bytes", member.uncompressed_len)?;
+    }
+    let compressed: u64 = members.iter().map(|m| m.compressed_len).sum();
+    let uncompressed: u64 = members.iter().map(|m| m.uncompressed_len).sum();
+    writeln!(
+        w,
+        "  {} member(s), {} bytes compressed, {} bytes uncompressed",
+        members.len(),
+        compressed,
+        uncompressed
+    )?;
+    if is_bgzf {
+        let eof = if bgzf::has_eof_block(members) { "present" } else { "missing" };
+        writeln!(w, "  BGZF: {} block(s), EOF marker {}", members.len(), eof)?;
+    }
+    Ok(())
+}
+
+fn header_json(header: &Header) -> String {
+    let extra = match &header.extra {
+        None => "null".to_string(),
+        Some(_) => {
+            let subfields: Vec<String> = header
+                .extra_subfields()
+                .iter()
+                .map(|sub| {
+                    format!(
+                        "{{\"id\":{},\"data\":{}}}",
+                        json::string(&format!("{}{}", sub.si1 as char, sub.si2 as char)),
+                        json::string(&hex_bytes(sub.data).replace(' ', ""))
+                    )
+                })
+                .collect();
+            format!("[{}]", subfields.join(","))
+        }
+    };
+    format!(
+        "{{\"flags\":{},\"ftext\":{},\"mtime\":{},\"xfl\":{},\"os\":{},\"os_name\":{},\"extra\":{},\"filename\":{},\"comment\":{},\"header_crc\":{},\"length\":{}}}",
+        header.flags,
+        header.flags & FTEXT != 0,
+        header.mtime,
+        header.xfl,
+        header.os,
+        json::string(os_name(header.os)),
+        extra,
+        json::optional_string(lossy(&header.filename).as_deref()),
+        json::optional_string(lossy(&header.comment).as_deref()),
+        header.header_crc.map_or("null".to_string(), |hcrc| hcrc.to_string()),
+        header.len
+    )
+}
+
+// BGZF のファイルでなければ virtual_offset は null にする。
+fn member_json(member: &MemberInfo, is_bgzf: bool) -> String {
+    let virtual_offset = if is_bgzf { bgzf::virtual_offset(member.offset, 0).to_string() } else { "null".to_string() };
+    format!(
+        "{{\"index\":{},\"offset\":{},\"virtual_offset\":{},\"compressed_length\":{},\"header\":{},\"crc32\":{},\"isize\":{},\"uncompressed_length\":{}}}",
+        member.index,
+        member.offset,
+        virtual_offset,
+        member.compressed_len,
+        header_json(&member.header),
+        json::string(&format!("{:08x}", member.trailer.crc32)),
+        member.trailer.isize,
+        member.uncompressed_len
+    )
+}
+
+fn file_json(filename: &str, members: &[MemberInfo]) -> String {
+    let is_bgzf = bgzf::is_bgzf(members);
+    let bgzf_info = if is_bgzf {
+        format!("{{\"blocks\":{},\"eof_marker\":{}}}", members.len(), bgzf::has_eof_block(members))
+    } else {
+        "null".to_string()
+    };
+    let members: Vec<String> = members.iter().map(|member| member_json(member, is_bgzf)).collect();
+    format!("{{\"file\":{},\"bgzf\":{},\"members\":[{}]}}", json::string(filename), bgzf_info, members.join(","))
+}
+
+/// `list` コマンド: 各ファイルのメンバーを順に調べ、ヘッダーとサイズを表示する。
+pub fn list(args: &ListArgs) -> Result<(), Box<dyn Error>> {
+    let out = stdout();
+    let mut writer = BufWriter::new(out.lock());
+    if args.json {
+        // 途中のファイルで失敗した時に閉じていない配列を残さないよう、すべて調べてからまとめて書き出す。
+        let files = args.inputs.iter().map(|filename| Ok(file_json(filename, &read_members(filename)?))).collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        writeln!(writer, "[")?;
+        for (i, file) in files.iter().enumerate() {
+            let separator = if i + 1 < files.len() { "," } else { "" };
+            writeln!(writer, "  {}{}", file, separator)?;
+        }
+        writeln!(writer, "]")?;
+    } else {
+        for filename in &args.inputs {
+            write_text(&mut writer, filename, &read_members(filename)?)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
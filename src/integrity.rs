@@ -0,0 +1,39 @@
+use std::error::Error;
+
+use crate::cli::TestArgs;
+use crate::error::{self, Failed};
+use crate::inspect::read_members;
+
+/// `test` コマンド: `gzip -t` と同じく、出力せずに展開して各メンバーの CRC32 と ISIZE を確かめる。
+///
+/// 失敗したファイルがあっても残りのファイルを続けて調べ、最後に結果をまとめて表示する。
+/// 終了コードは最初に失敗したファイルのエラーの種類による。
+pub fn test(args: &TestArgs) -> Result<(), Box<dyn Error>> {
+    let mut failed = 0;
+    let mut first_code = None;
+    for filename in &args.inputs {
+        match read_members(filename) {
+            Ok(members) => {
+                if !args.quiet {
+                    let uncompressed: u64 = members.iter().map(|m| m.uncompressed_len).sum();
+                    println!("{}: OK ({} member(s), {} bytes)", filename, members.len(), uncompressed);
+                }
+            }
+            Err(err) => {
+                // エラーメッセージには失敗したメンバーの番号と圧縮データ上の位置が含まれる。
+                println!("{}: FAILED: {}", filename, err);
+                failed += 1;
+                first_code.get_or_insert(error::exit_code(err.as_ref()));
+            }
+        }
+    }
+    let tested = args.inputs.len();
+    println!("{} file(s) tested, {} OK, {} failed", tested, tested - failed, failed);
+    match first_code {
+        Some(code) => Err(Box::new(Failed {
+            code,
+            message: format!("{} of {} file(s) failed the integrity test", failed, tested),
+        })),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,27 @@
+use std::fmt::Write;
+
+/// 文字列を JSON の文字列リテラル (引用符付き) に変換する。
+pub fn string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// 値がなければ `null`、あれば JSON の文字列リテラルにする。
+pub fn optional_string(value: Option<&str>) -> String {
+    value.map_or_else(|| "null".to_string(), string)
+}
@@ -0,0 +1,129 @@
+//! 複数のメンバーを持つ gzip ファイルを最後のメンバーまで展開し、検査するためのライブラリ。
+//!
+//! flate2 の `GzDecoder` は最初のメンバーしか展開しないので、`cat a.gz b.gz > ab.gz` のように
+//! つなげたファイルの後半のデータが何のエラーもなく失われる。[`MultiMemberDecoder`] はすべてのメンバーを展開し、
+//! 各メンバーの CRC32 と ISIZE を確かめ、誤りはファイル名、メンバーの番号、圧縮データ上の位置を持つ
+//! [`GzTestError`] で返す。
+//!
+//! ```no_run
+//! use std::fs::File;
+//!
+//! for line in gzip_test::Lines::new(gzip_test::open_reader(File::open("test-multi.txt.gz")?, "test-multi.txt.gz")) {
+//!     println!("{}", line?);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+/// `tokio` の `AsyncRead` から読むデコーダーと、行の `Stream`。`tokio` フィーチャーで使える。
+#[cfg(feature = "tokio")]
+pub mod async_io;
+/// すべてのメンバーを展開するデコーダーと、その入力を扱う `BufRead`。
+pub mod decoder;
+/// 位置を持つエラーと、その終了コード。
+pub mod error;
+/// gzip、zlib、raw deflate、圧縮されていない入力の判別と展開。
+pub mod format;
+/// gzip のヘッダーとトレーラー (RFC 1952)。
+pub mod header;
+/// 展開したテキストの行ごとの読み込み。
+pub mod lines;
+// 入力ファイルのメモリーへのマップ。マップした領域は外から書き換えられうるので、型はライブラリの API には出さず、
+// 使うかどうかは unsafe な map_input_files で選ばせる。
+mod mmap;
+/// メンバーをいくつものスレッドで展開するデコーダー。
+pub mod parallel;
+/// 届いた分ずつ入力を渡す、非同期の読み込みから使うためのデコーダー。
+pub mod push;
+
+use std::fs::File;
+use std::io::{self, stdin, BufRead, BufReader, Cursor, Read, Seek};
+
+pub use decoder::{Locate, Member, MemberInfo, Members, MultiMemberDecoder};
+pub use error::{GzTestError, Location};
+pub use header::{read_header, Header, Trailer};
+pub use lines::Lines;
+pub use push::{PushDecoder, PushLines};
+#[cfg(feature = "tokio")]
+pub use async_io::{AsyncDecoder, AsyncLines};
+
+/// ファイルから読む [`MultiMemberDecoder`]。
+pub type Decoder = MultiMemberDecoder<Box<dyn BufRead>>;
+
+fn open_error(filename: &str, source: io::Error) -> GzTestError {
+    GzTestError::Open { at: Location { file: filename.to_string(), member: 0, offset: 0 }, source }
+}
+
+/// 入力ファイルを開く。`"-"` の時は標準入力から読み込む。
+/// ファイルのオープンに失敗した時にはファイル名とエラー内容を持つエラーを返す。
+pub fn open_input(filename: &str) -> Result<Box<dyn Read>, GzTestError> {
+    if filename == "-" {
+        return Ok(Box::new(stdin()));
+    }
+    let file = File::open(filename).map_err(|source| open_error(filename, source))?;
+    Ok(Box::new(file))
+}
+
+/// これ以降に開く通常の入力ファイルをメモリーにマップし、展開する時にファイルの中身を `read` でコピーしないようにする。
+/// [`open_buffered`]、[`open_seekable`]、[`open_decoder`]、[`ParallelDecoder::open`](parallel::ParallelDecoder::open) と
+/// [`format::open_any`] が対象になる。呼ばなければ、これらは `BufReader<File>` や `File` で読む。
+///
+/// # Safety
+///
+/// プロセスが終わるまで、開いた入力ファイルを読み終える前に書き換えたり切り詰めたりしないこと。
+/// マップした領域は `&[u8]` として渡すので、書き換えられると変わらないはずの参照の中身が変わり、
+/// 切り詰められるとその先を読んだ時にプロセスが SIGBUS で止まる。他のプロセスによる書き換えも含む。
+pub unsafe fn map_input_files() {
+    mmap::enable();
+}
+
+/// 入力ファイルをバッファリングして読めるように開く。`"-"` の時は標準入力から読み込む。
+pub fn open_buffered(filename: &str) -> Result<Box<dyn BufRead>, GzTestError> {
+    if filename == "-" {
+        return Ok(Box::new(BufReader::new(stdin())));
+    }
+    let file = File::open(filename).map_err(|source| open_error(filename, source))?;
+    match mmap::map_input(&file) {
+        Some(map) => Ok(Box::new(Cursor::new(map))),
+        None => Ok(Box::new(BufReader::new(file))),
+    }
+}
+
+/// 2回読み直せる入力。
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// 入力ファイルをシークできるように開く。標準入力はすべてメモリに読み込んでおく。
+pub fn open_seekable(filename: &str) -> Result<Box<dyn ReadSeek>, GzTestError> {
+    if filename == "-" {
+        let mut data = Vec::new();
+        stdin().read_to_end(&mut data).map_err(|source| open_error(filename, source))?;
+        return Ok(Box::new(Cursor::new(data)));
+    }
+    let file = File::open(filename).map_err(|source| open_error(filename, source))?;
+    match mmap::map_input(&file) {
+        Some(map) => Ok(Box::new(Cursor::new(map))),
+        None => Ok(Box::new(file)),
+    }
+}
+
+/// gzip ファイルのパスを受け取り、メンバーごとに展開するデコーダーを準備する。
+pub fn open_decoder(filename: &str) -> Result<Decoder, GzTestError> {
+    Ok(MultiMemberDecoder::new(open_buffered(filename)?, filename))
+}
+
+/// 任意の `Read` から読む gzip データを、すべてのメンバーまで展開するデコーダーを準備する。
+/// `name` はエラーの位置を示すのに使う。
+pub fn open_reader<R: Read>(reader: R, name: &str) -> MultiMemberDecoder<BufReader<R>> {
+    MultiMemberDecoder::new(BufReader::new(reader), name)
+}
+
+/// `gzip -t` と同じく、出力せずにすべてのメンバーを展開して CRC32 と ISIZE を確かめ、各メンバーの情報を返す。
+pub fn verify<R: BufRead>(input: R, name: &str) -> Result<Vec<MemberInfo>, GzTestError> {
+    let mut members = Members::new(input, name);
+    let mut infos = Vec::new();
+    while let Some(member) = members.next_member() {
+        infos.push(member?.finish()?);
+    }
+    Ok(infos)
+}
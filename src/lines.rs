@@ -0,0 +1,67 @@
+use std::io::{BufRead, BufReader, Read};
+
+use crate::decoder::Locate;
+use crate::error::GzTestError;
+
+/// 展開したテキストを1行ずつ返すイテレーター。
+///
+/// `BufRead::lines()` と同じく行末の `"\n"` と `"\r\n"` を取り除くが、エラーは位置を持つ [`GzTestError`] で返し、
+/// UTF-8 でない行は1から数えた行番号と合わせて [`GzTestError::Utf8`] にする。
+/// 行はメンバーをまたいで数えるので、`zcat | wc -l` と同じ番号になる。エラーの後は何も返さない。
+pub struct Lines<D> {
+    reader: BufReader<D>,
+    counter_lines: u64,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<D: Read + Locate> Lines<D> {
+    /// `decoder` が展開したデータを行に分ける。
+    pub fn new(decoder: D) -> Self {
+        Lines { reader: BufReader::new(decoder), counter_lines: 0, buf: Vec::new(), done: false }
+    }
+
+    /// 最後に返した行の、1から数えた番号。
+    pub fn line_number(&self) -> u64 {
+        self.counter_lines
+    }
+
+    /// 内側のデコーダー。
+    pub fn get_ref(&self) -> &D {
+        self.reader.get_ref()
+    }
+
+    fn next_line(&mut self) -> Result<Option<String>, GzTestError> {
+        self.buf.clear();
+        let read = self.reader.read_until(b'\n', &mut self.buf).map_err(|err| {
+            GzTestError::from_io(err).unwrap_or_else(|source| GzTestError::Read { at: self.reader.location(), source })
+        })?;
+        if read == 0 {
+            return Ok(None);
+        }
+        self.counter_lines += 1;
+        if self.buf.last() == Some(&b'\n') {
+            self.buf.pop();
+            if self.buf.last() == Some(&b'\r') {
+                self.buf.pop();
+            }
+        }
+        match String::from_utf8(std::mem::take(&mut self.buf)) {
+            Ok(line) => Ok(Some(line)),
+            Err(_) => Err(GzTestError::Utf8 { at: self.reader.location(), line: self.counter_lines }),
+        }
+    }
+}
+
+impl<D: Read + Locate> Iterator for Lines<D> {
+    type Item = Result<String, GzTestError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let line = self.next_line().transpose();
+        self.done = !matches!(line, Some(Ok(_)));
+        line
+    }
+}
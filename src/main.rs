@@ -1,37 +1,330 @@
-use std::fs::File;
-use std::io::{stdout, BufRead, BufReader, BufWriter, Write};
+mod bgzf;
+mod check;
+mod cli;
+mod compress;
+mod concat;
+mod extract;
+mod grep;
+mod index;
+mod inflate;
+mod inspect;
+mod integrity;
+mod json;
+mod output;
+mod paths;
+mod recover;
+mod regex;
+mod split;
+mod stats;
+
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
 use std::error::Error;
-use flate2::read::MultiGzDecoder;
-
-
-// gzipファイルのパスを受け取る。
-// ファイルのオープンに失敗した時にはファイル名とエラー内容を表示。
-fn open_reading_gzip(filename: &str) -> BufReader<MultiGzDecoder<File>> {
-    let file = File::open(filename).unwrap_or_else(|err| {
-        panic!("Cannnot open file '{}', Error: {}", filename, err);
-    });
-    let decoder = MultiGzDecoder::new(file);
-    BufReader::new(decoder)
-}
-
-fn main() -> Result<(), Box<dyn Error>> {
-    let filename = "test-multi.txt.gz";
-    // 読み込むgzipファイルを開き、バッファリングして読み込むためのBufReaderを準備
-    let reader = open_reading_gzip(filename);
-    // バッファリングして標準出力に書き出すためのBufwriterを準備
-    let out = stdout();
-    let mut writer = BufWriter::new(out.lock());
-    // ファイルを行ごとに読み出す。
-    let mut counter_lines: u64 = 0;
-    for line in reader.lines() {
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use gzip_test::{decoder, error, format, header, open_buffered, open_decoder, open_input, open_seekable, ReadSeek};
+
+use cli::{Command, DecompressArgs, Range};
+use decoder::Locate;
+use error::{Failed, GzTestError, Location};
+use header::Header;
+use output::{AtomicFile, Output, OutputOptions};
+
+// 読み込み中のエラーに GzTestError が含まれていれば取り出す。
+fn reading_error(err: io::Error) -> Box<dyn Error> {
+    match GzTestError::from_io(err) {
+        Ok(err) => Box::new(err),
+        Err(err) => Box::new(err),
+    }
+}
+
+// compress などの出力先を準備する。パスの指定がなければ標準出力に書き出す。
+// ファイルは一時ファイルに書き出して最後に付け替えるので、書きかけのファイルは残らない。
+// これらのコマンドは既にある出力ファイルを上書きしてきたので、force にする。
+fn create_output(output: Option<&str>, inputs: &[String]) -> Result<Output, Box<dyn Error>> {
+    if let Some(path) = output {
+        output::check_not_input(Path::new(path), inputs)?;
+    }
+    Output::open(output.map(Path::new), OutputOptions { force: true, fsync: false })
+}
+
+// 1行を書き出す。binary の時はそのまま書き出し、そうでなければ UTF-8 か確かめて行末を "\n" にそろえる。
+fn write_line<W: Write>(
+    buf: &mut Vec<u8>,
+    counter_lines: u64,
+    binary: bool,
+    writer: &mut W,
+    at: impl FnOnce() -> Location,
+) -> Result<(), Box<dyn Error>> {
+    if binary {
+        writer.write_all(buf)?;
+        return Ok(());
+    }
+    // lines() と同じく行末の "\n" と "\r\n" を取り除く。
+    if buf.last() == Some(&b'\n') {
+        buf.pop();
+        if buf.last() == Some(&b'\r') {
+            buf.pop();
+        }
+    }
+    let line = std::str::from_utf8(buf).map_err(|_| GzTestError::Utf8 { at: at(), line: counter_lines })?;
+    writer.write_all(line.as_bytes())?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+// 展開したテキストのうち、1から数えて range.start 行目から range.end 行目までを書き出す。
+// 範囲の最後の行を書き出したら、残りは展開しない。
+fn write_lines<R: BufRead + Locate, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    binary: bool,
+    range: Range,
+) -> Result<(), Box<dyn Error>> {
+    let mut buf = Vec::new();
+    for counter_lines in 1_u64.. {
+        if range.end.is_some_and(|end| counter_lines > end) {
+            break;
+        }
+        buf.clear();
+        // 行の取り出しに失敗した時にはファイル名、メンバーの番号、位置を持つエラーを返す。
+        if reader.read_until(b'\n', &mut buf).map_err(reading_error)? == 0 {
+            break;
+        }
+        if counter_lines >= range.start {
+            write_line(&mut buf, counter_lines, binary, writer, || reader.location())?;
+        }
+    }
+    Ok(())
+}
+
+// 最後の count 行を書き出す。読んだ行は count 行までしか持たない。
+// first_line は reader が指している行の、1から数えた番号。
+fn write_tail<R: BufRead + Locate, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    binary: bool,
+    first_line: u64,
+    count: u64,
+) -> Result<(), Box<dyn Error>> {
+    if count == 0 {
+        return Ok(());
+    }
+    let mut kept: VecDeque<Vec<u8>> = VecDeque::new();
+    let mut counter_lines = first_line;
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf).map_err(reading_error)? == 0 {
+            break;
+        }
+        // 一番古い行を捨て、そのバッファを次の行に使い回す。
+        let spare = if kept.len() as u64 == count { kept.pop_front() } else { None };
+        kept.push_back(std::mem::replace(&mut buf, spare.unwrap_or_default()));
         counter_lines += 1;
-        // 行の取り出しに失敗した時にはファイル名、その行が何行目か、エラー内容を表示する。
-        let line = line.unwrap_or_else(|err|{
-            panic!("Cannnot reading the {}th line of {}, Error: {}", counter_lines, filename, err);
-        });
-        // 標準出力に書き出し
-        writer.write_all((line + "\n").as_bytes())?;
-    }
-    writer.flush()?;
+    }
+    let at = reader.location();
+    let first = counter_lines - kept.len() as u64;
+    for (line, mut buf) in (first..).zip(kept) {
+        write_line(&mut buf, line, binary, writer, || at.clone())?;
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+// --tail で、索引があれば最後の行の手前のチェックポイントから展開する。索引が gzip ファイルより古ければ、
+// 警告して false を返し、最初から展開させる。
+// 索引の行数は改行の数なので、"\n" で終わらない最後の行の分だけ1行多く読み、write_tail で捨てる。
+fn write_indexed_tail<W: Write>(args: &DecompressArgs, filename: &str, count: u64, writer: &mut W) -> Result<bool, Box<dyn Error>> {
+    if filename == "-" || args.raw {
+        return Ok(false);
+    }
+    let Some(index) = index::load(filename, index::IfStale::Ignore)? else {
+        return Ok(false);
+    };
+    let first_line = index.total_lines.saturating_sub(count) + 1;
+    let mut reader = index::IndexedReader::new(open_seekable(filename)?, filename, Some(index));
+    reader.seek_line(first_line).map_err(reading_error)?;
+    write_tail(&mut reader, writer, args.binary, first_line, count)?;
+    Ok(true)
+}
+
+// 展開したバイト列を改行の変換や UTF-8 の検査をせずにそのまま書き出す。
+fn write_bytes<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> Result<(), Box<dyn Error>> {
+    loop {
+        let buf = reader.fill_buf().map_err(reading_error)?;
+        if buf.is_empty() {
+            return Ok(());
+        }
+        writer.write_all(buf)?;
+        let len = buf.len();
+        reader.consume(len);
+    }
+}
+
+// 1つの入力を展開して書き出す。--recover で壊れたデータを飛ばした時は false を返す。
+fn decompress_file<W: Write>(args: &DecompressArgs, filename: &str, writer: &mut W) -> Result<bool, Box<dyn Error>> {
+    if args.recover {
+        return recover::recover(filename, writer);
+    }
+    if let Some(count) = args.tail {
+        if write_indexed_tail(args, filename, count, writer)? {
+            return Ok(true);
+        }
+    }
+    // 形式に合わせたデコーダーを選び、バッファリングして読み込むためのBufReaderを準備
+    let mut reader = BufReader::new(format::open_any(filename, args.raw, args.force, args.jobs)?);
+    if let Some(count) = args.tail {
+        write_tail(&mut reader, writer, args.binary, 1, count)?;
+    } else if let Some(range) = args.lines {
+        write_lines(&mut reader, writer, args.binary, range)?;
+    } else if args.binary {
+        write_bytes(&mut reader, writer)?;
+    } else {
+        // ファイルを行ごとに読み出す。
+        write_lines(&mut reader, writer, false, Range { start: 1, end: None })?;
+    }
+    Ok(true)
+}
+
+// gzip ファイルの最初のメンバーのヘッダーを読む。gzip でなければ None を返し、エラーは展開する時に報告する。
+fn first_header(filename: &str) -> Option<Header> {
+    let mut input = open_buffered(filename).ok()?;
+    header::read_header(&mut input).ok()
+}
+
+// ヘッダーの MTIME を更新時刻にする。0 は時刻がないことを表す。
+fn header_time(header: &Header) -> Option<SystemTime> {
+    (header.mtime != 0).then(|| UNIX_EPOCH + Duration::from_secs(u64::from(header.mtime)))
+}
+
+// --gunzip の出力先。--name の時は FNAME を使い、ディレクトリの部分は取り除いて入力ファイルの隣に置く。
+fn gunzip_output(filename: &str, header: Option<&Header>) -> Result<PathBuf, Box<dyn Error>> {
+    let fname = header.and_then(|header| header.filename.as_deref());
+    let path = match fname.and_then(paths::safe_file_name) {
+        Some(name) => Path::new(filename).with_file_name(name),
+        None => {
+            if fname.is_some() {
+                eprintln!("gzip_test: warning: the FNAME of '{}' cannot be used as a file name; ignoring it", filename);
+            }
+            let path = paths::gunzip_path(filename)
+                .ok_or_else(|| format!("'{}' does not end in .gz or a similar suffix; no output name", filename))?;
+            PathBuf::from(path)
+        }
+    };
+    // FNAME が入力ファイル自身の名前なら、上書きや --delete で元のデータを失う。
+    if path.file_name() == Path::new(filename).file_name() {
+        return Err(format!("the FNAME of '{}' names the input file itself", filename).into());
+    }
+    Ok(path)
+}
+
+fn remove_input(filename: &str) -> Result<(), Box<dyn Error>> {
+    fs::remove_file(filename).map_err(|err| format!("Cannot delete file '{}', Error: {}", filename, err).into())
+}
+
+fn decompress(args: &DecompressArgs) -> Result<(), Box<dyn Error>> {
+    let options = OutputOptions { force: args.force, fsync: args.fsync };
+    let mut clean = true;
+    if args.gunzip {
+        // 入力ごとに隣のファイルへ書き出し、確定してから次の入力に進む。
+        for filename in &args.inputs {
+            let header = if args.restore_name { first_header(filename) } else { None };
+            let path = gunzip_output(filename, header.as_ref())?;
+            let mut out = AtomicFile::create(&path, options)?;
+            if let Some(time) = header.as_ref().and_then(header_time) {
+                out.set_modified(time);
+            }
+            // gunzip と同じく、展開したファイルは元のファイルと同じ許可にする。
+            if let Ok(metadata) = fs::metadata(filename) {
+                let _ = out.file().set_permissions(metadata.permissions());
+            }
+            let complete = decompress_file(args, filename, &mut out)?;
+            out.commit()?;
+            clean &= complete;
+            if complete && args.delete {
+                remove_input(filename)?;
+            }
+        }
+    } else {
+        if let Some(path) = args.output.as_deref().filter(|_| args.delete) {
+            output::check_not_deleted(Path::new(path), &args.inputs)?;
+        }
+        // バッファリングして書き出すための出力先を準備
+        let mut out = Output::open(args.output.as_deref().map(Path::new), options)?;
+        // 複数の入力をつなげる時は、最初の入力の MTIME を使う。
+        let first = args.inputs.first().filter(|_| args.restore_name);
+        if let Some(time) = first.and_then(|filename| first_header(filename)).as_ref().and_then(header_time) {
+            out.set_modified(time);
+        }
+        for filename in &args.inputs {
+            clean &= decompress_file(args, filename, &mut out)?;
+        }
+        out.finish()?;
+        if clean && args.delete {
+            for filename in &args.inputs {
+                remove_input(filename)?;
+            }
+        }
+    }
+    if !clean {
+        return Err(Box::new(Failed {
+            code: recover::EXIT_RECOVERED,
+            message: "some data could not be recovered".to_string(),
+        }));
+    }
+    Ok(())
+}
+
+// 出力先のパイプが閉じられた (`gzip_test FILE | head` の) 時の書き込みエラーか。
+fn is_broken_pipe(err: &(dyn Error + 'static)) -> bool {
+    err.downcast_ref::<io::Error>().is_some_and(|err| err.kind() == io::ErrorKind::BrokenPipe)
+}
+
+fn main() -> ExitCode {
+    // SAFETY: 入力ファイルは読み終えるまで変わらないものとして扱う。このコマンドは出力を一時ファイルに書いて
+    // 置き換え、入力と同じファイルには書かないので、マップした入力を自分で書き換えることはない。
+    // 読んでいる間に他のプロセスが入力を書き換えるのは、zcat などが読んでいる間に書き換えるのと同じく、使う側が避けること。
+    unsafe { gzip_test::map_input_files() };
+    let command = match cli::parse_args(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("gzip_test: {}", err);
+            eprint!("{}", cli::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let result = match command {
+        Command::Help => {
+            print!("{}", cli::USAGE);
+            Ok(())
+        }
+        Command::Version => {
+            println!("gzip_test {}", env!("CARGO_PKG_VERSION"));
+            Ok(())
+        }
+        Command::Decompress(args) => decompress(&args),
+        Command::List(args) => inspect::list(&args),
+        Command::Check(args) => check::check(&args),
+        Command::Test(args) => integrity::test(&args),
+        Command::Compress(args) => compress::compress(&args),
+        Command::Concat(args) => concat::concat(&args),
+        Command::Split(args) => split::split(&args),
+        Command::Index(args) => index::index(&args),
+        Command::Extract(args) => extract::extract(&args),
+        Command::Grep(args) => grep::grep(&args),
+        Command::Stats(args) => stats::stats(&args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        // 他のフィルターと同じく、読み手がいなくなったら何も表示せずに終わる。
+        Err(err) if is_broken_pipe(err.as_ref()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("gzip_test: {}", err);
+            ExitCode::from(error::exit_code(err.as_ref()))
+        }
+    }
+}
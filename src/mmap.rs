@@ -0,0 +1,204 @@
+use std::fs::File;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use self::sys::Mapping;
+
+/// 読み込み専用でメモリーにマップしたファイル。複製してもマップは共有し、最後の複製が落ちた時に解除する。
+///
+/// `Cursor<Mmap>` にすると `BufRead + Seek` になり、`fill_buf` はマップした領域をそのまま返すので、
+/// [`MultiMemberDecoder`](crate::decoder::MultiMemberDecoder) はファイルを `read` でコピーせずに展開できる。
+#[derive(Clone)]
+pub struct Mmap {
+    map: Arc<Mapping>,
+}
+
+impl Mmap {
+    /// `file` 全体をマップする。空のファイルはマップできないので、空の領域として扱う。
+    ///
+    /// # Safety
+    ///
+    /// 返した `Mmap` とその複製が残っている間、ファイルを書き換えたり切り詰めたりしないこと。
+    /// マップした領域は `&[u8]` として渡すので、書き換えられると変わらないはずの参照の中身が変わり、
+    /// 切り詰められるとその先を読んだ時にプロセスが SIGBUS で止まる。
+    pub unsafe fn map(file: &File) -> io::Result<Self> {
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "file too large to map"))?;
+        Ok(Mmap { map: Arc::new(Mapping::new(file, len)?) })
+    }
+}
+
+impl AsRef<[u8]> for Mmap {
+    fn as_ref(&self) -> &[u8] {
+        self.map.as_slice()
+    }
+}
+
+#[cfg(all(unix, target_pointer_width = "64"))]
+mod sys {
+    use std::ffi::c_void;
+    use std::fs::File;
+    use std::io;
+    use std::os::raw::c_int;
+    use std::os::unix::io::AsRawFd;
+    use std::ptr;
+
+    // Linux と macOS で値は同じ。
+    const PROT_READ: c_int = 1;
+    const MAP_PRIVATE: c_int = 2;
+
+    extern "C" {
+        fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+        fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+
+    pub struct Mapping {
+        ptr: *mut c_void,
+        len: usize,
+    }
+
+    // マップした領域は読み込み専用で、解除するまで動かない。
+    unsafe impl Send for Mapping {}
+    unsafe impl Sync for Mapping {}
+
+    impl Mapping {
+        pub fn new(file: &File, len: usize) -> io::Result<Self> {
+            if len == 0 {
+                return Ok(Mapping { ptr: ptr::null_mut(), len });
+            }
+            // SAFETY: 開いているファイルの先頭から len バイトを、読み込み専用の新しい領域にマップする。
+            let ptr = unsafe { mmap(ptr::null_mut(), len, PROT_READ, MAP_PRIVATE, file.as_raw_fd(), 0) };
+            // MAP_FAILED は (void *)-1。
+            if ptr as isize == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Mapping { ptr, len })
+        }
+
+        pub fn as_slice(&self) -> &[u8] {
+            if self.len == 0 {
+                return &[];
+            }
+            // SAFETY: ptr から len バイトは Drop で解除するまで読める。
+            unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            if self.len > 0 {
+                // SAFETY: new でマップした領域で、as_slice で返した参照はもう残っていない。
+                unsafe { munmap(self.ptr, self.len) };
+            }
+        }
+    }
+}
+
+// mmap を使えない環境では、ファイル全体をメモリーに読み込んで代わりにする。
+#[cfg(not(all(unix, target_pointer_width = "64")))]
+mod sys {
+    use std::fs::File;
+    use std::io::{self, Read};
+
+    pub struct Mapping(Vec<u8>);
+
+    impl Mapping {
+        pub fn new(mut file: &File, len: usize) -> io::Result<Self> {
+            let mut data = Vec::with_capacity(len);
+            file.read_to_end(&mut data)?;
+            Ok(Mapping(data))
+        }
+
+        pub fn as_slice(&self) -> &[u8] {
+            &self.0
+        }
+    }
+}
+
+// map_input_files が呼ばれたか。
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// 入力ファイルを開く関数がファイルをマップするようにする。
+///
+/// # Safety
+///
+/// プロセスが終わるまで、開いた入力ファイルを読み終える前に書き換えたり切り詰めたりしないこと ([`Mmap::map`] を参照)。
+pub unsafe fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// [`enable`] が呼ばれていて、通常のファイルならマップする。それ以外は `None` を返し、呼んだ側は `read` で読む。
+pub fn map_input(file: &File) -> Option<Mmap> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    // SAFETY: enable を呼んだ側が、入力ファイルを読んでいる間に書き換えないと約束している。
+    unsafe { map_regular_file(file) }
+}
+
+/// 通常のファイルならマップする。パイプやデバイスのようにマップできないファイルは `None` を返す。
+///
+/// # Safety
+///
+/// [`Mmap::map`] と同じ。
+pub unsafe fn map_regular_file(file: &File) -> Option<Mmap> {
+    match file.metadata() {
+        Ok(metadata) if metadata.is_file() => Mmap::map(file).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, Cursor, Read, Seek, SeekFrom};
+    use std::path::PathBuf;
+
+    use super::*;
+
+    // テストごとのファイル。落とす時に消す。
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str, data: &[u8]) -> Self {
+            let path = std::env::temp_dir().join(format!("gzip_test-mmap-{}-{}", std::process::id(), name));
+            std::fs::write(&path, data).unwrap();
+            Scratch(path)
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn maps_the_whole_file() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let file = Scratch::new("whole", &data);
+        let map = unsafe { map_regular_file(&File::open(&file.0).unwrap()) }.unwrap();
+        let copy = map.clone();
+        drop(map);
+        assert!(copy.as_ref() == data);
+        let mut cursor = Cursor::new(copy);
+        cursor.seek(SeekFrom::Start(99_990)).unwrap();
+        assert_eq!(cursor.fill_buf().unwrap(), &data[99_990..]);
+        let mut rest = Vec::new();
+        cursor.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[99_990..]);
+    }
+
+    #[test]
+    fn empty_file() {
+        let file = Scratch::new("empty", b"");
+        let map = unsafe { map_regular_file(&File::open(&file.0).unwrap()) }.unwrap();
+        assert!(map.as_ref().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn devices_are_not_mapped() {
+        assert!(unsafe { map_regular_file(&File::open("/dev/null").unwrap()) }.is_none());
+    }
+}
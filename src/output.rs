@@ -0,0 +1,236 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, stdout, BufWriter, StdoutLock, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+use crate::error::Failed;
+
+// コマンドライン引数の誤りと同じ終了コード。
+const EXIT_USAGE: u8 = 2;
+
+/// 出力先のファイルを書き出す時の設定。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OutputOptions {
+    /// 既にあるファイルを上書きする。
+    pub force: bool,
+    /// 名前を付け替える前にデータをディスクに書き込ませる。
+    pub fsync: bool,
+}
+
+/// 同じディレクトリの一時ファイルに書き出し、[`AtomicFile::commit`] で目的の名前に付け替えるファイル。
+///
+/// 書き出しの途中でエラーが起きた時やプロセスが止まった時にも、目的の名前には書きかけのファイルが残らない。
+/// `commit` しないまま落とすと一時ファイルを消す。
+pub struct AtomicFile {
+    writer: Option<BufWriter<File>>,
+    temp: PathBuf,
+    path: PathBuf,
+    options: OutputOptions,
+    modified: Option<SystemTime>,
+    committed: bool,
+}
+
+fn create_error(path: &Path, err: impl std::fmt::Display) -> Box<dyn Error> {
+    format!("Cannot create file '{}', Error: {}", path.display(), err).into()
+}
+
+fn exists_error(path: &Path) -> Box<dyn Error> {
+    format!("'{}' already exists; use --force to overwrite it", path.display()).into()
+}
+
+// 2つのパスが同じファイルを指すか。どちらかがまだなければ false を返す。
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// 出力先 `path` が入力ファイル (標準入力の `-` を除く) のどれかと同じファイルなら、その入力を返す。
+pub fn input_at<'a>(path: &Path, inputs: &'a [String]) -> Option<&'a str> {
+    inputs.iter().map(String::as_str).find(|&input| input != "-" && same_file(path, Path::new(input)))
+}
+
+/// 入力を読み終える前に書き出すコマンドのために、出力先が入力のどれかと同じファイルならエラーにする。
+pub fn check_not_input(path: &Path, inputs: &[String]) -> Result<(), Box<dyn Error>> {
+    match input_at(path, inputs) {
+        Some(input) => Err(Box::new(Failed {
+            code: EXIT_USAGE,
+            message: format!("the output '{}' is the input file '{}'", path.display(), input),
+        })),
+        None => Ok(()),
+    }
+}
+
+/// `--delete` で消す入力のどれかが出力先 `path` と同じファイルならエラーにする。
+/// 出力を書き終えて入力を消すと、書いたばかりの出力も消えてしまう。
+pub fn check_not_deleted(path: &Path, inputs: &[String]) -> Result<(), Box<dyn Error>> {
+    match input_at(path, inputs) {
+        Some(input) => Err(Box::new(Failed {
+            code: EXIT_USAGE,
+            message: format!("'--delete' would delete the output '{}' along with the input file '{}'", path.display(), input),
+        })),
+        None => Ok(()),
+    }
+}
+
+impl AtomicFile {
+    /// `path` に書き出す準備をする。`force` でなければ、既にあるファイルはエラーにする。
+    pub fn create(path: &Path, options: OutputOptions) -> Result<Self, Box<dyn Error>> {
+        if !options.force && fs::symlink_metadata(path).is_ok() {
+            return Err(exists_error(path));
+        }
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let name = path.file_name().ok_or_else(|| create_error(path, "not a file name"))?;
+        let mut attempt = 0;
+        loop {
+            let temp = directory.join(format!(".{}.{}.{}.tmp", name.to_string_lossy(), process::id(), attempt));
+            match OpenOptions::new().write(true).create_new(true).open(&temp) {
+                Ok(file) => {
+                    return Ok(AtomicFile {
+                        writer: Some(BufWriter::new(file)),
+                        temp,
+                        path: path.to_path_buf(),
+                        options,
+                        modified: None,
+                        committed: false,
+                    })
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+                Err(err) => return Err(create_error(path, err)),
+            }
+        }
+    }
+
+    /// 書き出している一時ファイル。名前を付け替える前に属性を変える時に使う。
+    pub fn file(&self) -> &File {
+        self.writer.as_ref().expect("not committed yet").get_ref()
+    }
+
+    /// 確定する時に、ファイルの更新時刻を `time` にする。
+    pub fn set_modified(&mut self, time: SystemTime) {
+        self.modified = Some(time);
+    }
+
+    /// 書き出したデータを確定し、一時ファイルを目的の名前に付け替える。
+    pub fn commit(mut self) -> Result<(), Box<dyn Error>> {
+        let writer = self.writer.take().expect("not committed yet");
+        let file = writer.into_inner().map_err(|err| err.into_error())?;
+        // 書き込みを終えてから設定しないと、書き込んだ時刻で上書きされる。
+        if let Some(time) = self.modified {
+            file.set_modified(time)?;
+        }
+        if self.options.fsync {
+            file.sync_all()?;
+        }
+        drop(file);
+        if self.options.force {
+            fs::rename(&self.temp, &self.path).map_err(|err| create_error(&self.path, err))?;
+        } else {
+            // ハードリンクは目的の名前が既にあれば失敗するので、確かめてから付け替えるまでの間に
+            // 他のプロセスが作ったファイルも上書きしない。ハードリンクを作れないファイルシステムでは名前を付け替える。
+            match fs::hard_link(&self.temp, &self.path) {
+                Ok(()) => fs::remove_file(&self.temp)?,
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => return Err(exists_error(&self.path)),
+                Err(_) if fs::symlink_metadata(&self.path).is_ok() => return Err(exists_error(&self.path)),
+                Err(_) => fs::rename(&self.temp, &self.path).map_err(|err| create_error(&self.path, err))?,
+            }
+        }
+        self.committed = true;
+        if self.options.fsync {
+            sync_directory(&self.path);
+        }
+        Ok(())
+    }
+}
+
+// 名前の付け替えをディスクに書き込ませる。ディレクトリを開けない環境もあるので、失敗は無視する。
+fn sync_directory(path: &Path) {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if let Ok(directory) = File::open(directory) {
+        let _ = directory.sync_all();
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.as_mut().expect("not committed yet").write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.as_mut().expect("not committed yet").flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// 展開したデータの出力先。標準出力か、名前を付け替えて確定するファイル。
+pub enum Output {
+    Stdout(BufWriter<StdoutLock<'static>>),
+    File(AtomicFile),
+}
+
+impl Output {
+    /// 確定する時に、出力ファイルの更新時刻を `time` にする。標準出力では何もしない。
+    pub fn set_modified(&mut self, time: SystemTime) {
+        if let Output::File(file) = self {
+            file.set_modified(time);
+        }
+    }
+
+    /// `path` がなければ標準出力に書き出す。
+    pub fn open(path: Option<&Path>, options: OutputOptions) -> Result<Self, Box<dyn Error>> {
+        match path {
+            Some(path) => Ok(Output::File(AtomicFile::create(path, options)?)),
+            None => Ok(Output::Stdout(BufWriter::new(stdout().lock()))),
+        }
+    }
+
+    /// 書き出したデータを確定する。ファイルはここで初めて目的の名前になる。
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self {
+            Output::Stdout(mut writer) => Ok(writer.flush()?),
+            Output::File(file) => file.commit(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout(writer) => writer.write(buf),
+            Output::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout(writer) => writer.flush(),
+            Output::File(file) => file.flush(),
+        }
+    }
+}
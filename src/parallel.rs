@@ -0,0 +1,336 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::decoder::{Locate, MultiMemberDecoder};
+use crate::error::{GzTestError, Location};
+use crate::header::{scan_headers, scan_headers_in};
+use crate::mmap::{map_input, Mmap};
+use crate::open_input;
+
+// 先読みして展開するメンバーの展開後のサイズの上限。
+// これより大きいメンバーは、順番が来てから出力しながら展開する。
+const MAX_BUFFERED_MEMBER: usize = 32 << 20;
+
+// スレッドごとに開き直せる入力。標準入力はメモリに読み込んでおく。
+// 通常のファイルは、[`map_input_files`](crate::map_input_files) が呼ばれていればメモリーにマップし、各スレッドはマップした領域をコピーせずに読む。
+#[derive(Clone)]
+enum Source {
+    File(String),
+    Memory(Arc<[u8]>),
+    Mapped(Mmap),
+}
+
+impl Source {
+    fn open(&self, offset: u64) -> io::Result<Box<dyn BufRead + Send>> {
+        let mut input: Box<dyn BufRead + Send> = match self {
+            Source::File(path) => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                return Ok(Box::new(BufReader::new(file)));
+            }
+            Source::Memory(data) => Box::new(Cursor::new(Arc::clone(data))),
+            Source::Mapped(map) => Box::new(Cursor::new(map.clone())),
+        };
+        input.consume(offset as usize);
+        Ok(input)
+    }
+
+    // メモリー上にあるデータ。
+    fn bytes(&self) -> Option<&[u8]> {
+        match self {
+            Source::File(_) => None,
+            Source::Memory(data) => Some(data),
+            Source::Mapped(map) => Some(map.as_ref()),
+        }
+    }
+}
+
+// 先読みして展開した結果。
+enum Decoded {
+    Member { compressed_len: u64, data: Vec<u8> },
+    // 展開後のデータが MAX_BUFFERED_MEMBER を超えたので途中でやめた。
+    TooLarge,
+    // メンバーとして展開できなかった。ヘッダーに見えただけの位置か、壊れたメンバー。
+    Failed,
+}
+
+// MAX_BUFFERED_MEMBER を超えて書き込もうとすると失敗するバッファー。
+struct LimitedBuffer {
+    data: Vec<u8>,
+    exceeded: bool,
+}
+
+impl Write for LimitedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.data.len() + buf.len() > MAX_BUFFERED_MEMBER {
+            self.exceeded = true;
+            return Err(io::Error::other("member too large to buffer"));
+        }
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// offset から始まるメンバーを1つ展開する。メンバーの番号は分からないので 0 として扱う。
+fn decode_member(source: &Source, name: &str, offset: u64) -> Decoded {
+    let Ok(input) = source.open(offset) else {
+        return Decoded::Failed;
+    };
+    let mut decoder = MultiMemberDecoder::new(input, name).starting_at(offset, 0).one_member();
+    let mut out = LimitedBuffer { data: Vec::new(), exceeded: false };
+    match decoder.next_member(&mut out) {
+        Ok(Some(member)) => Decoded::Member { compressed_len: member.compressed_len, data: out.data },
+        Err(_) if out.exceeded => Decoded::TooLarge,
+        _ => Decoded::Failed,
+    }
+}
+
+type Sequential = MultiMemberDecoder<Box<dyn BufRead + Send>>;
+
+/// メンバーをいくつものスレッドで同時に展開し、ファイルの中の順番どおりに返す `Read`。
+///
+/// 初めにファイル全体からメンバーのヘッダーに見える位置を探し、それぞれの位置から先読みして展開しておく。
+/// 展開したデータは直前のメンバーの終わりと一致する位置のものだけを使うので、
+/// 圧縮データの中に偶然ヘッダーと同じバイト列があっても出力は変わらない。
+/// 先読みで展開できなかった位置と大きすぎるメンバーは、順番が来た時に [`MultiMemberDecoder`] で展開する。
+/// そのため、エラーの種類と位置、エラーまでに返すデータは1スレッドで展開した時と同じになる。
+pub struct ParallelDecoder {
+    source: Source,
+    name: String,
+    // メンバーの先頭かもしれない位置と、次に展開を頼む位置の添字。
+    candidates: Vec<u64>,
+    next_candidate: usize,
+    jobs: Option<Sender<u64>>,
+    results: Receiver<(u64, Decoded)>,
+    workers: Vec<JoinHandle<()>>,
+    // 展開を頼んでまだ結果を受け取っていない位置と、受け取ってまだ使っていない結果。
+    // 合わせて slots 個までにして、メモリーの使用量を抑える。
+    pending: HashSet<u64>,
+    done: HashMap<u64, Decoded>,
+    slots: usize,
+    // 次のメンバーの開始位置と番号。
+    offset: u64,
+    member: u64,
+    // 返している途中のメンバーのデータ。
+    data: Vec<u8>,
+    data_pos: usize,
+    sequential: Option<Sequential>,
+    finished: bool,
+}
+
+impl ParallelDecoder {
+    /// `threads` 個のスレッドで展開を始める。
+    pub fn open(filename: &str, threads: usize) -> Result<Self, GzTestError> {
+        if filename == "-" {
+            return Self::from_reader(open_input(filename)?, filename, threads);
+        }
+        let file = File::open(filename).map_err(|source| GzTestError::Open {
+            at: Location { file: filename.to_string(), member: 0, offset: 0 },
+            source,
+        })?;
+        let source = match map_input(&file) {
+            Some(map) => Source::Mapped(map),
+            None => Source::File(filename.to_string()),
+        };
+        Self::start(source, filename, threads)
+    }
+
+    /// シークできない入力を最後までメモリーに読み込み、`threads` 個のスレッドで展開を始める。
+    pub fn from_reader<R: Read>(mut input: R, filename: &str, threads: usize) -> Result<Self, GzTestError> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data).map_err(|source| GzTestError::Read {
+            at: Location { file: filename.to_string(), member: 0, offset: 0 },
+            source,
+        })?;
+        Self::start(Source::Memory(data.into()), filename, threads)
+    }
+
+    fn start(source: Source, filename: &str, threads: usize) -> Result<Self, GzTestError> {
+        let at = Location { file: filename.to_string(), member: 0, offset: 0 };
+        let mut candidates = Vec::new();
+        match source.bytes() {
+            // メモリー上のデータはコピーせずに調べる。
+            Some(data) => scan_headers_in(data, 0, |offset| {
+                candidates.push(offset);
+                true
+            }),
+            None => {
+                let input = source.open(0).map_err(|source| GzTestError::Open { at: at.clone(), source })?;
+                scan_headers(input, 0, |offset| {
+                    candidates.push(offset);
+                    true
+                })
+                .map_err(|source| GzTestError::Read { at, source })?;
+            }
+        }
+
+        let (jobs, job_receiver) = mpsc::channel::<u64>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let workers = (0..threads)
+            .map(|_| {
+                let jobs = Arc::clone(&job_receiver);
+                let results = result_sender.clone();
+                let source = source.clone();
+                let name = filename.to_string();
+                thread::spawn(move || loop {
+                    let job = jobs.lock().ok().and_then(|jobs| jobs.recv().ok());
+                    let Some(offset) = job else {
+                        return;
+                    };
+                    if results.send((offset, decode_member(&source, &name, offset))).is_err() {
+                        return;
+                    }
+                })
+            })
+            .collect();
+        Ok(ParallelDecoder {
+            source,
+            name: filename.to_string(),
+            candidates,
+            next_candidate: 0,
+            jobs: Some(jobs),
+            results,
+            workers,
+            pending: HashSet::new(),
+            done: HashMap::new(),
+            slots: threads * 2,
+            offset: 0,
+            member: 0,
+            data: Vec::new(),
+            data_pos: 0,
+            sequential: None,
+            finished: false,
+        })
+    }
+
+    // 空いている分だけ、次の候補の位置の展開を頼む。
+    fn dispatch(&mut self) {
+        while self.pending.len() + self.done.len() < self.slots {
+            let Some(&offset) = self.candidates.get(self.next_candidate) else {
+                return;
+            };
+            self.next_candidate += 1;
+            // 展開し終えたメンバーの中にある位置は、メンバーの先頭ではない。
+            if offset < self.offset {
+                continue;
+            }
+            if let Some(jobs) = &self.jobs {
+                if jobs.send(offset).is_ok() {
+                    self.pending.insert(offset);
+                }
+            }
+        }
+    }
+
+    fn receive(&mut self) -> io::Result<()> {
+        let (offset, decoded) = self
+            .results
+            .recv()
+            .map_err(|_| io::Error::other("a decoding thread stopped unexpectedly"))?;
+        self.pending.remove(&offset);
+        if offset >= self.offset {
+            self.done.insert(offset, decoded);
+        }
+        Ok(())
+    }
+
+    // 次のメンバーのデータを用意する。先読みした結果がなければ、その位置から順に展開するデコーダーを用意する。
+    fn next_member(&mut self) -> io::Result<()> {
+        loop {
+            self.dispatch();
+            match self.done.remove(&self.offset) {
+                Some(Decoded::Member { compressed_len, data }) => {
+                    self.data = data;
+                    self.data_pos = 0;
+                    self.offset += compressed_len;
+                    self.member += 1;
+                    let offset = self.offset;
+                    self.done.retain(|&candidate, _| candidate >= offset);
+                    return Ok(());
+                }
+                Some(Decoded::TooLarge | Decoded::Failed) => break,
+                None if self.candidates.binary_search(&self.offset).is_ok() && !self.pending.is_empty() => {
+                    self.receive()?;
+                }
+                None => break,
+            }
+        }
+        let input = self.source.open(self.offset)?;
+        let decoder = MultiMemberDecoder::new(input, &self.name)
+            .starting_at(self.offset, self.member)
+            .one_member();
+        self.sequential = Some(decoder);
+        Ok(())
+    }
+}
+
+impl Read for ParallelDecoder {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(decoder) = &mut self.sequential {
+                let n = match decoder.read(buf) {
+                    Ok(n) => n,
+                    Err(err) => {
+                        self.sequential = None;
+                        self.finished = true;
+                        return Err(err);
+                    }
+                };
+                if n > 0 {
+                    return Ok(n);
+                }
+                let end = decoder.location().offset;
+                self.sequential = None;
+                // メンバーがなかった時は入力の終わり。
+                if end == self.offset {
+                    self.finished = true;
+                    return Ok(0);
+                }
+                self.offset = end;
+                self.member += 1;
+                continue;
+            }
+            if self.data_pos < self.data.len() {
+                let n = (self.data.len() - self.data_pos).min(buf.len());
+                buf[..n].copy_from_slice(&self.data[self.data_pos..self.data_pos + n]);
+                self.data_pos += n;
+                return Ok(n);
+            }
+            if self.finished {
+                return Ok(0);
+            }
+            self.next_member()?;
+        }
+    }
+}
+
+impl Locate for ParallelDecoder {
+    fn location(&self) -> Location {
+        match &self.sequential {
+            Some(decoder) => decoder.location(),
+            None => Location { file: self.name.clone(), member: self.member.saturating_sub(1), offset: self.offset },
+        }
+    }
+}
+
+impl Drop for ParallelDecoder {
+    fn drop(&mut self) {
+        // 送り口を閉じると、各スレッドは今の展開を終えてから止まる。
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,38 @@
+/// gzipヘッダーの FNAME を、出力先のディレクトリの外を指さない1つのファイル名にする。
+///
+/// FNAME は圧縮した側が自由に書き込める値なので、`../` や絶対パスを含んでいても
+/// 最後の要素だけを使う。使える名前が残らなければ `None` を返す。
+pub fn safe_file_name(raw: &[u8]) -> Option<String> {
+    let name = String::from_utf8_lossy(raw);
+    let last = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = last.chars().map(|c| if c.is_control() { '_' } else { c }).collect();
+    match cleaned.as_str() {
+        "" | "." | ".." => None,
+        _ => Some(cleaned),
+    }
+}
+
+/// 入力ファイルのパスから、拡張子 `.gz` を除いたファイル名を返す。標準入力は `stdin` とする。
+pub fn input_stem(filename: &str) -> String {
+    if filename == "-" {
+        return "stdin".to_string();
+    }
+    let name = safe_file_name(filename.as_bytes()).unwrap_or_else(|| "output".to_string());
+    match name.strip_suffix(".gz") {
+        Some(stem) if !stem.is_empty() => stem.to_string(),
+        _ => name,
+    }
+}
+
+/// gunzip と同じく、圧縮されたファイルのパスから展開したファイルのパスを作る。
+/// `.gz` などの接尾辞を取り除き、`.tgz` と `.taz` は `.tar` にする。知らない接尾辞なら `None` を返す。
+pub fn gunzip_path(filename: &str) -> Option<String> {
+    const SUFFIXES: [(&str, &str); 8] =
+        [(".gz", ""), ("-gz", ""), (".z", ""), ("-z", ""), ("_z", ""), (".Z", ""), (".tgz", ".tar"), (".taz", ".tar")];
+    let name_start = filename.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    SUFFIXES.iter().find_map(|(suffix, replacement)| {
+        let stem = filename.strip_suffix(suffix)?;
+        // 接尾辞だけのファイル名からは名前を作れない。
+        (stem.len() > name_start).then(|| format!("{}{}", stem, replacement))
+    })
+}
@@ -0,0 +1,365 @@
+use std::io::Cursor;
+
+use flate2::{Crc, Decompress, FlushDecompress, Status};
+
+use crate::decoder::{Locate, MemberInfo};
+use crate::error::{GzTestError, Location};
+use crate::header::{self, Header, HeaderError, FCOMMENT, FEXTRA, FHCRC, FIXED_HEADER_LEN, FNAME, ID1, TRAILER_LEN};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Header,
+    Body,
+    Trailer,
+    Done,
+}
+
+// 途中までしか届いていないヘッダーを、次に読み直せる条件。届いた入力が len バイト以上になり、
+// nul の時はさらに新しく届いた部分に 0 があること (FNAME と FCOMMENT は 0 で終わる)。
+// 長い FNAME が少しずつ届く時に、毎回ヘッダーを先頭から読み直さないために使う。
+#[derive(Debug, Clone, Copy, Default)]
+struct HeaderWait {
+    len: usize,
+    nul: bool,
+}
+
+impl HeaderWait {
+    fn new(bytes: &[u8]) -> Self {
+        let wait = |len| HeaderWait { len, nul: false };
+        if bytes.len() < FIXED_HEADER_LEN {
+            return wait(FIXED_HEADER_LEN);
+        }
+        let flags = bytes[3];
+        let mut pos = FIXED_HEADER_LEN;
+        if flags & FEXTRA != 0 {
+            if bytes.len() < pos + 2 {
+                return wait(pos + 2);
+            }
+            pos += 2 + usize::from(u16::from_le_bytes([bytes[pos], bytes[pos + 1]]));
+            if bytes.len() < pos {
+                return wait(pos);
+            }
+        }
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                match bytes[pos..].iter().position(|&b| b == 0) {
+                    Some(i) => pos += i + 1,
+                    None => return HeaderWait { len: bytes.len() + 1, nul: true },
+                }
+            }
+        }
+        wait(if flags & FHCRC != 0 { pos + 2 } else { pos })
+    }
+
+    // 読み直せるかを調べる。0 を待っている時は、0 がないと分かった所の後ろまで待つ位置を進める。
+    fn ready(&mut self, bytes: &[u8]) -> bool {
+        if bytes.len() < self.len {
+            return false;
+        }
+        if self.nul && !bytes[self.len - 1..].contains(&0) {
+            self.len = bytes.len() + 1;
+            return false;
+        }
+        true
+    }
+}
+
+/// 届いた分だけの入力を渡して展開する、[`MultiMemberDecoder`](crate::decoder::MultiMemberDecoder) と同じ働きのデコーダー。
+///
+/// 入力を自分では読まないので、ソケットのように非同期に届くデータにも使える。
+/// `tokio` フィーチャーを有効にすれば、これを `AsyncRead` として包んだ `AsyncDecoder` も使える。
+/// `tokio` の `AsyncRead` からは次のように読んだ分を [`PushDecoder::feed`] に渡し、入力が終わったら
+/// [`PushDecoder::finish`] を呼ぶ。すべてのメンバーを展開し、CRC32 と ISIZE の確認、エラーの種類と位置も同じ。
+///
+/// ```ignore
+/// let mut decoder = PushDecoder::new("socket");
+/// let mut buf = vec![0; 64 * 1024];
+/// let mut out = Vec::new();
+/// loop {
+///     let n = socket.read(&mut buf).await?;
+///     if n == 0 {
+///         decoder.finish()?;
+///         break;
+///     }
+///     decoder.feed(&buf[..n], &mut out)?;
+///     body.write_all(&out).await?;
+///     out.clear();
+/// }
+/// ```
+pub struct PushDecoder {
+    name: String,
+    // 届いた入力。ヘッダーとトレーラーは揃うまでここに貯める。
+    buf: Vec<u8>,
+    // buf のうち、まだ使っていない部分の先頭。
+    start: usize,
+    // buf[start] の、圧縮された入力の先頭からの位置。
+    position: u64,
+    state: State,
+    member: u64,
+    member_start: u64,
+    header: Header,
+    header_wait: HeaderWait,
+    inflate: Decompress,
+    crc: Crc,
+    member_out: u64,
+    members: Vec<MemberInfo>,
+}
+
+impl PushDecoder {
+    /// `name` はエラーメッセージに使うファイル名。
+    pub fn new(name: &str) -> Self {
+        PushDecoder {
+            name: name.to_string(),
+            buf: Vec::new(),
+            start: 0,
+            position: 0,
+            state: State::Header,
+            member: 0,
+            member_start: 0,
+            header: Header::default(),
+            header_wait: HeaderWait::default(),
+            inflate: Decompress::new(false),
+            crc: Crc::new(),
+            member_out: 0,
+            members: Vec::new(),
+        }
+    }
+
+    /// 展開し終えたメンバーの情報を取り出す。
+    pub fn take_members(&mut self) -> Vec<MemberInfo> {
+        std::mem::take(&mut self.members)
+    }
+
+    fn location_at(&self, offset: u64) -> Location {
+        Location { file: self.name.clone(), member: self.member, offset }
+    }
+
+    fn pending(&self) -> &[u8] {
+        &self.buf[self.start..]
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.start += amt;
+        self.position += amt as u64;
+    }
+
+    // ヘッダーが揃っていれば読む。まだ足りなければ false を返す。
+    fn start_member(&mut self) -> Result<bool, GzTestError> {
+        let start = self.position;
+        match self.pending().first() {
+            None => return Ok(false),
+            Some(&byte) if byte != ID1 && self.member > 0 => {
+                return Err(GzTestError::TrailingGarbage { at: self.location() });
+            }
+            Some(_) => {}
+        }
+        if !self.header_wait.ready(&self.buf[self.start..]) {
+            return Ok(false);
+        }
+        let mut input = Cursor::new(&self.buf[self.start..]);
+        let header = match header::read_header(&mut input) {
+            Ok(header) => header,
+            Err(HeaderError::Eof) => {
+                self.header_wait = HeaderWait::new(self.pending());
+                return Ok(false);
+            }
+            Err(HeaderError::NotGzip) if self.member > 0 => {
+                return Err(GzTestError::TrailingGarbage { at: self.location_at(start) });
+            }
+            Err(HeaderError::NotGzip) => {
+                return Err(GzTestError::Header { at: self.location_at(start), reason: "not in gzip format" });
+            }
+            Err(HeaderError::Invalid(reason)) => {
+                return Err(GzTestError::Header { at: self.location_at(start), reason });
+            }
+            Err(HeaderError::Io(source)) => return Err(GzTestError::Read { at: self.location(), source }),
+        };
+        let len = input.position() as usize;
+        self.header = header;
+        self.header_wait = HeaderWait::default();
+        self.consume(len);
+        self.member_start = start;
+        self.inflate.reset(false);
+        self.crc.reset();
+        self.member_out = 0;
+        self.state = State::Body;
+        Ok(true)
+    }
+
+    // 届いている圧縮データをすべて展開して out に追加する。
+    fn read_body(&mut self, out: &mut Vec<u8>) -> Result<(), GzTestError> {
+        let mut buf = [0u8; 32 * 1024];
+        while self.state == State::Body && !self.pending().is_empty() {
+            let before_in = self.inflate.total_in();
+            let before_out = self.inflate.total_out();
+            let status = self.inflate.decompress(&self.buf[self.start..], &mut buf, FlushDecompress::None);
+            let consumed = (self.inflate.total_in() - before_in) as usize;
+            let produced = (self.inflate.total_out() - before_out) as usize;
+            self.consume(consumed);
+            let status = status.map_err(|err| GzTestError::Corrupt {
+                at: self.location(),
+                reason: err.message().unwrap_or("invalid deflate data").to_string(),
+            })?;
+            self.crc.update(&buf[..produced]);
+            self.member_out += produced as u64;
+            out.extend_from_slice(&buf[..produced]);
+            match status {
+                Status::StreamEnd => self.state = State::Trailer,
+                // 入力も出力も進まない時は壊れたデータとして扱い、無限ループを避ける。
+                _ if consumed == 0 && produced == 0 => {
+                    return Err(GzTestError::Corrupt {
+                        at: self.location(),
+                        reason: "no progress in deflate stream".to_string(),
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // トレーラーが揃っていれば読み、CRC32 と ISIZE を確かめる。まだ足りなければ false を返す。
+    fn finish_member(&mut self) -> Result<bool, GzTestError> {
+        let at = self.location();
+        let trailer = match header::read_trailer(&mut self.pending()) {
+            Ok(trailer) => trailer,
+            Err(_) => return Ok(false),
+        };
+        if trailer.crc32 != self.crc.sum() {
+            return Err(GzTestError::Crc { at, expected: trailer.crc32, actual: self.crc.sum() });
+        }
+        if trailer.isize != self.member_out as u32 {
+            return Err(GzTestError::Isize { at, expected: trailer.isize, actual: self.member_out as u32 });
+        }
+        self.consume(TRAILER_LEN);
+        self.members.push(MemberInfo {
+            index: self.member,
+            offset: self.member_start,
+            compressed_len: self.position - self.member_start,
+            header: std::mem::take(&mut self.header),
+            trailer,
+            uncompressed_len: self.member_out,
+        });
+        self.member += 1;
+        self.state = State::Header;
+        Ok(true)
+    }
+
+    fn step(&mut self, out: &mut Vec<u8>) -> Result<(), GzTestError> {
+        loop {
+            let progress = match self.state {
+                State::Header => self.start_member()?,
+                State::Body => {
+                    self.read_body(out)?;
+                    self.state != State::Body
+                }
+                State::Trailer => self.finish_member()?,
+                State::Done => false,
+            };
+            if !progress {
+                return Ok(());
+            }
+        }
+    }
+
+    /// 届いた入力を渡し、展開できた分を `out` に追加する。エラーの後は何もしない。
+    pub fn feed(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<(), GzTestError> {
+        if self.state == State::Done {
+            return Ok(());
+        }
+        self.buf.drain(..self.start);
+        self.start = 0;
+        self.buf.extend_from_slice(input);
+        self.step(out).inspect_err(|_| self.state = State::Done)
+    }
+
+    /// 入力が終わったことを伝える。メンバーの途中で終わっていれば [`GzTestError::Truncated`] を返す。
+    pub fn finish(&mut self) -> Result<(), GzTestError> {
+        let complete = self.state == State::Header && self.pending().is_empty() && self.member > 0;
+        if self.state == State::Done || complete {
+            self.state = State::Done;
+            return Ok(());
+        }
+        self.state = State::Done;
+        // ヘッダーやトレーラーの途中で終わった入力も、空の入力も gzip として不完全。
+        Err(GzTestError::Truncated { at: self.location_at(self.position + self.pending().len() as u64) })
+    }
+}
+
+impl Locate for PushDecoder {
+    fn location(&self) -> Location {
+        self.location_at(self.position)
+    }
+}
+
+/// [`PushDecoder`] で展開したテキストを、[`Lines`](crate::lines::Lines) と同じく行に分ける。
+///
+/// 行末の `"\n"` と `"\r\n"` を取り除き、UTF-8 でない行は1から数えた行番号と合わせて [`GzTestError::Utf8`] にする。
+/// 非同期のコードでは、読んだ分を [`PushLines::feed`] に渡して揃った行から順に返せば、`lines()` と同じ行のストリームになる。
+pub struct PushLines {
+    decoder: PushDecoder,
+    data: Vec<u8>,
+    // data のうち、まだ行として返していない部分の先頭。
+    start: usize,
+    counter_lines: u64,
+    // エラーを返した後と finish の後は、PushDecoder と同じく何もしない。
+    done: bool,
+}
+
+impl PushLines {
+    /// `name` はエラーメッセージに使うファイル名。
+    pub fn new(name: &str) -> Self {
+        PushLines { decoder: PushDecoder::new(name), data: Vec::new(), start: 0, counter_lines: 0, done: false }
+    }
+
+    /// 内側のデコーダー。
+    pub fn decoder(&mut self) -> &mut PushDecoder {
+        &mut self.decoder
+    }
+
+    fn push_line(&mut self, end: usize, lines: &mut Vec<String>) -> Result<(), GzTestError> {
+        let mut line = &self.data[self.start..end];
+        self.start = end;
+        self.counter_lines += 1;
+        if let Some(rest) = line.strip_suffix(b"\n") {
+            line = rest.strip_suffix(b"\r").unwrap_or(rest);
+        }
+        let line = std::str::from_utf8(line)
+            .map_err(|_| GzTestError::Utf8 { at: self.decoder.location(), line: self.counter_lines })?;
+        lines.push(line.to_string());
+        Ok(())
+    }
+
+    /// 届いた入力を渡し、揃った行を `lines` に追加する。エラーの後は何もしない。
+    pub fn feed(&mut self, input: &[u8], lines: &mut Vec<String>) -> Result<(), GzTestError> {
+        if self.done {
+            return Ok(());
+        }
+        self.feed_lines(input, lines).inspect_err(|_| self.done = true)
+    }
+
+    fn feed_lines(&mut self, input: &[u8], lines: &mut Vec<String>) -> Result<(), GzTestError> {
+        self.data.drain(..self.start);
+        self.start = 0;
+        let searched = self.data.len();
+        self.decoder.feed(input, &mut self.data)?;
+        let mut from = searched;
+        while let Some(i) = self.data[from..].iter().position(|&b| b == b'\n') {
+            self.push_line(from + i + 1, lines)?;
+            from += i + 1;
+        }
+        Ok(())
+    }
+
+    /// 入力が終わったことを伝え、`"\n"` で終わらない最後の行があれば `lines` に追加する。エラーの後は何もしない。
+    pub fn finish(&mut self, lines: &mut Vec<String>) -> Result<(), GzTestError> {
+        if std::mem::replace(&mut self.done, true) {
+            return Ok(());
+        }
+        self.decoder.finish()?;
+        if self.start < self.data.len() {
+            self.push_line(self.data.len(), lines)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use std::env;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::process;
+
+use crate::decoder::MultiMemberDecoder;
+use crate::error::GzTestError;
+use crate::header::{scan_headers, TRAILER_LEN};
+use crate::{open_seekable, ReadSeek};
+
+/// `--recover` で読めなかった部分があった時の終了コード。
+pub const EXIT_RECOVERED: u8 = 13;
+
+// from 以降で、メンバーのヘッダーの先頭に見える最初の位置を探す。
+fn scan_header(input: &mut dyn ReadSeek, from: u64) -> io::Result<Option<u64>> {
+    input.seek(SeekFrom::Start(from))?;
+    let mut first = None;
+    scan_headers(input, from, |offset| {
+        first = Some(offset);
+        false
+    })?;
+    Ok(first)
+}
+
+// offset から始まるメンバーを試しに展開し、最後まで (または入力の終わりまで) 読めるかを調べる。
+// 圧縮データの中に偶然ヘッダーと同じバイト列があっても、それを次のメンバーと取り違えないようにする。
+fn member_decodes(input: &mut dyn ReadSeek, filename: &str, offset: u64, member: u64) -> io::Result<bool> {
+    input.seek(SeekFrom::Start(offset))?;
+    let mut decoder = MultiMemberDecoder::new(BufReader::new(input), filename).starting_at(offset, member);
+    match decoder.next_member(&mut io::sink()) {
+        Ok(_) => Ok(true),
+        Err(err) => match GzTestError::from_io(err) {
+            Ok(GzTestError::Truncated { .. }) => Ok(true),
+            Ok(GzTestError::Read { source, .. }) => Err(source),
+            Ok(_) => Ok(false),
+            Err(err) => Err(err),
+        },
+    }
+}
+
+// from 以降で、次のメンバーとして展開できる位置を探す。
+fn find_next_member(input: &mut dyn ReadSeek, filename: &str, from: u64, member: u64) -> io::Result<Option<u64>> {
+    let mut pos = from;
+    while let Some(candidate) = scan_header(input, pos)? {
+        if member_decodes(input, filename, candidate, member)? {
+            return Ok(Some(candidate));
+        }
+        pos = candidate + 1;
+    }
+    Ok(None)
+}
+
+// メモリーに置くメンバーの大きさの上限。これより大きなメンバーは一時ファイルに書き出しておく。
+const SPILL_LIMIT: usize = 64 << 20;
+
+// 1つのメンバーを展開したデータ。CRC32 と ISIZE を確かめるまで出力には書き出さない。
+struct MemberBuffer {
+    memory: Vec<u8>,
+    spill: Option<(File, PathBuf)>,
+}
+
+impl MemberBuffer {
+    fn new() -> Self {
+        MemberBuffer { memory: Vec::new(), spill: None }
+    }
+
+    // 一時ファイルを作る。Unix では開いたまま名前を消すので、プロセスが止まっても残らない。
+    fn create_spill() -> io::Result<(File, PathBuf)> {
+        let mut attempt = 0;
+        loop {
+            let path = env::temp_dir().join(format!(".gzip_test-recover.{}.{}.tmp", process::id(), attempt));
+            match OpenOptions::new().read(true).write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    if cfg!(unix) {
+                        let _ = fs::remove_file(&path);
+                    }
+                    return Ok((file, path));
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // 貯めたデータを out に書き出し、次のメンバーのために空にする。
+    fn write_to<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        if let Some((file, _)) = &mut self.spill {
+            file.seek(SeekFrom::Start(0))?;
+            io::copy(file, out)?;
+        }
+        out.write_all(&self.memory)?;
+        self.clear()
+    }
+
+    // 貯めたデータを捨てる。
+    fn clear(&mut self) -> io::Result<()> {
+        self.memory.clear();
+        if let Some((file, _)) = &mut self.spill {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for MemberBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.memory.len() + buf.len() > SPILL_LIMIT {
+            if self.spill.is_none() {
+                self.spill = Some(Self::create_spill()?);
+            }
+            let (file, _) = self.spill.as_mut().expect("created above");
+            file.write_all(&self.memory)?;
+            self.memory.clear();
+            file.write_all(buf)?;
+        } else {
+            self.memory.extend_from_slice(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for MemberBuffer {
+    fn drop(&mut self) {
+        if let Some((_, path)) = &self.spill {
+            if !cfg!(unix) {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+/// 壊れたメンバーを飛ばしながら展開したバイト列を書き出す。
+///
+/// 各メンバーは展開し終えて CRC32 と ISIZE を確かめてから書き出すので、壊れたメンバーのデータは書き出さない。
+/// メンバーが壊れていた時は、その先で次のメンバーのヘッダーを探して展開を続け、
+/// 飛ばしたバイト範囲を警告として表示する。途中で終わっている最後のメンバーは、展開できた部分を警告と合わせて書き出す。
+/// すべて問題なく読めた時は true を返す。
+pub fn recover<W: Write>(filename: &str, out: &mut W) -> Result<bool, Box<dyn Error>> {
+    let mut input = open_seekable(filename)?;
+    let mut buffer = MemberBuffer::new();
+    let mut pos = 0;
+    let mut member = 0;
+    let mut clean = true;
+    loop {
+        input.seek(SeekFrom::Start(pos))?;
+        let mut decoder = MultiMemberDecoder::new(BufReader::new(&mut input), filename).starting_at(pos, member);
+        let err = loop {
+            match decoder.next_member(&mut buffer) {
+                Ok(Some(_)) => buffer.write_to(out)?,
+                Ok(None) => return Ok(clean),
+                Err(err) => break err,
+            }
+        };
+        let failed_start = decoder.member_start();
+        drop(decoder);
+        // 書き込みのエラーはそのまま返す。
+        let err = GzTestError::from_io(err)?;
+        member = err.location().member;
+        clean = false;
+        let skip_from = match err {
+            // メンバーの境界は正しいので、データを捨てて次のメンバーに進む。
+            GzTestError::Crc { ref at, .. } | GzTestError::Isize { ref at, .. } => {
+                buffer.clear()?;
+                pos = at.offset + TRAILER_LEN as u64;
+                eprintln!("gzip_test: warning: skipped bytes {}..{} of '{}': {}", failed_start, pos, filename, err);
+                member += 1;
+                continue;
+            }
+            GzTestError::Truncated { .. } => {
+                buffer.write_to(out)?;
+                eprintln!("gzip_test: warning: {}; the partial data was written", err);
+                return Ok(false);
+            }
+            GzTestError::TrailingGarbage { ref at } => at.offset,
+            GzTestError::Header { .. } | GzTestError::Corrupt { .. } => {
+                buffer.clear()?;
+                member += 1;
+                failed_start
+            }
+            _ => return Err(Box::new(err)),
+        };
+        match find_next_member(input.as_mut(), filename, skip_from + 1, member)? {
+            Some(next) => {
+                eprintln!("gzip_test: warning: skipped bytes {}..{} of '{}': {}", skip_from, next, filename, err);
+                pos = next;
+            }
+            None => {
+                let end = input.seek(SeekFrom::End(0))?;
+                eprintln!("gzip_test: warning: skipped bytes {}..{} of '{}': {}", skip_from, end, filename, err);
+                return Ok(false);
+            }
+        }
+    }
+}
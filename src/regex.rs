@@ -0,0 +1,771 @@
+use std::fmt;
+
+// 繰り返しの回数の上限。{m,n} は命令を複製して実現するので、大きすぎるとプログラムが膨らむ。
+const MAX_REPEAT: u32 = 1000;
+
+// 命令の数の上限。繰り返しを入れ子にすると回数の積だけ複製されるので、全体でも抑える。
+const MAX_PROGRAM_LEN: usize = 100_000;
+
+/// パターンの誤り。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError(String);
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+fn error<T>(message: impl Into<String>) -> Result<T, PatternError> {
+    Err(PatternError(message.into()))
+}
+
+// 文字クラス。ranges は両端を含む文字の範囲。
+#[derive(Debug, Clone)]
+struct Class {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+}
+
+impl Class {
+    fn contains(&self, c: char, ignore_case: bool) -> bool {
+        let in_ranges = |c: char| self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+        // 大文字と小文字を区別しない時は、どちらかが範囲にあれば含む。否定はその後で取る。
+        let found = in_ranges(c) || (ignore_case && (in_ranges(fold(c)) || c.to_uppercase().any(in_ranges)));
+        found != self.negated
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    LineStart,
+    LineEnd,
+    WordBoundary(bool),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat { node: Box<Node>, min: u32, max: Option<u32> },
+}
+
+// \d \w \s とその否定、POSIX の [:name:] の範囲。
+fn perl_class(c: char) -> Option<Class> {
+    let (negated, ranges) = match c {
+        'd' | 'D' => (c == 'D', vec![('0', '9')]),
+        'w' | 'W' => (c == 'W', vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]),
+        's' | 'S' => (c == 'S', vec![('\t', '\r'), (' ', ' ')]),
+        _ => return None,
+    };
+    Some(Class { negated, ranges })
+}
+
+fn posix_class(name: &str) -> Option<Vec<(char, char)>> {
+    Some(match name {
+        "alpha" => vec![('A', 'Z'), ('a', 'z')],
+        "digit" => vec![('0', '9')],
+        "alnum" => vec![('0', '9'), ('A', 'Z'), ('a', 'z')],
+        "upper" => vec![('A', 'Z')],
+        "lower" => vec![('a', 'z')],
+        "space" => vec![('\t', '\r'), (' ', ' ')],
+        "blank" => vec![('\t', '\t'), (' ', ' ')],
+        "punct" => vec![('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+        "xdigit" => vec![('0', '9'), ('A', 'F'), ('a', 'f')],
+        _ => return None,
+    })
+}
+
+// grep -E と同じ拡張正規表現の構文を読む。
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn alternation(&mut self) -> Result<Node, PatternError> {
+        let mut branches = vec![self.concatenation()?];
+        while self.chars.peek() == Some(&'|') {
+            self.chars.next();
+            branches.push(self.concatenation()?);
+        }
+        Ok(if branches.len() == 1 { branches.pop().expect("one branch") } else { Node::Alternate(branches) })
+    }
+
+    fn concatenation(&mut self) -> Result<Node, PatternError> {
+        let mut nodes = Vec::new();
+        while let Some(&c) = self.chars.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().expect("one node"),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn atom(&mut self) -> Result<Node, PatternError> {
+        let c = self.chars.next().expect("peeked");
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::LineStart,
+            '$' => Node::LineEnd,
+            '(' => {
+                let node = self.alternation()?;
+                if self.chars.next() != Some(')') {
+                    return error("unmatched '('");
+                }
+                node
+            }
+            '[' => Node::Class(self.class()?),
+            '\\' => self.escape()?,
+            '*' | '+' | '?' => return error(format!("'{}' has nothing to repeat", c)),
+            c => Node::Char(c),
+        })
+    }
+
+    fn escape(&mut self) -> Result<Node, PatternError> {
+        let Some(c) = self.chars.next() else {
+            return error("trailing backslash");
+        };
+        Ok(match c {
+            'b' => Node::WordBoundary(true),
+            'B' => Node::WordBoundary(false),
+            't' => Node::Char('\t'),
+            'n' => Node::Char('\n'),
+            c => match perl_class(c) {
+                Some(class) => Node::Class(class),
+                None if c.is_ascii_alphanumeric() => return error(format!("unknown escape '\\{}'", c)),
+                None => Node::Char(c),
+            },
+        })
+    }
+
+    fn class(&mut self) -> Result<Class, PatternError> {
+        let mut class = Class { negated: false, ranges: Vec::new() };
+        if self.chars.peek() == Some(&'^') {
+            self.chars.next();
+            class.negated = true;
+        }
+        let mut first = true;
+        loop {
+            let Some(c) = self.chars.next() else {
+                return error("unmatched '['");
+            };
+            let lo = match c {
+                // 先頭の ']' は文字として扱う。
+                ']' if !first => return Ok(class),
+                '[' if self.chars.peek() == Some(&':') => {
+                    self.chars.next();
+                    let name: String = self.chars.by_ref().take_while(|&c| c != ':').collect();
+                    if self.chars.next() != Some(']') {
+                        return error("unmatched '[:'");
+                    }
+                    let ranges = posix_class(&name).ok_or_else(|| PatternError(format!("unknown class '[:{}:]'", name)))?;
+                    class.ranges.extend(ranges);
+                    first = false;
+                    continue;
+                }
+                '\\' => {
+                    let Some(c) = self.chars.next() else {
+                        return error("unmatched '['");
+                    };
+                    if let Some(inner) = perl_class(c).filter(|inner| !inner.negated) {
+                        class.ranges.extend(inner.ranges);
+                        first = false;
+                        continue;
+                    }
+                    match c {
+                        't' => '\t',
+                        'n' => '\n',
+                        c => c,
+                    }
+                }
+                c => c,
+            };
+            first = false;
+            let mut lookahead = self.chars.clone();
+            if lookahead.next() == Some('-') && lookahead.peek().is_some_and(|&hi| hi != ']') {
+                self.chars.next();
+                let hi = self.chars.next().expect("peeked");
+                if hi < lo {
+                    return error(format!("invalid range '{}-{}'", lo, hi));
+                }
+                class.ranges.push((lo, hi));
+            } else {
+                class.ranges.push((lo, lo));
+            }
+        }
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let mut digits = String::new();
+        while let Some(&c) = self.chars.peek().filter(|c| c.is_ascii_digit()) {
+            digits.push(c);
+            self.chars.next();
+        }
+        digits.parse().ok()
+    }
+
+    fn quantified(&mut self, mut node: Node) -> Result<Node, PatternError> {
+        loop {
+            let (min, max) = match self.chars.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => {
+                    self.chars.next();
+                    let min = self.number().unwrap_or(0);
+                    let max = if self.chars.peek() == Some(&',') {
+                        self.chars.next();
+                        self.number()
+                    } else {
+                        Some(min)
+                    };
+                    if self.chars.peek() != Some(&'}') {
+                        return error("unmatched '{'");
+                    }
+                    if max.is_some_and(|max| max < min) || min.max(max.unwrap_or(0)) > MAX_REPEAT {
+                        return error(format!("invalid repetition count (0 to {})", MAX_REPEAT));
+                    }
+                    (min, max)
+                }
+                _ => return Ok(node),
+            };
+            self.chars.next();
+            node = Node::Repeat { node: Box::new(node), min, max };
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    LineStart,
+    LineEnd,
+    WordBoundary(bool),
+    // -w の一致の前後: 直前 (NoWordBefore) または直後 (NoWordAfter) が単語の文字でない。
+    NoWordBefore,
+    NoWordAfter,
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+fn compile(node: &Node, prog: &mut Vec<Inst>) -> Result<(), PatternError> {
+    // 入れ子の繰り返しで膨らむ前に止める。どの命令もこの確認の後に高々数個ずつしか加えない。
+    if prog.len() > MAX_PROGRAM_LEN {
+        return error(format!("pattern too large (more than {} instructions once repetitions are expanded)", MAX_PROGRAM_LEN));
+    }
+    match node {
+        Node::Empty => {}
+        Node::Char(c) => prog.push(Inst::Char(*c)),
+        Node::Any => prog.push(Inst::Any),
+        Node::Class(class) => prog.push(Inst::Class(class.clone())),
+        Node::LineStart => prog.push(Inst::LineStart),
+        Node::LineEnd => prog.push(Inst::LineEnd),
+        Node::WordBoundary(expected) => prog.push(Inst::WordBoundary(*expected)),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, prog)?;
+            }
+        }
+        Node::Alternate(branches) => {
+            // split L1, next; L1: 枝; jump end; next: 残りの枝 ...
+            let mut jumps = Vec::new();
+            for (i, branch) in branches.iter().enumerate() {
+                if i + 1 < branches.len() {
+                    let split = prog.len();
+                    prog.push(Inst::Split(split + 1, 0));
+                    compile(branch, prog)?;
+                    jumps.push(prog.len());
+                    prog.push(Inst::Jump(0));
+                    let next = prog.len();
+                    prog[split] = Inst::Split(split + 1, next);
+                } else {
+                    compile(branch, prog)?;
+                }
+            }
+            let end = prog.len();
+            for jump in jumps {
+                prog[jump] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat { node, min, max } => {
+            for _ in 0..*min {
+                compile(node, prog)?;
+            }
+            match max {
+                None => {
+                    let split = prog.len();
+                    prog.push(Inst::Split(split + 1, 0));
+                    compile(node, prog)?;
+                    prog.push(Inst::Jump(split));
+                    let end = prog.len();
+                    prog[split] = Inst::Split(split + 1, end);
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(prog.len());
+                        prog.push(Inst::Split(0, 0));
+                        compile(node, prog)?;
+                    }
+                    let end = prog.len();
+                    for split in splits {
+                        prog[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_word(c: Option<char>) -> bool {
+    c.is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+// 大文字と小文字を区別しない時の比較に使う小文字。
+fn fold(c: char) -> char {
+    if c.is_ascii() {
+        return c.to_ascii_lowercase();
+    }
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+// 行のバイト列の先頭の文字とそのバイト数。行を String にコピーせずに照らし合わせるために使う。
+// UTF-8 でない部分は String::from_utf8_lossy と同じく、壊れた並びごとに U+FFFD 1文字として読む。
+fn decode(text: &[u8]) -> Option<(char, usize)> {
+    let &first = text.first()?;
+    if first.is_ascii() {
+        return Some((char::from(first), 1));
+    }
+    // UTF-8 の1文字は4バイトまでで、壊れた並びも3バイトまでなので、先頭の4バイトだけを調べれば足りる。
+    let chunk = text[..text.len().min(4)].utf8_chunks().next()?;
+    Some(match chunk.valid().chars().next() {
+        Some(c) => (c, c.len_utf8()),
+        None => (char::REPLACEMENT_CHARACTER, chunk.invalid().len()),
+    })
+}
+
+// text[..pos] の最後の文字。pos は decode で読み進めた時の文字の境界であること。
+// 単語の文字か、行頭かを調べるためだけに使うので、壊れた並びは U+FFFD とだけ分かればよい。
+fn char_before(text: &[u8], pos: usize) -> Option<char> {
+    let last = pos.checked_sub(1)?;
+    if text[last].is_ascii() {
+        return Some(char::from(text[last]));
+    }
+    let single = (pos.saturating_sub(4)..last + 1).rev().find_map(|start| {
+        let s = std::str::from_utf8(&text[start..pos]).ok()?;
+        let mut chars = s.chars();
+        chars.next().filter(|_| chars.next().is_none())
+    });
+    Some(single.unwrap_or(char::REPLACEMENT_CHARACTER))
+}
+
+// 一致の最初の文字の UTF-8 の先頭のバイトになりうるバイトの表。どのバイトからでも始まりうる時は None を返す。
+// 途中の一致がない間はこの表にないバイトを読み飛ばせるので、固定の文字列で始まるパターンを速く調べられる。
+fn first_bytes(prog: &[Inst], ignore_case: bool) -> Option<Box<[bool; 256]>> {
+    let mut table = Box::new([false; 256]);
+    let mut seen = vec![false; prog.len()];
+    let mut stack = vec![0];
+    while let Some(pc) = stack.pop() {
+        if std::mem::replace(&mut seen[pc], true) {
+            continue;
+        }
+        match &prog[pc] {
+            // U+FFFD は壊れた並びにも一致するので、どのバイトからでも始まりうる。
+            Inst::Char(c) if *c != char::REPLACEMENT_CHARACTER => {
+                let mut utf8 = [0u8; 4];
+                table[usize::from(c.encode_utf8(&mut utf8).as_bytes()[0])] = true;
+                if ignore_case {
+                    table[usize::from(c.to_ascii_uppercase() as u8)] |= c.is_ascii();
+                    // K (U+212A) のように、小文字にすると ASCII になる文字もある。
+                    table[0xc2..=0xf4].fill(true);
+                }
+            }
+            Inst::Char(_) | Inst::Any | Inst::Class(_) | Inst::Match => return None,
+            Inst::Jump(to) => stack.push(*to),
+            Inst::Split(first, second) => stack.extend([*first, *second]),
+            Inst::LineStart | Inst::LineEnd | Inst::WordBoundary(_) | Inst::NoWordBefore | Inst::NoWordAfter => {
+                stack.push(pc + 1)
+            }
+        }
+    }
+    Some(table)
+}
+
+// 一致の先頭に必ず来る固定の文字列の UTF-8。プログラムの最初の命令は一致ごとに必ず順に通るので、
+// 先頭の位置の検査の後に続く Char の命令をつなげたものになる。大文字と小文字を区別しない時は使わない。
+fn literal_prefix(prog: &[Inst], ignore_case: bool) -> Vec<u8> {
+    if ignore_case {
+        return Vec::new();
+    }
+    let start = prog.iter().position(|inst| !matches!(inst, Inst::LineStart | Inst::NoWordBefore)).unwrap_or(prog.len());
+    let mut prefix = String::new();
+    for inst in &prog[start..] {
+        match inst {
+            Inst::Char(c) if *c != char::REPLACEMENT_CHARACTER => prefix.push(*c),
+            _ => break,
+        }
+    }
+    prefix.into_bytes()
+}
+
+/// 照らし合わせ方の設定。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MatchOptions {
+    /// 大文字と小文字を区別しない。
+    pub ignore_case: bool,
+    /// grep -w と同じく、直前と直後が単語の文字 (英数字と '_') でない一致だけを探す。
+    pub whole_words: bool,
+}
+
+/// 行が一致するかだけを調べる正規表現。
+///
+/// 構文は grep -E と同じ拡張正規表現 (`|`, `()`, `* + ? {m,n}`, `.`, `[...]`, `[[:alpha:]]`, `^`, `$`) に、
+/// `\d \w \s` とその否定、`\b \B` を加えたもの。後方参照はない。
+/// Thompson の NFA を Pike VM で同時に進めるので、パターンによらず行の長さに比例した時間で調べ終わる。
+/// 行は [`Regex::matcher`] で作った [`Matcher`] で調べる。
+#[derive(Debug, Clone)]
+pub struct Regex {
+    prog: Vec<Inst>,
+    ignore_case: bool,
+    first_bytes: Option<Box<[bool; 256]>>,
+    prefix: Vec<u8>,
+}
+
+impl Regex {
+    /// 正規表現のパターンを読む。繰り返しを展開した命令が多すぎるパターンも誤りにする。
+    pub fn new(pattern: &str, options: MatchOptions) -> Result<Self, PatternError> {
+        let mut parser = Parser { chars: pattern.chars().peekable() };
+        let node = parser.alternation()?;
+        if parser.chars.next().is_some() {
+            return error("unmatched ')'");
+        }
+        Self::from_node(&node, options)
+    }
+
+    /// 特別な意味を持つ文字のない、固定の文字列として読む。
+    pub fn literal(text: &str, options: MatchOptions) -> Result<Self, PatternError> {
+        Self::from_node(&Node::Concat(text.chars().map(Node::Char).collect()), options)
+    }
+
+    fn from_node(node: &Node, options: MatchOptions) -> Result<Self, PatternError> {
+        let mut prog = Vec::new();
+        if options.whole_words {
+            prog.push(Inst::NoWordBefore);
+        }
+        compile(node, &mut prog)?;
+        if options.whole_words {
+            prog.push(Inst::NoWordAfter);
+        }
+        prog.push(Inst::Match);
+        if options.ignore_case {
+            for inst in &mut prog {
+                if let Inst::Char(c) = inst {
+                    *c = fold(*c);
+                }
+            }
+        }
+        let first_bytes = first_bytes(&prog, options.ignore_case);
+        let prefix = literal_prefix(&prog, options.ignore_case);
+        Ok(Regex { prog, ignore_case: options.ignore_case, first_bytes, prefix })
+    }
+
+    /// 行を調べるための [`Matcher`] を作る。
+    pub fn matcher(&self) -> Matcher<'_> {
+        Matcher {
+            regex: self,
+            current: Threads::new(self.prog.len()),
+            next: Threads::new(self.prog.len()),
+            stack: Vec::new(),
+        }
+    }
+
+    fn char_matches(&self, inst: &Inst, c: char) -> bool {
+        match inst {
+            Inst::Char(expected) if self.ignore_case => fold(c) == *expected,
+            Inst::Char(expected) => c == *expected,
+            Inst::Any => true,
+            Inst::Class(class) => class.contains(c, self.ignore_case),
+            _ => false,
+        }
+    }
+
+}
+
+// pc から空遷移でたどれる命令を threads に加える。Match に着けば true を返す。
+// stack は呼び出しごとに作らずに使い回す作業領域。
+fn add_thread(prog: &[Inst], threads: &mut Threads, stack: &mut Vec<usize>, pc: usize, prev: Option<char>, next: Option<char>) -> bool {
+    stack.clear();
+    stack.push(pc);
+    while let Some(pc) = stack.pop() {
+        if !threads.insert(pc) {
+            continue;
+        }
+        match &prog[pc] {
+            Inst::Match => return true,
+            Inst::Jump(to) => stack.push(*to),
+            Inst::Split(first, second) => {
+                stack.push(*second);
+                stack.push(*first);
+            }
+            Inst::LineStart if prev.is_none() => stack.push(pc + 1),
+            Inst::LineEnd if next.is_none() => stack.push(pc + 1),
+            Inst::WordBoundary(expected) if (is_word(prev) != is_word(next)) == *expected => stack.push(pc + 1),
+            Inst::NoWordBefore if !is_word(prev) => stack.push(pc + 1),
+            Inst::NoWordAfter if !is_word(next) => stack.push(pc + 1),
+            _ => {}
+        }
+    }
+    false
+}
+
+/// [`Regex`] で行を1つずつ調べる。スレッドの集合を行ごとに作り直さずに使い回す。
+pub struct Matcher<'a> {
+    regex: &'a Regex,
+    current: Threads,
+    next: Threads,
+    stack: Vec<usize>,
+}
+
+impl Matcher<'_> {
+    /// 行のバイト列 `text` のどこかに一致する部分があるかを調べる。
+    /// UTF-8 でない部分は U+FFFD として照らし合わせる。
+    pub fn is_match(&mut self, text: &[u8]) -> bool {
+        let Matcher { regex, current, next, stack } = self;
+        current.clear();
+        next.clear();
+        let mut pos = 0;
+        let mut prev = None;
+        let mut c = decode(text);
+        loop {
+            // 途中の一致がなければ、一致を始められる次の位置まで読み飛ばす。
+            if let Some(first_bytes) = regex.first_bytes.as_deref().filter(|_| current.list.is_empty()) {
+                let rest = &text[pos..];
+                let start = (0..rest.len())
+                    .filter(|&at| first_bytes[usize::from(rest[at])])
+                    .find(|&at| rest[at..].starts_with(&regex.prefix));
+                match start {
+                    Some(0) => {}
+                    Some(skip) => {
+                        pos += skip;
+                        prev = char_before(text, pos);
+                        c = decode(&text[pos..]);
+                    }
+                    None => return false,
+                }
+            }
+            // どの位置からでも一致を始められる。
+            if add_thread(&regex.prog, current, stack, 0, prev, c.map(|(c, _)| c)) {
+                return true;
+            }
+            let Some((ch, len)) = c else {
+                return false;
+            };
+            pos += len;
+            let after = decode(&text[pos..]);
+            for &pc in &current.list {
+                if regex.char_matches(&regex.prog[pc], ch)
+                    && add_thread(&regex.prog, next, stack, pc + 1, Some(ch), after.map(|(c, _)| c))
+                {
+                    return true;
+                }
+            }
+            std::mem::swap(current, next);
+            next.clear();
+            prev = Some(ch);
+            c = after;
+        }
+    }
+}
+
+// 同じ位置で進めているスレッドの集合。同じ命令を2回加えないことで、空のループでも止まる。
+struct Threads {
+    list: Vec<usize>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Self {
+        Threads { list: Vec::with_capacity(len), seen: vec![false; len] }
+    }
+
+    fn insert(&mut self, pc: usize) -> bool {
+        if self.seen[pc] {
+            return false;
+        }
+        self.seen[pc] = true;
+        self.list.push(pc);
+        true
+    }
+
+    fn clear(&mut self) {
+        for &pc in &self.list {
+            self.seen[pc] = false;
+        }
+        self.list.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, options: MatchOptions, text: &str) -> bool {
+        Regex::new(pattern, options).unwrap().matcher().is_match(text.as_bytes())
+    }
+
+    // パターンが yes の行すべてに一致し、no の行のどれにも一致しないことを確かめる。
+    fn check(pattern: &str, options: MatchOptions, yes: &[&str], no: &[&str]) {
+        for text in yes {
+            assert!(matches(pattern, options, text), "{:?} should match {:?}", pattern, text);
+        }
+        for text in no {
+            assert!(!matches(pattern, options, text), "{:?} should not match {:?}", pattern, text);
+        }
+    }
+
+    fn plain(pattern: &str, yes: &[&str], no: &[&str]) {
+        check(pattern, MatchOptions::default(), yes, no);
+    }
+
+    fn pattern_error(pattern: &str) -> String {
+        Regex::new(pattern, MatchOptions::default()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn anchors() {
+        plain("^abc", &["abc", "abcdef"], &["xabc", ""]);
+        plain("abc$", &["abc", "xxabc"], &["abcx"]);
+        plain("^$", &[""], &["a"]);
+        plain("^a|b$", &["ax", "xb"], &["xa", "bx"]);
+    }
+
+    #[test]
+    fn word_boundaries() {
+        plain(r"\bcat\b", &["cat", "a cat.", "(cat)"], &["cats", "bobcat", "cat_"]);
+        plain(r"\Bcat", &["bobcat"], &["cat", "a cat"]);
+    }
+
+    #[test]
+    fn classes() {
+        plain("[a-c]x", &["ax", "cx"], &["dx", "x"]);
+        plain("[^a-c]x", &["dx", "-x"], &["ax", "x"]);
+        plain("[]a]", &["]", "a"], &["b"]);
+        plain("[a-]", &["-"], &["b"]);
+        plain(r"\d+\.\d", &["3.1"], &["3x1", ".1"]);
+        plain(r"\w\s\W", &["a !"], &["a b", "ab!"]);
+        plain("[[:digit:][:upper:]]z", &["7z", "Qz"], &["qz"]);
+        plain("[[:punct:]]", &["a,b"], &["ab"]);
+        plain("a.c", &["abc", "aéc"], &["ac"]);
+    }
+
+    #[test]
+    fn alternation_and_groups() {
+        plain("cat|dog", &["hotdog", "cat"], &["cow"]);
+        plain("a(b|c)d", &["abd", "acd"], &["ad", "abcd"]);
+        plain("(ab)+c", &["ababc", "abc"], &["ac", "bc"]);
+        plain("x(|y)z", &["xz", "xyz"], &["xyyz"]);
+    }
+
+    #[test]
+    fn repetition() {
+        plain("ab*c", &["ac", "abbbc"], &["abxc"]);
+        plain("ab+c", &["abc", "abbc"], &["ac"]);
+        plain("ab?c", &["ac", "abc"], &["abbc"]);
+        plain("^a{3}$", &["aaa"], &["aa", "aaaa"]);
+        plain("^a{2,3}$", &["aa", "aaa"], &["a", "aaaa"]);
+        plain("^a{2,}$", &["aa", "aaaaaa"], &["a"]);
+        plain("^a{,2}$", &["", "aa"], &["aaa"]);
+        plain("^(ab){2}$", &["abab"], &["ab", "ababab"]);
+    }
+
+    #[test]
+    fn ignore_case() {
+        let options = MatchOptions { ignore_case: true, ..MatchOptions::default() };
+        check("hello", options, &["HELLO", "say HeLLo"], &["help"]);
+        check("[a-c]+Z", options, &["ABcz"], &["dz"]);
+        check("ÉTÉ", options, &["été"], &["ete"]);
+        let literal = Regex::literal("A.B", options).unwrap();
+        assert!(literal.matcher().is_match(b"xa.by"));
+        assert!(!literal.matcher().is_match(b"axb"));
+    }
+
+    #[test]
+    fn whole_words() {
+        let options = MatchOptions { whole_words: true, ..MatchOptions::default() };
+        check("foo", options, &["foo", "a foo.", "x-foo-y"], &["foobar", "_foo", "foo1"]);
+        // 最初の候補が単語の途中でも、後ろに単語として現れれば一致する (GNU grep -w と同じ)。
+        check("foo", options, &["foobar foo"], &[]);
+        check("a+", options, &["b aa c"], &["baac"]);
+        check("é", options, &["à é"], &["àé"]);
+    }
+
+    #[test]
+    fn literal_has_no_special_characters() {
+        let literal = Regex::literal("a.c|(d)*", MatchOptions::default()).unwrap();
+        assert!(literal.matcher().is_match(b"xa.c|(d)*y"));
+        assert!(!literal.matcher().is_match(b"abc"));
+    }
+
+    #[test]
+    fn invalid_utf8_reads_as_replacement_characters() {
+        let regex = Regex::new("a.b", MatchOptions::default()).unwrap();
+        let mut matcher = regex.matcher();
+        assert!(matcher.is_match(b"a\xffb"));
+        // 壊れた並び "\xe2\x82" は1文字として読む。
+        assert!(matcher.is_match(b"a\xe2\x82b"));
+        assert!(!matcher.is_match(b"a\xff\xffb"));
+        let replacement = Regex::new("a\u{fffd}b", MatchOptions::default()).unwrap();
+        assert!(replacement.matcher().is_match(b"xa\xffb"));
+        // 読み飛ばした後の直前の文字も、壊れた並びは単語の文字でないと読む。
+        let words = Regex::new("b", MatchOptions { whole_words: true, ..MatchOptions::default() }).unwrap();
+        assert!(words.matcher().is_match(b"aa\xffb"));
+        assert!(!words.matcher().is_match("aaéb".as_bytes()));
+    }
+
+    #[test]
+    fn matcher_is_reusable() {
+        let regex = Regex::new("ab+c", MatchOptions::default()).unwrap();
+        let mut matcher = regex.matcher();
+        for _ in 0..3 {
+            assert!(matcher.is_match(b"xabbbc"));
+            assert!(!matcher.is_match(b"xabbb"));
+        }
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(pattern_error("(ab"), "unmatched '('");
+        assert_eq!(pattern_error("ab)"), "unmatched ')'");
+        assert_eq!(pattern_error("[ab"), "unmatched '['");
+        assert_eq!(pattern_error("*a"), "'*' has nothing to repeat");
+        assert_eq!(pattern_error("a\\"), "trailing backslash");
+        assert_eq!(pattern_error("\\q"), "unknown escape '\\q'");
+        assert_eq!(pattern_error("[[:nope:]]"), "unknown class '[:nope:]'");
+        assert_eq!(pattern_error("[z-a]"), "invalid range 'z-a'");
+        assert_eq!(pattern_error("a{2"), "unmatched '{'");
+    }
+
+    #[test]
+    fn repetition_limits() {
+        assert!(Regex::new("a{1000}", MatchOptions::default()).is_ok());
+        assert_eq!(pattern_error("a{1001}"), "invalid repetition count (0 to 1000)");
+        assert_eq!(pattern_error("a{3,2}"), "invalid repetition count (0 to 1000)");
+        // 1つ1つの回数は上限内でも、入れ子にすると命令が回数の積だけ増える。
+        for pattern in ["((a{1000}){1000}){1000}", "(a{1000}){101}", "(((((a{9}){9}){9}){9}){9}){9}"] {
+            assert!(pattern_error(pattern).starts_with("pattern too large"), "{}", pattern);
+        }
+        assert!(Regex::new("(a{100}){100}", MatchOptions::default()).is_ok());
+    }
+}
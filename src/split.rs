@@ -0,0 +1,156 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::cli::SplitArgs;
+use crate::concat::copy_range;
+use crate::decoder::{MemberInfo, MultiMemberDecoder};
+use crate::output;
+use crate::paths::{input_stem, safe_file_name};
+use crate::{open_input, open_seekable, reading_error};
+
+// メンバーを書き出すファイルの名前を決める。
+// FNAME があればそれに ".gz" を付け、なければ (または名前が重なれば) 番号を付ける。
+// 同じ名前の入力が2つあれば番号を付けた名前も重なるので、それも重なれば重ならなくなるまで後ろに番号を足す。
+fn member_file_name(member: &MemberInfo, stem: &str, used: &mut HashSet<String>) -> String {
+    let from_header = member.header.filename.as_deref().and_then(safe_file_name);
+    let numbered = format!("{}.{:04}", from_header.as_deref().unwrap_or(stem), member.index);
+    let name = from_header
+        .map(|name| format!("{}.gz", name))
+        .into_iter()
+        .chain(std::iter::once(format!("{}.gz", numbered)))
+        .chain((1..).map(|n| format!("{}.{}.gz", numbered, n)))
+        .find(|name| !used.contains(name))
+        .expect("the numbers never run out");
+    used.insert(name.clone());
+    name
+}
+
+fn create_output(path: &Path, force: bool) -> Result<File, Box<dyn Error>> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        // 既にあるファイルは上書きしない。
+        options.create_new(true);
+    }
+    options
+        .open(path)
+        .map_err(|err| format!("Cannot create file '{}', Error: {}", path.display(), err).into())
+}
+
+// 読み進めたバイト列を取っておく `BufRead`。シークできない入力から、展開し終えたメンバーのバイト列を取り出すのに使う。
+struct Recorder<R> {
+    inner: R,
+    recorded: Vec<u8>,
+}
+
+impl<R: BufRead> Read for Recorder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.recorded.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Recorder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.recorded.extend_from_slice(&buf[..amt.min(buf.len())]);
+        }
+        self.inner.consume(amt);
+    }
+}
+
+fn write_member_file(
+    path: &Path,
+    args: &SplitArgs,
+    filename: &str,
+    member: &MemberInfo,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> Result<(), Box<dyn Error>> {
+    // FNAME が入力ファイルの名前なら、--force でも入力を切り詰めて読めなくなる。
+    output::check_not_input(path, &args.inputs)?;
+    let mut out = BufWriter::new(create_output(path, args.force)?);
+    write(&mut out)?;
+    out.flush()?;
+    println!(
+        "{}: member {} (offset {}, {} bytes) -> {}",
+        filename,
+        member.index,
+        member.offset,
+        member.compressed_len,
+        path.display()
+    );
+    Ok(())
+}
+
+// 標準入力をシークせずに1回だけ読み、メンバーを展開し終えるたびにそのバイト列を書き出す。
+// メモリーに置くのは1メンバー分の圧縮されたバイト列だけ。
+fn split_stream(args: &SplitArgs, directory: &Path, used: &mut HashSet<String>, filename: &str) -> Result<(), Box<dyn Error>> {
+    let input = Recorder { inner: BufReader::new(open_input(filename)?), recorded: Vec::new() };
+    let mut decoder = MultiMemberDecoder::new(input, filename);
+    let stem = input_stem(filename);
+    let mut written = 0;
+    loop {
+        let member = match decoder.next_member(&mut io::sink()) {
+            Ok(Some(member)) => member,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                eprintln!("gzip_test: wrote {} member(s) of '{}' before the error", written, filename);
+                return Err(reading_error(err));
+            }
+        };
+        let bytes = std::mem::take(&mut decoder.get_mut().recorded);
+        let path = directory.join(member_file_name(&member, &stem, used));
+        write_member_file(&path, args, filename, &member, |out| out.write_all(&bytes))?;
+        written += 1;
+    }
+}
+
+/// `split` コマンド: 複数メンバーのgzipファイルを、メンバーごとの `.gz` ファイルに分ける。
+///
+/// 壊れたメンバーが見つかった時は、その手前までのメンバーを書き出してからエラーを返す。
+/// 標準入力はシークせずに読むので、パイプからでも分けられる。
+pub fn split(args: &SplitArgs) -> Result<(), Box<dyn Error>> {
+    let directory = Path::new(args.directory.as_deref().unwrap_or("."));
+    let mut used = HashSet::new();
+    for filename in &args.inputs {
+        if filename == "-" {
+            split_stream(args, directory, &mut used, filename)?;
+            continue;
+        }
+        let mut input = open_seekable(filename)?;
+        // 1回目: すべてのメンバーを展開して境界を調べ、CRC32 と ISIZE を確かめる。
+        let mut decoder = MultiMemberDecoder::new(BufReader::new(&mut input), filename);
+        let mut members = Vec::new();
+        let failure = loop {
+            match decoder.next_member(&mut io::sink()) {
+                Ok(Some(member)) => members.push(member),
+                Ok(None) => break None,
+                Err(err) => break Some(reading_error(err)),
+            }
+        };
+        drop(decoder);
+        // 2回目: 各メンバーのバイト列をそのまま別のファイルに書き写す。
+        let stem = input_stem(filename);
+        for member in &members {
+            let path = directory.join(member_file_name(member, &stem, &mut used));
+            write_member_file(&path, args, filename, member, |out| {
+                copy_range(input.as_mut(), member.offset, member.compressed_len, out)
+            })?;
+        }
+        if let Some(err) = failure {
+            eprintln!("gzip_test: wrote {} member(s) of '{}' before the error", members.len(), filename);
+            return Err(err);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,170 @@
+use std::error::Error;
+use std::io::{self, stdout, BufWriter, Write};
+
+use crate::cli::StatsArgs;
+use crate::decoder::MemberInfo;
+use crate::{json, open_decoder, reading_error};
+
+// 展開したデータの行、単語、バイトを wc と同じく数える Write。
+// 単語は空白 (スペース、\t \n \v \f \r) で区切られた並びで、メンバーをまたぐ単語は始まったメンバーで数える。
+#[derive(Debug, Default, Clone, Copy)]
+struct Counts {
+    lines: u64,
+    words: u64,
+    bytes: u64,
+    in_word: bool,
+}
+
+impl Write for Counts {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &b in buf {
+            let space = matches!(b, b' ' | b'\t'..=b'\r');
+            if b == b'\n' {
+                self.lines += 1;
+            }
+            if !space && !self.in_word {
+                self.words += 1;
+            }
+            self.in_word = !space;
+        }
+        self.bytes += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// 1つのメンバーの情報と、そのメンバーで数えた行と単語。
+struct MemberStats {
+    info: MemberInfo,
+    lines: u64,
+    words: u64,
+}
+
+// 1つのファイルの集計。
+struct FileStats {
+    members: Vec<MemberStats>,
+    counts: Counts,
+    compressed: u64,
+}
+
+// gzip -l と同じく、圧縮で減った割合を百分率で表す。展開後が空なら None。
+fn space_saving(compressed: u64, uncompressed: u64) -> Option<f64> {
+    (uncompressed > 0).then(|| (1.0 - compressed as f64 / uncompressed as f64) * 100.0)
+}
+
+// 展開後のバイト数が圧縮後の何倍か。
+fn ratio(compressed: u64, uncompressed: u64) -> Option<f64> {
+    (compressed > 0).then(|| uncompressed as f64 / compressed as f64)
+}
+
+fn read_stats(filename: &str) -> Result<FileStats, Box<dyn Error>> {
+    let mut decoder = open_decoder(filename)?;
+    let mut counts = Counts::default();
+    let mut members = Vec::new();
+    loop {
+        let before = counts;
+        let Some(info) = decoder.next_member(&mut counts).map_err(reading_error)? else {
+            break;
+        };
+        members.push(MemberStats { info, lines: counts.lines - before.lines, words: counts.words - before.words });
+    }
+    let compressed = members.iter().map(|m| m.info.compressed_len).sum();
+    Ok(FileStats { members, counts, compressed })
+}
+
+fn format_ratio(compressed: u64, uncompressed: u64) -> String {
+    let ratio = ratio(compressed, uncompressed).map_or("-".to_string(), |ratio| format!("{:.2}:1", ratio));
+    match space_saving(compressed, uncompressed) {
+        Some(saving) => format!("{} ({:.1}% saved)", ratio, saving),
+        None => ratio,
+    }
+}
+
+fn write_text<W: Write>(w: &mut W, filename: &str, stats: &FileStats) -> io::Result<()> {
+    writeln!(w, "{}", filename)?;
+    for member in &stats.members {
+        let info = &member.info;
+        writeln!(
+            w,
+            "  member {}: offset {}, {} -> {} bytes, ratio {}, {} lines, {} words",
+            info.index,
+            info.offset,
+            info.compressed_len,
+            info.uncompressed_len,
+            format_ratio(info.compressed_len, info.uncompressed_len),
+            member.lines,
+            member.words
+        )?;
+    }
+    let counts = &stats.counts;
+    writeln!(w, "  members            {}", stats.members.len())?;
+    writeln!(w, "  compressed size    {} bytes", stats.compressed)?;
+    writeln!(w, "  uncompressed size  {} bytes", counts.bytes)?;
+    writeln!(w, "  ratio              {}", format_ratio(stats.compressed, counts.bytes))?;
+    writeln!(w, "  lines              {}", counts.lines)?;
+    writeln!(w, "  words              {}", counts.words)?;
+    writeln!(w, "  bytes              {}", counts.bytes)?;
+    Ok(())
+}
+
+fn optional_number(value: Option<f64>) -> String {
+    value.map_or("null".to_string(), |value| format!("{:.4}", value))
+}
+
+fn member_json(member: &MemberStats) -> String {
+    let info = &member.info;
+    format!(
+        "{{\"index\":{},\"offset\":{},\"compressed_length\":{},\"uncompressed_length\":{},\"ratio\":{},\"space_saving\":{},\"lines\":{},\"words\":{}}}",
+        info.index,
+        info.offset,
+        info.compressed_len,
+        info.uncompressed_len,
+        optional_number(ratio(info.compressed_len, info.uncompressed_len)),
+        optional_number(space_saving(info.compressed_len, info.uncompressed_len)),
+        member.lines,
+        member.words
+    )
+}
+
+fn file_json(filename: &str, stats: &FileStats) -> String {
+    let counts = &stats.counts;
+    let members: Vec<String> = stats.members.iter().map(member_json).collect();
+    format!(
+        "{{\"file\":{},\"member_count\":{},\"compressed_length\":{},\"uncompressed_length\":{},\"ratio\":{},\"space_saving\":{},\"lines\":{},\"words\":{},\"bytes\":{},\"members\":[{}]}}",
+        json::string(filename),
+        stats.members.len(),
+        stats.compressed,
+        counts.bytes,
+        optional_number(ratio(stats.compressed, counts.bytes)),
+        optional_number(space_saving(stats.compressed, counts.bytes)),
+        counts.lines,
+        counts.words,
+        counts.bytes,
+        members.join(",")
+    )
+}
+
+/// `stats` コマンド: 各ファイルを展開し、圧縮前後のサイズと圧縮率、メンバーの数、
+/// 展開したデータの行、単語、バイトの数 (`zcat FILE | wc` と同じ) をメンバーごとの内訳と合わせて表示する。
+pub fn stats(args: &StatsArgs) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(stdout().lock());
+    if args.json {
+        // 途中のファイルで失敗した時に閉じていない配列を残さないよう、すべて集計してからまとめて書き出す。
+        let files = args.inputs.iter().map(|filename| Ok(file_json(filename, &read_stats(filename)?))).collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        writeln!(writer, "[")?;
+        for (i, file) in files.iter().enumerate() {
+            let separator = if i + 1 < files.len() { "," } else { "" };
+            writeln!(writer, "  {}{}", file, separator)?;
+        }
+        writeln!(writer, "]")?;
+    } else {
+        for filename in &args.inputs {
+            write_text(&mut writer, filename, &read_stats(filename)?)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
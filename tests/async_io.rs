@@ -0,0 +1,125 @@
+#![cfg(feature = "tokio")]
+
+mod common;
+
+use std::io::Read;
+
+use common::{gzip, multi_member};
+use gzip_test::{open_reader, AsyncDecoder, AsyncLines, GzTestError, Lines};
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+// ソケットの代わりに、容量 capacity バイトの duplex に data を書き込み、書き終えたら閉じる。
+async fn send(mut writer: DuplexStream, data: Vec<u8>) {
+    // 読む側がエラーで止まると書き込みは失敗するが、それは読む側で確かめる。
+    let _ = writer.write_all(&data).await;
+    drop(writer);
+}
+
+async fn async_decode(data: &[u8], capacity: usize) -> Result<Vec<u8>, GzTestError> {
+    let (writer, reader) = duplex(capacity);
+    // エラーで読むのをやめたら reader を閉じ、書き込みを止める。
+    let read = async move {
+        let mut out = Vec::new();
+        AsyncDecoder::new(reader, "test.gz").read_to_end(&mut out).await.map(|_| out)
+    };
+    let (_, result) = tokio::join!(send(writer, data.to_vec()), read);
+    result.map_err(|err| GzTestError::from_io(err).unwrap())
+}
+
+// エラーまでに返した行と、エラーがあればそのエラー。
+async fn async_lines(data: &[u8], capacity: usize) -> (Vec<String>, Option<GzTestError>) {
+    let (writer, reader) = duplex(capacity);
+    let read_lines = async move {
+        let mut lines = AsyncLines::new(reader, "test.gz");
+        let mut read = Vec::new();
+        while let Some(line) = lines.next_line().await {
+            match line {
+                Ok(line) => read.push(line),
+                Err(err) => {
+                    // Lines と同じく、エラーの後は何も返さない。
+                    assert!(lines.next_line().await.is_none());
+                    return (read, Some(err));
+                }
+            }
+        }
+        (read, None)
+    };
+    tokio::join!(send(writer, data.to_vec()), read_lines).1
+}
+
+fn pull_lines(data: &[u8]) -> (Vec<String>, Option<GzTestError>) {
+    let mut read = Vec::new();
+    for line in Lines::new(open_reader(data, "test.gz")) {
+        match line {
+            Ok(line) => read.push(line),
+            Err(err) => return (read, Some(err)),
+        }
+    }
+    (read, None)
+}
+
+const CAPACITIES: [usize; 3] = [1, 7, 64 * 1024];
+
+#[tokio::test]
+async fn decodes_every_member() {
+    let data = multi_member(&[b"first member\n", b"", b"second member\nno newline", b" continues\n"]);
+    for capacity in CAPACITIES {
+        assert_eq!(async_decode(&data, capacity).await.unwrap(), b"first member\nsecond member\nno newline continues\n");
+    }
+}
+
+#[tokio::test]
+async fn errors_match_the_blocking_decoder() {
+    let data = multi_member(&[b"hello\n", b"world\n"]);
+    let mut crc = data.clone();
+    let at = crc.len() - 8;
+    crc[at] ^= 0xff;
+    let inputs = [data[..data.len() - 3].to_vec(), crc, [data.clone(), b"garbage".to_vec()].concat(), Vec::new()];
+    for input in inputs {
+        let mut out = Vec::new();
+        let expected = open_reader(&input[..], "test.gz").read_to_end(&mut out).unwrap_err();
+        let expected = GzTestError::from_io(expected).unwrap();
+        for capacity in CAPACITIES {
+            let err = async_decode(&input, capacity).await.unwrap_err();
+            assert_eq!(err.to_string(), expected.to_string(), "capacity {}", capacity);
+            assert_eq!(err.exit_code(), expected.exit_code());
+        }
+    }
+}
+
+#[tokio::test]
+async fn lines_match_pull_lines() {
+    let text: String = (0..2000).map(|i| format!("line {}\r\n", i)).collect();
+    let (head, tail) = text.split_at(7777);
+    let data = multi_member(&[head.as_bytes(), tail.as_bytes(), b"last line without newline"]);
+    let (expected, _) = pull_lines(&data);
+    assert_eq!(expected.len(), 2001);
+    for capacity in CAPACITIES {
+        let (read, err) = async_lines(&data, capacity).await;
+        assert!(err.is_none(), "{:?}", err.map(|err| err.to_string()));
+        assert_eq!(read, expected);
+    }
+}
+
+#[tokio::test]
+async fn lines_stop_at_the_same_error() {
+    let truncated = {
+        let data = multi_member(&[b"one\ntwo\n", b"three\n"]);
+        data[..data.len() - 2].to_vec()
+    };
+    for data in [gzip(b"ok\nbad \xff line\nafter\n"), truncated] {
+        let (expected, expected_err) = pull_lines(&data);
+        let expected_err = expected_err.unwrap();
+        for capacity in CAPACITIES {
+            let (read, err) = async_lines(&data, capacity).await;
+            assert_eq!(read, expected);
+            let err = err.unwrap();
+            assert_eq!(err.exit_code(), expected_err.exit_code(), "{}", err);
+            // UTF-8 のエラーの位置はその時までに読んだ入力の位置なので、行番号だけを比べる。
+            match (&err, &expected_err) {
+                (GzTestError::Utf8 { line, .. }, GzTestError::Utf8 { line: expected, .. }) => assert_eq!(line, expected),
+                _ => assert_eq!(err.to_string(), expected_err.to_string()),
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use std::io::Read;
+
+use common::{gzip, run, TempDir};
+use flate2::read::MultiGzDecoder;
+
+// 1ブロックに入る展開後のバイト数。
+const BLOCK_DATA_SIZE: usize = 0xff00;
+
+const EOF_BLOCK: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00, 0x1b, 0x00, 0x03,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+// 圧縮しにくいデータを混ぜた、4ブロック余りのテキスト。
+fn sample() -> Vec<u8> {
+    let mut seed = 1u32;
+    let mut data: Vec<u8> = (0..20_000).map(|i| format!("line {}\n", i)).collect::<String>().into_bytes();
+    data.extend((0..100_000).map(|_| {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (seed >> 16) as u8
+    }));
+    data
+}
+
+// BSIZE をたどって、各ブロックの開始位置を返す。
+fn block_offsets(data: &[u8]) -> Vec<usize> {
+    let mut offsets = Vec::new();
+    let mut at = 0;
+    while at < data.len() {
+        assert_eq!(&data[at + 12..at + 16], b"BC\x02\x00", "block at {}", at);
+        offsets.push(at);
+        at += usize::from(u16::from_le_bytes([data[at + 16], data[at + 17]])) + 1;
+    }
+    assert_eq!(at, data.len());
+    offsets
+}
+
+fn compress_bgzf(dir: &TempDir, jobs: &str) -> Vec<u8> {
+    let run = run(dir, &["compress", "--bgzf", "-j", jobs, "-o", "out.gz", "in.bin"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    dir.read("out.gz")
+}
+
+#[test]
+fn writes_blocks_and_an_eof_marker() {
+    let dir = TempDir::new();
+    dir.write("in.bin", &sample());
+    let data = compress_bgzf(&dir, "1");
+    let offsets = block_offsets(&data);
+    assert_eq!(offsets.len(), sample().len().div_ceil(BLOCK_DATA_SIZE) + 1);
+    assert!(data.ends_with(&EOF_BLOCK));
+    let mut decoded = Vec::new();
+    MultiGzDecoder::new(&data[..]).read_to_end(&mut decoded).unwrap();
+    assert!(decoded == sample());
+    assert!(compress_bgzf(&dir, "4") == data);
+}
+
+#[test]
+fn list_reports_bgzf() {
+    let dir = TempDir::new();
+    dir.write("in.bin", &sample());
+    let data = compress_bgzf(&dir, "2");
+    let blocks = block_offsets(&data).len();
+    let list = run(&dir, &["list", "out.gz"]);
+    assert_eq!(list.code, Some(0), "{}", list.stderr);
+    assert!(list.stdout_text().contains(&format!("  BGZF: {} block(s), EOF marker present\n", blocks)), "{}", list.stdout_text());
+    let json = run(&dir, &["list", "--json", "out.gz"]).stdout_text();
+    assert!(json.contains(&format!("\"bgzf\":{{\"blocks\":{},\"eof_marker\":true}}", blocks)), "{}", json);
+    // 2つ目のブロックの仮想ファイルオフセットは、1つ目のブロックの大きさを 16 ビットずらしたもの。
+    assert!(json.contains(&format!("\"virtual_offset\":{}", (block_offsets(&data)[1] as u64) << 16)), "{}", json);
+
+    dir.write("no-eof.gz", &data[..data.len() - EOF_BLOCK.len()]);
+    assert!(run(&dir, &["list", "no-eof.gz"]).stdout_text().contains("EOF marker missing"));
+    dir.write("plain.gz", &gzip(b"plain\n"));
+    let plain = run(&dir, &["list", "--json", "plain.gz"]).stdout_text();
+    assert!(plain.contains("\"bgzf\":null") && plain.contains("\"virtual_offset\":null"), "{}", plain);
+}
+
+#[test]
+fn extract_virtual_offsets() {
+    let dir = TempDir::new();
+    let text = sample();
+    dir.write("in.bin", &text);
+    let offsets = block_offsets(&compress_bgzf(&dir, "1"));
+    let virtual_offset = |block: usize, within: usize| ((offsets[block] as u64) << 16 | within as u64).to_string();
+    let cases = [
+        (virtual_offset(0, 10), virtual_offset(0, 20), &text[10..20]),
+        (virtual_offset(1, 100), virtual_offset(2, 5), &text[BLOCK_DATA_SIZE + 100..2 * BLOCK_DATA_SIZE + 5]),
+        (virtual_offset(3, 7), String::new(), &text[3 * BLOCK_DATA_SIZE + 7..]),
+    ];
+    for (start, end, expected) in cases {
+        let range = format!("{}..{}", start, end);
+        let extract = run(&dir, &["extract", "--virtual", &range, "out.gz"]);
+        assert_eq!(extract.code, Some(0), "{}: {}", range, extract.stderr);
+        assert!(extract.stdout == expected, "{}", range);
+    }
+}
+
+#[test]
+fn bgzf_and_member_size_conflict() {
+    let dir = TempDir::new();
+    let run = run(&dir, &["compress", "--bgzf", "--member-size", "1K", "in.bin"]);
+    assert_eq!(run.code, Some(2));
+    assert!(run.stderr.contains("'--bgzf' and '--member-size' cannot be used together"), "{}", run.stderr);
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use std::io::Read;
+use std::process::{Command, Stdio};
+
+use common::{gzip, multi_member, run, TempDir};
+
+#[test]
+fn binary_output_is_byte_exact() {
+    let dir = TempDir::new();
+    let parts: [&[u8]; 3] = [b"crlf\r\nline\r\n", b"\x00\xff\xfe binary", b" no final newline"];
+    dir.write("in.gz", &multi_member(&parts));
+    let run = run(&dir, &["-b", "in.gz"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, parts.concat());
+}
+
+#[test]
+fn text_output_rewrites_line_endings() {
+    let dir = TempDir::new();
+    dir.write("in.gz", &gzip(b"crlf\r\nlf\nlast"));
+    assert_eq!(run(&dir, &["in.gz"]).stdout_text(), "crlf\nlf\nlast\n");
+}
+
+#[test]
+fn closed_pipe_ends_quietly() {
+    let dir = TempDir::new();
+    let text: String = (0..500_000).map(|i| format!("line {}\n", i)).collect();
+    dir.write("big.gz", &gzip(text.as_bytes()));
+    for args in [&["big.gz"][..], &["-b", "big.gz"], &["grep", "line", "big.gz"]] {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_gzip_test"))
+            .args(args)
+            .current_dir(dir.path())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // head と同じく、少しだけ読んでパイプを閉じる。
+        let mut stdout = child.stdout.take().unwrap();
+        let mut head = [0; 100];
+        stdout.read_exact(&mut head).unwrap();
+        drop(stdout);
+        let output = child.wait_with_output().unwrap();
+        assert_eq!(output.status.code(), Some(0), "{:?}", args);
+        assert!(output.stderr.is_empty(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    }
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use common::{gzip, multi_member, run, TempDir};
+
+#[test]
+fn counts_members_and_warns_about_multi_member_files() {
+    let dir = TempDir::new();
+    dir.write("single.gz", &gzip(b"hello\n"));
+    dir.write("multi.gz", &multi_member(&[b"hello\n", b"world\n"]));
+    let run = run(&dir, &["check", "single.gz", "multi.gz"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout_text(), "single.gz: 1 member(s)\nmulti.gz: 2 member(s)\n");
+    assert!(run.stderr.contains("'multi.gz' has 2 members; a first-member-only decoder such as GzDecoder would stop after 6 of 12 bytes"), "{}", run.stderr);
+    assert!(!run.stderr.contains("single.gz"), "{}", run.stderr);
+}
+
+#[test]
+fn strict_single_fails_on_multi_member_files() {
+    let dir = TempDir::new();
+    dir.write("single.gz", &gzip(b"hello\n"));
+    dir.write("multi.gz", &multi_member(&[b"hello\n", b"world\n"]));
+    assert_eq!(run(&dir, &["check", "--strict-single", "single.gz"]).code, Some(0));
+    let run = run(&dir, &["check", "--strict-single", "single.gz", "multi.gz"]);
+    assert_eq!(run.code, Some(12), "{}", run.stderr);
+    assert!(run.stderr.contains("1 multi-member file(s) found with --strict-single"), "{}", run.stderr);
+}
+
+#[test]
+fn corrupt_input_keeps_its_exit_code() {
+    let dir = TempDir::new();
+    let data = gzip(b"hello\n");
+    dir.write("short.gz", &data[..data.len() - 1]);
+    assert_eq!(run(&dir, &["check", "short.gz"]).code, Some(7));
+}
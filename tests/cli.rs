@@ -0,0 +1,82 @@
+mod common;
+
+use common::{gzip, multi_member, run, run_in, TempDir};
+
+#[test]
+fn decodes_every_member_of_the_given_file() {
+    let dir = TempDir::new();
+    dir.write("multi.txt.gz", &multi_member(&[b"first\n", b"second\n"]));
+    let run = run(&dir, &["multi.txt.gz"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout_text(), "first\nsecond\n");
+}
+
+#[test]
+fn joins_several_inputs_in_order() {
+    let dir = TempDir::new();
+    dir.write("a.gz", &gzip(b"a\n"));
+    dir.write("b.gz", &gzip(b"b\n"));
+    assert_eq!(run(&dir, &["b.gz", "a.gz"]).stdout_text(), "b\na\n");
+}
+
+#[test]
+fn reads_the_standard_input_for_dash() {
+    let dir = TempDir::new();
+    dir.write("a.gz", &gzip(b"file\n"));
+    let run = run_in(dir.path(), &["a.gz", "-"], &gzip(b"stdin\n"));
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout_text(), "file\nstdin\n");
+}
+
+#[test]
+fn writes_to_the_output_path() {
+    let dir = TempDir::new();
+    dir.write("a.gz", &gzip(b"text\n"));
+    let short = run(&dir, &["-o", "out.txt", "a.gz"]);
+    assert_eq!(short.code, Some(0), "{}", short.stderr);
+    assert!(short.stdout.is_empty());
+    assert_eq!(dir.read("out.txt"), b"text\n");
+    assert_eq!(run(&dir, &["--output=out2.txt", "a.gz"]).code, Some(0));
+    assert_eq!(dir.read("out2.txt"), b"text\n");
+}
+
+#[test]
+fn files_after_double_dash_are_not_options() {
+    let dir = TempDir::new();
+    dir.write("-o", &gzip(b"dash\n"));
+    assert_eq!(run(&dir, &["--", "-o"]).stdout_text(), "dash\n");
+}
+
+#[test]
+fn help_and_version() {
+    let dir = TempDir::new();
+    let help = run(&dir, &["--help"]);
+    assert_eq!(help.code, Some(0));
+    assert!(help.stdout_text().starts_with("Usage: gzip_test"));
+    let version = run(&dir, &["-V"]);
+    assert_eq!(version.code, Some(0));
+    assert_eq!(version.stdout_text(), format!("gzip_test {}\n", env!("CARGO_PKG_VERSION")));
+}
+
+#[test]
+fn usage_errors_exit_with_2() {
+    let dir = TempDir::new();
+    for args in [&[][..], &["--no-such-option", "a.gz"], &["-o"], &["--gunzip", "-o", "x", "a.gz"]] {
+        let run = run(&dir, args);
+        assert_eq!(run.code, Some(2), "{:?}", args);
+        assert!(run.stderr.contains("Usage: gzip_test"), "{:?}: {}", args, run.stderr);
+        assert!(run.stdout.is_empty());
+    }
+}
+
+#[test]
+fn short_options_can_be_bundled() {
+    let dir = TempDir::new();
+    dir.write("a.gz", &gzip(b"text\n"));
+    let bundled = run(&dir, &["-bj2", "-oout.txt", "a.gz"]);
+    assert_eq!(bundled.code, Some(0), "{}", bundled.stderr);
+    assert_eq!(dir.read("out.txt"), b"text\n");
+    let run = run(&dir, &["-bq", "a.gz"]);
+    assert_eq!(run.code, Some(2));
+    assert!(run.stderr.contains("unknown option '-q'"), "{}", run.stderr);
+}
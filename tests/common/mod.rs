@@ -0,0 +1,134 @@
+// 統合テストで共有する、入力の gzip データを作る関数と gzip_test を実行する関数。
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+/// `data` を1つのメンバーに圧縮する。
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// 各部分を1つずつのメンバーに圧縮してつなげる。
+pub fn multi_member(parts: &[&[u8]]) -> Vec<u8> {
+    parts.iter().flat_map(|part| gzip(part)).collect()
+}
+
+/// テストごとの作業ディレクトリ。落とす時に中身ごと消す。
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("gzip_test-{}-{}", std::process::id(), n));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// ディレクトリの中の `name` のパス。
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+
+    /// `name` に `data` を書き、そのパスを返す。
+    pub fn write(&self, name: &str, data: &[u8]) -> PathBuf {
+        let path = self.join(name);
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    pub fn read(&self, name: &str) -> Vec<u8> {
+        fs::read(self.join(name)).unwrap()
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.join(name).exists()
+    }
+
+    /// 一時ファイルなども含めた、ディレクトリの中のファイル名。
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> =
+            fs::read_dir(&self.path).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        names.sort();
+        names
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// gzip_test を実行した結果。
+pub struct Run {
+    /// 終了コード。シグナルで止まった時は `None`。
+    pub code: Option<i32>,
+    pub stdout: Vec<u8>,
+    pub stderr: String,
+}
+
+impl Run {
+    pub fn stdout_text(&self) -> String {
+        String::from_utf8(self.stdout.clone()).unwrap()
+    }
+}
+
+/// `dir` をカレントディレクトリにして gzip_test を実行し、`stdin` を標準入力に渡す。
+pub fn run_in(dir: &Path, args: &[&str], stdin: &[u8]) -> Run {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gzip_test"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut input = child.stdin.take().unwrap();
+    let stdin = stdin.to_vec();
+    // 出力を読みながら書かないと、大きな入力ではパイプが詰まる。
+    let writer = std::thread::spawn(move || {
+        let _ = input.write_all(&stdin);
+    });
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap();
+    Run { code: output.status.code(), stdout: output.stdout, stderr: String::from_utf8_lossy(&output.stderr).into_owned() }
+}
+
+/// `dir` をカレントディレクトリにして、標準入力なしで gzip_test を実行する。
+pub fn run(dir: &TempDir, args: &[&str]) -> Run {
+    run_in(dir.path(), args, b"")
+}
+
+/// 索引ファイルの各チェックポイントの、その手前にある改行の数。
+/// チェックポイントのすぐ後ろの行を読むテストで使うので、索引の形式のうち必要な所だけを読む。
+pub fn checkpoint_lines(index: &[u8]) -> Vec<u64> {
+    let u64_at = |at: usize| u64::from_le_bytes(index[at..at + 8].try_into().unwrap());
+    // 識別子の後に 5 個の u64 とチェックポイントの数が続く。
+    let count = u64_at(8 + 5 * 8);
+    let mut at = 8 + 6 * 8;
+    let mut lines = Vec::new();
+    for _ in 0..count {
+        // out, input, member, lines, bits, ウィンドウの長さ、圧縮したウィンドウの長さ、圧縮したウィンドウ。
+        lines.push(u64_at(at + 24));
+        let compressed_len = u64_at(at + 4 * 8 + 1 + 8) as usize;
+        at += 4 * 8 + 1 + 2 * 8 + compressed_len;
+    }
+    lines
+}
@@ -0,0 +1,165 @@
+mod common;
+
+use std::io::Read;
+
+use common::{run, run_in, TempDir};
+use flate2::read::{GzDecoder, MultiGzDecoder};
+
+fn gunzip(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    MultiGzDecoder::new(data).read_to_end(&mut out).unwrap();
+    out
+}
+
+// check コマンドで数えたメンバーの数。
+fn members(dir: &TempDir, name: &str) -> String {
+    run(dir, &["check", name]).stdout_text()
+}
+
+fn sample() -> Vec<u8> {
+    (0..20_000).map(|i| format!("line {}\n", i)).collect::<String>().into_bytes()
+}
+
+#[test]
+fn compresses_to_one_member_per_input() {
+    let dir = TempDir::new();
+    dir.write("a.txt", b"first\n");
+    dir.write("b.txt", b"second\n");
+    let run = run(&dir, &["compress", "-o", "out.gz", "a.txt", "b.txt"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(gunzip(&dir.read("out.gz")), b"first\nsecond\n");
+    assert_eq!(members(&dir, "out.gz"), "out.gz: 2 member(s)\n");
+}
+
+#[test]
+fn writes_to_stdout_without_output() {
+    let dir = TempDir::new();
+    let run = run_in(dir.path(), &["compress", "-"], b"from stdin\n");
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(gunzip(&run.stdout), b"from stdin\n");
+}
+
+#[test]
+fn levels() {
+    let dir = TempDir::new();
+    dir.write("in.txt", &sample());
+    for level in ["0", "1", "9"] {
+        let out = format!("{}.gz", level);
+        assert_eq!(run(&dir, &["compress", "-l", level, "-o", &out, "in.txt"]).code, Some(0));
+        assert_eq!(gunzip(&dir.read(&out)), sample());
+    }
+    assert!(dir.read("0.gz").len() > sample().len());
+    // XFL は gzip と同じく、最も速い圧縮で 4、最も強い圧縮で 2。
+    assert_eq!((dir.read("1.gz")[8], dir.read("9.gz")[8]), (4, 2));
+    assert_eq!(run(&dir, &["compress", "-l", "10", "in.txt"]).code, Some(2));
+}
+
+#[test]
+fn header_fields() {
+    let dir = TempDir::new();
+    dir.write("in.txt", b"text\n");
+    let args = ["compress", "--name", "stored.txt", "--mtime", "1234567", "--comment", "note", "-o", "out.gz", "in.txt"];
+    assert_eq!(run(&dir, &args).code, Some(0));
+    let data = dir.read("out.gz");
+    let decoder = GzDecoder::new(&data[..]);
+    let header = decoder.header().unwrap();
+    assert_eq!(header.filename(), Some(&b"stored.txt"[..]));
+    assert_eq!(header.comment(), Some(&b"note"[..]));
+    assert_eq!(header.mtime(), 1234567);
+
+    assert_eq!(run(&dir, &["compress", "-o", "default.gz", "in.txt"]).code, Some(0));
+    let data = dir.read("default.gz");
+    assert_eq!(GzDecoder::new(&data[..]).header().unwrap().filename(), Some(&b"in.txt"[..]));
+
+    assert_eq!(run(&dir, &["compress", "--no-name", "-o", "none.gz", "in.txt"]).code, Some(0));
+    let data = dir.read("none.gz");
+    let decoder = GzDecoder::new(&data[..]);
+    assert_eq!(decoder.header().unwrap().filename(), None);
+    assert_eq!(decoder.header().unwrap().mtime(), 0);
+}
+
+#[test]
+fn member_size_starts_new_members() {
+    let dir = TempDir::new();
+    dir.write("in.txt", &[b'x'; 10_000]);
+    assert_eq!(run(&dir, &["compress", "--member-size", "4K", "-o", "out.gz", "in.txt"]).code, Some(0));
+    assert_eq!(members(&dir, "out.gz"), "out.gz: 3 member(s)\n");
+    assert_eq!(gunzip(&dir.read("out.gz")), vec![b'x'; 10_000]);
+}
+
+#[test]
+fn empty_input_gives_one_empty_member() {
+    let dir = TempDir::new();
+    dir.write("empty.txt", b"");
+    assert_eq!(run(&dir, &["compress", "-o", "out.gz", "empty.txt"]).code, Some(0));
+    assert_eq!(members(&dir, "out.gz"), "out.gz: 1 member(s)\n");
+}
+
+#[test]
+fn refuses_to_write_over_an_input() {
+    let dir = TempDir::new();
+    dir.write("a.txt", b"keep me\n");
+    dir.write("b.txt", b"and me\n");
+    for args in [&["compress", "-o", "a.txt", "a.txt"][..], &["compress", "-o", "./b.txt", "a.txt", "b.txt"]] {
+        let run = run(&dir, args);
+        assert_eq!(run.code, Some(2), "{:?}", args);
+        assert!(run.stderr.contains("is the input file"), "{}", run.stderr);
+    }
+    assert_eq!(dir.read("a.txt"), b"keep me\n");
+    assert_eq!(dir.read("b.txt"), b"and me\n");
+}
+
+#[test]
+fn overwrites_an_existing_output_atomically() {
+    let dir = TempDir::new();
+    dir.write("in.txt", b"new\n");
+    dir.write("out.gz", b"old");
+    assert_eq!(run(&dir, &["compress", "-o", "out.gz", "in.txt"]).code, Some(0));
+    assert_eq!(gunzip(&dir.read("out.gz")), b"new\n");
+    // 入力がなければ、既にある出力ファイルも一時ファイルも残したまま失敗する。
+    let failed = run(&dir, &["compress", "-o", "out.gz", "missing.txt"]);
+    assert_eq!(failed.code, Some(3));
+    assert_eq!(gunzip(&dir.read("out.gz")), b"new\n");
+    assert_eq!(dir.names(), ["in.txt", "out.gz"]);
+}
+
+#[test]
+fn parallel_members_match_one_thread() {
+    let dir = TempDir::new();
+    dir.write("in.txt", &sample());
+    for jobs in ["1", "4", "0"] {
+        let output = format!("j{}.gz", jobs);
+        let run = run(&dir, &["compress", "-j", jobs, "--member-size", "10K", "-o", &output, "in.txt"]);
+        assert_eq!(run.code, Some(0), "{}", run.stderr);
+    }
+    assert_eq!(members(&dir, "j1.gz"), "j1.gz: 21 member(s)\n");
+    assert!(dir.read("j4.gz") == dir.read("j1.gz"));
+    assert!(dir.read("j0.gz") == dir.read("j1.gz"));
+}
+
+#[test]
+fn parallel_blocks_make_one_member() {
+    let dir = TempDir::new();
+    dir.write("in.txt", &sample());
+    dir.write("empty.txt", b"");
+    let run = run(&dir, &["compress", "-j", "4", "--block-size", "16K", "-o", "out.gz", "in.txt", "empty.txt"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(members(&dir, "out.gz"), "out.gz: 2 member(s)\n");
+    // 1つ目のメンバーだけを読むデコーダーでも、1つ目の入力を全部読める。
+    let mut first = Vec::new();
+    GzDecoder::new(&dir.read("out.gz")[..]).read_to_end(&mut first).unwrap();
+    assert!(first == sample());
+    assert!(gunzip(&dir.read("out.gz")) == sample());
+    // CRC32 と ISIZE はブロックごとの値をまとめたもの。
+    assert_eq!(common::run(&dir, &["test", "out.gz"]).code, Some(0));
+}
+
+#[test]
+fn parallel_from_the_standard_input() {
+    let dir = TempDir::new();
+    for args in [&["compress", "-j", "3", "-"][..], &["compress", "-j", "3", "--member-size", "1K", "-"]] {
+        let run = run_in(dir.path(), args, &sample());
+        assert_eq!(run.code, Some(0), "{:?}: {}", args, run.stderr);
+        assert!(gunzip(&run.stdout) == sample(), "{:?}", args);
+    }
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use std::io::{Read, Write};
+
+use common::{gzip, multi_member, run, TempDir};
+use flate2::read::{GzDecoder, MultiGzDecoder};
+use flate2::{Compression, GzBuilder};
+
+fn named(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzBuilder::new().filename(name).write(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn gunzip(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    MultiGzDecoder::new(data).read_to_end(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn joins_members_without_recompressing() {
+    let dir = TempDir::new();
+    let a = multi_member(&[b"a1\n", b"a2\n"]);
+    let b = gzip(b"b\n");
+    dir.write("a.gz", &a);
+    dir.write("b.gz", &b);
+    let joined = run(&dir, &["concat", "-o", "ab.gz", "a.gz", "b.gz"]);
+    assert_eq!(joined.code, Some(0), "{}", joined.stderr);
+    assert_eq!(dir.read("ab.gz"), [a, b].concat());
+    assert_eq!(run(&dir, &["concat", "a.gz", "b.gz"]).stdout, dir.read("ab.gz"));
+}
+
+#[test]
+fn rewrites_or_removes_fname() {
+    let dir = TempDir::new();
+    dir.write("a.gz", &named("a.txt", b"a\n"));
+    dir.write("b.gz", &named("b.txt", b"b\n"));
+    assert_eq!(run(&dir, &["concat", "--name", "all.txt", "-o", "renamed.gz", "a.gz", "b.gz"]).code, Some(0));
+    let data = dir.read("renamed.gz");
+    assert_eq!(gunzip(&data), b"a\nb\n");
+    assert_eq!(GzDecoder::new(&data[..]).header().unwrap().filename(), Some(&b"all.txt"[..]));
+    let listing = run(&dir, &["list", "renamed.gz"]).stdout_text();
+    assert_eq!(listing.matches("FNAME              all.txt\n").count(), 2, "{}", listing);
+
+    assert_eq!(run(&dir, &["concat", "--no-name", "-o", "bare.gz", "a.gz", "b.gz"]).code, Some(0));
+    let data = dir.read("bare.gz");
+    assert_eq!(gunzip(&data), b"a\nb\n");
+    assert!(!run(&dir, &["list", "bare.gz"]).stdout_text().contains("FNAME  "));
+    assert_eq!(run(&dir, &["concat", "--name", "x", "--no-name", "a.gz"]).code, Some(2));
+}
+
+#[test]
+fn writes_nothing_when_an_input_is_corrupt() {
+    let dir = TempDir::new();
+    dir.write("a.gz", &gzip(b"a\n"));
+    let mut bad = gzip(b"b\n");
+    let at = bad.len() - 8;
+    bad[at] ^= 1;
+    dir.write("bad.gz", &bad);
+    let run = run(&dir, &["concat", "-o", "out.gz", "a.gz", "bad.gz"]);
+    assert_eq!(run.code, Some(5), "{}", run.stderr);
+    assert_eq!(dir.names(), ["a.gz", "bad.gz"]);
+}
+
+#[test]
+fn refuses_to_write_over_an_input() {
+    let dir = TempDir::new();
+    let data = multi_member(&[b"self\n", b"more\n"]);
+    dir.write("self.gz", &data);
+    dir.write("a.gz", &gzip(b"a\n"));
+    let run = run(&dir, &["concat", "-o", "self.gz", "self.gz", "a.gz"]);
+    assert_eq!(run.code, Some(2), "{}", run.stderr);
+    assert!(run.stderr.contains("the output 'self.gz' is the input file 'self.gz'"), "{}", run.stderr);
+    assert_eq!(dir.read("self.gz"), data);
+    assert_eq!(dir.names(), ["a.gz", "self.gz"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn refuses_a_hard_link_to_an_input() {
+    let dir = TempDir::new();
+    dir.write("a.gz", &gzip(b"a\n"));
+    std::fs::hard_link(dir.join("a.gz"), dir.join("link.gz")).unwrap();
+    assert_eq!(run(&dir, &["concat", "-o", "link.gz", "a.gz"]).code, Some(2));
+    assert_eq!(gunzip(&dir.read("a.gz")), b"a\n");
+}
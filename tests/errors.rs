@@ -0,0 +1,102 @@
+mod common;
+
+use common::{gzip, multi_member, run, TempDir};
+
+// 2つ目のメンバーを壊した入力を書き、展開した時の終了コードと標準エラー出力を返す。
+fn decode_second_member(corrupt: impl FnOnce(&mut Vec<u8>)) -> (Option<i32>, String) {
+    let dir = TempDir::new();
+    let first = gzip(b"first\n");
+    let mut second = gzip(b"second\n");
+    corrupt(&mut second);
+    dir.write("in.gz", &[first, second].concat());
+    let run = run(&dir, &["in.gz"]);
+    (run.code, run.stderr)
+}
+
+#[test]
+fn open_failure() {
+    let dir = TempDir::new();
+    let run = run(&dir, &["missing.gz"]);
+    assert_eq!(run.code, Some(3));
+    assert!(run.stderr.contains("Cannot open file 'missing.gz'"), "{}", run.stderr);
+}
+
+#[test]
+fn not_gzip() {
+    let dir = TempDir::new();
+    dir.write("plain.txt", b"plain text\n");
+    let run = run(&dir, &["plain.txt"]);
+    assert_eq!(run.code, Some(4));
+    assert!(run.stderr.contains("'plain.txt' (member 0, byte offset 0): not in gzip format"), "{}", run.stderr);
+}
+
+#[test]
+fn corrupt_header() {
+    let (code, stderr) = decode_second_member(|member| member[2] = 7);
+    assert_eq!(code, Some(4), "{}", stderr);
+    assert!(stderr.contains("(member 1,"), "{}", stderr);
+}
+
+#[test]
+fn crc_mismatch() {
+    let (code, stderr) = decode_second_member(|member| {
+        let at = member.len() - 8;
+        member[at] ^= 0xff;
+    });
+    assert_eq!(code, Some(5), "{}", stderr);
+    assert!(stderr.contains("CRC32 mismatch in 'in.gz' (member 1,"), "{}", stderr);
+}
+
+#[test]
+fn isize_mismatch() {
+    let (code, stderr) = decode_second_member(|member| {
+        let at = member.len() - 4;
+        member[at] ^= 0xff;
+    });
+    assert_eq!(code, Some(6), "{}", stderr);
+    assert!(stderr.contains("ISIZE mismatch in 'in.gz' (member 1,"), "{}", stderr);
+}
+
+#[test]
+fn truncated() {
+    let (code, stderr) = decode_second_member(|member| member.truncate(member.len() - 3));
+    assert_eq!(code, Some(7), "{}", stderr);
+    assert!(stderr.contains("Unexpected end of file in 'in.gz' (member 1,"), "{}", stderr);
+}
+
+#[test]
+fn trailing_garbage() {
+    let dir = TempDir::new();
+    let data = multi_member(&[b"a\n", b"b\n"]);
+    let len = data.len();
+    dir.write("in.gz", &[data, b"garbage".to_vec()].concat());
+    let run = run(&dir, &["in.gz"]);
+    assert_eq!(run.code, Some(8), "{}", run.stderr);
+    assert!(run.stderr.contains(&format!("'in.gz' (member 2, byte offset {})", len)), "{}", run.stderr);
+    // 壊れた部分の手前までは書き出している。
+    assert_eq!(run.stdout_text(), "a\nb\n");
+}
+
+#[test]
+fn invalid_utf8() {
+    let dir = TempDir::new();
+    dir.write("in.gz", &gzip(b"ok\n\xff\xfe\n"));
+    let run = run(&dir, &["in.gz"]);
+    assert_eq!(run.code, Some(9), "{}", run.stderr);
+    assert!(run.stderr.contains("Invalid UTF-8 in the 2th line of 'in.gz'"), "{}", run.stderr);
+}
+
+#[test]
+fn corrupt_deflate_data() {
+    // 最初のブロックの種類を、使われていない 11 にする。
+    let (code, stderr) = decode_second_member(|member| member[10] = 0x07);
+    assert_eq!(code, Some(10), "{}", stderr);
+    assert!(stderr.contains("Corrupt compressed data in 'in.gz' (member 1,"), "{}", stderr);
+}
+
+#[test]
+fn no_panic_backtrace() {
+    let (_, stderr) = decode_second_member(|member| member.truncate(12));
+    assert!(stderr.starts_with("gzip_test: "), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
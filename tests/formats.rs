@@ -0,0 +1,108 @@
+mod common;
+
+use std::io::Write;
+
+use common::{gzip, run, run_in, TempDir};
+use flate2::write::{DeflateEncoder, ZlibEncoder};
+use flate2::Compression;
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn raw_deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[test]
+fn detects_zlib() {
+    let dir = TempDir::new();
+    dir.write("in.zz", &zlib(b"zlib text\n"));
+    for args in [&["in.zz"][..], &["-f", "in.zz"]] {
+        let run = run(&dir, args);
+        assert_eq!(run.code, Some(0), "{:?}: {}", args, run.stderr);
+        assert_eq!(run.stdout_text(), "zlib text\n", "{:?}", args);
+    }
+    let piped = run_in(dir.path(), &["-"], &zlib(b"from a pipe\n"));
+    assert_eq!(piped.stdout_text(), "from a pipe\n");
+    // -f で先頭を確かめる範囲より長いストリームも展開する。
+    let mut seed = 1u32;
+    let noise: Vec<u8> = (0..200_000)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as u8
+        })
+        .collect();
+    dir.write("noise.zz", &zlib(&noise));
+    let long = run(&dir, &["-f", "-b", "noise.zz"]);
+    assert_eq!(long.code, Some(0), "{}", long.stderr);
+    assert!(long.stdout == noise);
+}
+
+#[test]
+fn zlib_errors() {
+    let dir = TempDir::new();
+    let data = zlib(b"zlib text\n");
+    let mut bad_adler = data.clone();
+    let last = bad_adler.len() - 1;
+    bad_adler[last] ^= 1;
+    let cases = [
+        ("short.zz", data[..data.len() - 6].to_vec(), 7),
+        ("adler.zz", bad_adler, 10),
+        ("garbage.zz", [data.clone(), b"more".to_vec()].concat(), 8),
+    ];
+    for (name, data, code) in cases {
+        dir.write(name, &data);
+        let run = run(&dir, &[name]);
+        assert_eq!(run.code, Some(code), "{}: {}", name, run.stderr);
+        assert!(run.stderr.contains(&format!("'{}' (member 0,", name)), "{}: {}", name, run.stderr);
+    }
+}
+
+#[test]
+fn raw_deflate_needs_raw() {
+    let dir = TempDir::new();
+    dir.write("in.deflate", &raw_deflate(b"raw text\n"));
+    let raw = run(&dir, &["--raw", "in.deflate"]);
+    assert_eq!(raw.code, Some(0), "{}", raw.stderr);
+    assert_eq!(raw.stdout_text(), "raw text\n");
+    assert_eq!(run(&dir, &["in.deflate"]).code, Some(4));
+    // gzip のファイルは raw deflate としては展開できない。
+    dir.write("in.gz", &gzip(b"gzip\n"));
+    assert_eq!(run(&dir, &["--raw", "in.gz"]).code, Some(10));
+}
+
+#[test]
+fn force_passes_plain_input_through() {
+    let dir = TempDir::new();
+    dir.write("plain.txt", b"plain text\n");
+    dir.write("in.gz", &gzip(b"gzip\n"));
+    let run = run(&dir, &["-f", "plain.txt", "in.gz"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout_text(), "plain text\ngzip\n");
+    assert_eq!(common::run(&dir, &["plain.txt"]).code, Some(4));
+}
+
+#[test]
+fn force_passes_text_that_looks_like_zlib_through() {
+    let dir = TempDir::new();
+    let long: String = (0..10_000).map(|i| format!("x^{} and more text\n", i)).collect();
+    // どれも CMF と FLG が zlib のヘッダーの条件を満たす。
+    let texts = ["HK\n".to_string(), "x^2 + y^2\n".to_string(), "(S) and (T)\n".to_string(), "XG\n".to_string(), long];
+    for text in &texts {
+        let label = &text[..text.len().min(10)];
+        dir.write("in.txt", text.as_bytes());
+        let run = run(&dir, &["-f", "in.txt"]);
+        assert_eq!(run.code, Some(0), "{:?}: {}", label, run.stderr);
+        assert!(run.stdout_text() == *text, "{:?}", label);
+        let piped = run_in(dir.path(), &["-f", "-"], text.as_bytes());
+        assert!(piped.stdout_text() == *text, "{:?} from a pipe", label);
+    }
+    // -f がなければ zlib として展開して失敗する。
+    dir.write("in.txt", b"HK\n");
+    assert_ne!(run(&dir, &["in.txt"]).code, Some(0));
+}
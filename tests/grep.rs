@@ -0,0 +1,117 @@
+mod common;
+
+use common::{gzip, multi_member, run, TempDir};
+
+// 1行目から10行目までの "line N" を、4行ずつのメンバーに分けて書いた入力。
+fn numbered() -> TempDir {
+    let dir = TempDir::new();
+    let lines: Vec<String> = (1..=10).map(|i| format!("line {}\n", i)).collect();
+    let parts: Vec<String> = lines.chunks(4).map(|chunk| chunk.concat()).collect();
+    dir.write("in.gz", &multi_member(&parts.iter().map(|part| part.as_bytes()).collect::<Vec<_>>()));
+    dir
+}
+
+fn grep(dir: &TempDir, args: &[&str]) -> String {
+    let run = run(dir, &[&["grep"], args].concat());
+    assert_eq!(run.code, Some(0), "{:?}: {}", args, run.stderr);
+    run.stdout_text()
+}
+
+#[test]
+fn prints_the_file_and_member_of_each_line() {
+    let dir = numbered();
+    assert_eq!(grep(&dir, &["line [26]$", "in.gz"]), "in.gz:0:line 2\nin.gz:1:line 6\n");
+    assert_eq!(grep(&dir, &["-n", "line 1", "in.gz"]), "in.gz:0:1:line 1\nin.gz:2:10:line 10\n");
+}
+
+#[test]
+fn invert_and_count() {
+    let dir = numbered();
+    assert_eq!(grep(&dir, &["-v", "[2-9]", "in.gz"]), "in.gz:0:line 1\nin.gz:2:line 10\n");
+    assert_eq!(grep(&dir, &["-c", "[13579]$", "in.gz"]), "in.gz:5\n");
+    assert_eq!(grep(&dir, &["-vc", "[13579]$", "in.gz"]), "in.gz:5\n");
+}
+
+#[test]
+fn context_lines_are_separated_by_dashes() {
+    let dir = numbered();
+    assert_eq!(
+        grep(&dir, &["-n", "-C", "1", "line [29]$", "in.gz"]),
+        "in.gz-0-1-line 1\nin.gz:0:2:line 2\nin.gz-0-3-line 3\n--\nin.gz-1-8-line 8\nin.gz:2:9:line 9\nin.gz-2-10-line 10\n"
+    );
+    // 続いている行は区切らない。
+    assert_eq!(grep(&dir, &["-A1", "line [34]$", "in.gz"]), "in.gz:0:line 3\nin.gz:0:line 4\nin.gz-1-line 5\n");
+    assert_eq!(grep(&dir, &["-B", "2", "line 10", "in.gz"]), "in.gz-1-line 8\nin.gz-2-line 9\nin.gz:2:line 10\n");
+}
+
+#[test]
+fn ignore_case_whole_words_and_fixed_strings() {
+    let dir = TempDir::new();
+    dir.write("in.gz", &gzip(b"Foo bar\nfoobar\nfoo_bar\na.c\nabc\n"));
+    assert_eq!(grep(&dir, &["-i", "^foo", "in.gz"]), "in.gz:0:Foo bar\nin.gz:0:foobar\nin.gz:0:foo_bar\n");
+    assert_eq!(grep(&dir, &["-w", "-i", "foo", "in.gz"]), "in.gz:0:Foo bar\n");
+    assert_eq!(grep(&dir, &["--word-regexp", "bar", "in.gz"]), "in.gz:0:Foo bar\n");
+    assert_eq!(grep(&dir, &["-F", "a.c", "in.gz"]), "in.gz:0:a.c\n");
+    assert_eq!(grep(&dir, &["a.c", "in.gz"]), "in.gz:0:a.c\nin.gz:0:abc\n");
+}
+
+#[test]
+fn short_options_can_be_bundled() {
+    let dir = numbered();
+    let separate = grep(&dir, &["-i", "-n", "-v", "-C", "0", "LINE [1-8]", "in.gz"]);
+    assert_eq!(separate, "in.gz:2:9:line 9\n");
+    assert_eq!(grep(&dir, &["-inv", "-C0", "LINE [1-8]", "in.gz"]), separate);
+    assert_eq!(grep(&dir, &["-invC0", "LINE [1-8]", "in.gz"]), separate);
+    assert_eq!(grep(&dir, &["-ie", "LINE 9", "in.gz"]), "in.gz:2:line 9\n");
+}
+
+#[test]
+fn pattern_after_e_may_start_with_a_dash() {
+    let dir = TempDir::new();
+    dir.write("in.gz", &gzip(b"-x\ny\n"));
+    assert_eq!(grep(&dir, &["-e", "-x", "in.gz"]), "in.gz:0:-x\n");
+}
+
+#[test]
+fn several_inputs() {
+    let dir = TempDir::new();
+    dir.write("a.gz", &gzip(b"hit a\nmiss\n"));
+    dir.write("b.gz", &gzip(b"miss\nhit b\n"));
+    assert_eq!(grep(&dir, &["hit", "a.gz", "b.gz"]), "a.gz:0:hit a\nb.gz:0:hit b\n");
+    assert_eq!(grep(&dir, &["-c", "hit", "a.gz", "b.gz"]), "a.gz:1\nb.gz:1\n");
+}
+
+#[test]
+fn no_selected_line_exits_with_15() {
+    let dir = numbered();
+    let none = run(&dir, &["grep", "nothing", "in.gz"]);
+    assert_eq!(none.code, Some(15), "{}", none.stderr);
+    assert!(none.stdout.is_empty());
+    // -c でも数は表示する。
+    let count = run(&dir, &["grep", "-c", "nothing", "in.gz"]);
+    assert_eq!(count.code, Some(15));
+    assert_eq!(count.stdout_text(), "in.gz:0\n");
+}
+
+#[test]
+fn invalid_patterns_are_usage_errors() {
+    let dir = numbered();
+    for pattern in ["(a", "a{2000}", "((a{1000}){1000}){1000}"] {
+        let run = run(&dir, &["grep", pattern, "in.gz"]);
+        assert_eq!(run.code, Some(2), "{}: {}", pattern, run.stderr);
+        assert!(run.stderr.contains(&format!("invalid pattern '{}'", pattern)), "{}", run.stderr);
+    }
+    let run = run(&dir, &["grep", "-x", "line", "in.gz"]);
+    assert_eq!(run.code, Some(2));
+    assert!(run.stderr.contains("unknown option '-x'"), "{}", run.stderr);
+}
+
+#[test]
+fn invalid_utf8_is_matched_as_replacement_characters() {
+    let dir = TempDir::new();
+    dir.write("in.gz", &gzip(b"a\xffb\nab\n"));
+    let run = run(&dir, &["grep", "a.b", "in.gz"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    // 行はそのまま書き出す。
+    assert_eq!(run.stdout, b"in.gz:0:a\xffb\n");
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use common::{checkpoint_lines, gzip, multi_member, run, TempDir};
+
+fn text() -> Vec<u8> {
+    (1..=50_000).map(|i| format!("line {}\n", i)).collect::<String>().into_bytes()
+}
+
+// 3つのメンバーに分けた big.gz と、その索引を作る。
+fn indexed(dir: &TempDir) -> Vec<u8> {
+    let data = text();
+    let third = data.len() / 3;
+    dir.write("big.gz", &multi_member(&[&data[..third], &data[third..2 * third], &data[2 * third..]]));
+    let index = run(dir, &["index", "--span", "16K", "big.gz"]);
+    assert_eq!(index.code, Some(0), "{}", index.stderr);
+    assert!(index.stdout_text().starts_with("big.gz: "), "{}", index.stdout_text());
+    assert!(index.stdout_text().ends_with(&format!(" checkpoint(s), {} bytes, 50000 lines -> big.gz.gzidx\n", data.len())));
+    assert!(dir.exists("big.gz.gzidx"));
+    data
+}
+
+#[test]
+fn extract_with_and_without_an_index() {
+    let dir = TempDir::new();
+    let data = indexed(&dir);
+    let cases: [(&[&str], Vec<u8>); 4] = [
+        (&["--lines", "25000..25002"], b"line 25000\nline 25001\nline 25002\n".to_vec()),
+        (&["--lines", "49999.."], b"line 49999\nline 50000\n".to_vec()),
+        (&["--bytes", "300000..300020"], data[300_000..300_020].to_vec()),
+        (&["--bytes", "..10"], data[..10].to_vec()),
+    ];
+    for (range, expected) in &cases {
+        let args = [&["extract"][..], range, &["big.gz"]].concat();
+        let with_index = run(&dir, &args);
+        assert_eq!(with_index.code, Some(0), "{:?}: {}", range, with_index.stderr);
+        assert_eq!(&with_index.stdout, expected, "{:?}", range);
+    }
+    std::fs::remove_file(dir.join("big.gz.gzidx")).unwrap();
+    for (range, expected) in &cases {
+        let args = [&["extract"][..], range, &["big.gz"]].concat();
+        assert_eq!(&run(&dir, &args).stdout, expected, "{:?} without an index", range);
+    }
+}
+
+#[test]
+fn extract_lines_next_to_every_checkpoint() {
+    let dir = TempDir::new();
+    let data = indexed(&dir);
+    let lines: Vec<&[u8]> = data.split_inclusive(|&b| b == b'\n').collect();
+    let checkpoints = checkpoint_lines(&dir.read("big.gz.gzidx"));
+    assert!(checkpoints.len() > 2, "{:?}", checkpoints);
+    for line in checkpoints.iter().flat_map(|&cp| [cp + 1, cp + 2]).filter(|&line| line <= lines.len() as u64) {
+        let extract = run(&dir, &["extract", "--lines", &format!("{}..{}", line, line + 1), "big.gz"]);
+        assert_eq!(extract.code, Some(0), "{}", extract.stderr);
+        let expected: Vec<u8> = lines[line as usize - 1..].iter().take(2).flat_map(|line| line.to_vec()).collect();
+        assert_eq!(extract.stdout_text(), String::from_utf8(expected).unwrap(), "line {}", line);
+    }
+}
+
+#[test]
+fn index_is_replaced_through_a_temporary_file() {
+    let dir = TempDir::new();
+    indexed(&dir);
+    let first = dir.read("big.gz.gzidx");
+    dir.write("big.gz.gzidx", b"truncated");
+    assert_eq!(run(&dir, &["index", "--span", "16K", "big.gz"]).code, Some(0));
+    assert_eq!(dir.read("big.gz.gzidx"), first);
+    assert_eq!(dir.names(), ["big.gz", "big.gz.gzidx"]);
+}
+
+#[test]
+fn corrupt_index_exits_with_14() {
+    let dir = TempDir::new();
+    indexed(&dir);
+    let mut index = dir.read("big.gz.gzidx");
+    index.truncate(index.len() / 2);
+    dir.write("big.gz.gzidx", &index);
+    let extract = run(&dir, &["extract", "--lines", "2..2", "big.gz"]);
+    assert_eq!(extract.code, Some(14));
+    assert!(extract.stderr.contains("invalid index file 'big.gz.gzidx'"), "{}", extract.stderr);
+    assert!(extract.stdout.is_empty());
+}
+
+#[test]
+fn index_refuses_the_standard_input() {
+    let dir = TempDir::new();
+    let index = run(&dir, &["index", "-"]);
+    assert_eq!(index.code, Some(2));
+    assert!(index.stderr.contains("'index' cannot read from the standard input"), "{}", index.stderr);
+}
+
+#[test]
+fn extract_writes_its_output_atomically() {
+    let dir = TempDir::new();
+    let data = gzip(b"0123456789\n");
+    dir.write("in.gz", &data);
+    let refused = run(&dir, &["extract", "--bytes", "2..5", "-o", "in.gz", "in.gz"]);
+    assert_eq!(refused.code, Some(2), "{}", refused.stderr);
+    assert!(refused.stderr.contains("the output 'in.gz' is the input file 'in.gz'"), "{}", refused.stderr);
+    assert_eq!(dir.read("in.gz"), data);
+    dir.write("out.txt", b"old\n");
+    assert_eq!(run(&dir, &["extract", "--bytes", "2..5", "-o", "out.txt", "in.gz"]).code, Some(0));
+    assert_eq!(dir.read("out.txt"), b"234");
+    dir.write("short.gz", &data[..data.len() - 9]);
+    assert_ne!(run(&dir, &["extract", "-o", "out.txt", "short.gz"]).code, Some(0));
+    assert_eq!(dir.read("out.txt"), b"234");
+    assert_eq!(dir.names(), ["in.gz", "out.txt", "short.gz"]);
+}
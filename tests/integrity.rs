@@ -0,0 +1,44 @@
+mod common;
+
+use common::{gzip, multi_member, run, TempDir};
+
+#[test]
+fn reports_every_file_and_a_summary() {
+    let dir = TempDir::new();
+    dir.write("good.gz", &multi_member(&[b"hello\n", b"world\n"]));
+    let mut bad = multi_member(&[b"hello\n", b"world\n"]);
+    let at = bad.len() - 8;
+    bad[at] ^= 1;
+    dir.write("bad.gz", &bad);
+    let run = run(&dir, &["test", "good.gz", "bad.gz", "missing.gz"]);
+    // 最初に失敗したファイルの終了コードを返す。
+    assert_eq!(run.code, Some(5), "{}", run.stderr);
+    let text = run.stdout_text();
+    assert!(text.contains("good.gz: OK (2 member(s), 12 bytes)\n"), "{}", text);
+    assert!(text.contains("bad.gz: FAILED: CRC32 mismatch in 'bad.gz' (member 1,"), "{}", text);
+    assert!(text.contains("missing.gz: FAILED: Cannot open file 'missing.gz'"), "{}", text);
+    assert!(text.ends_with("3 file(s) tested, 1 OK, 2 failed\n"), "{}", text);
+    assert!(run.stderr.contains("2 of 3 file(s) failed the integrity test"), "{}", run.stderr);
+}
+
+#[test]
+fn all_ok() {
+    let dir = TempDir::new();
+    dir.write("a.gz", &gzip(b"a"));
+    let run = run(&dir, &["test", "a.gz"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout_text(), "a.gz: OK (1 member(s), 1 bytes)\n1 file(s) tested, 1 OK, 0 failed\n");
+}
+
+#[test]
+fn quiet_prints_only_failures() {
+    let dir = TempDir::new();
+    dir.write("a.gz", &gzip(b"a"));
+    let data = gzip(b"b");
+    dir.write("short.gz", &data[..data.len() - 2]);
+    let run = run(&dir, &["test", "-q", "a.gz", "short.gz"]);
+    assert_eq!(run.code, Some(7), "{}", run.stderr);
+    let text = run.stdout_text();
+    assert!(!text.contains("a.gz: OK"), "{}", text);
+    assert!(text.starts_with("short.gz: FAILED: Unexpected end of file"), "{}", text);
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use std::io::{Cursor, Read};
+
+use common::{gzip, multi_member, TempDir};
+use gzip_test::{open_buffered, open_decoder, open_reader, open_seekable, read_header, verify, GzTestError, Header, Lines, MultiMemberDecoder};
+
+fn decode(data: &[u8]) -> Result<Vec<u8>, GzTestError> {
+    let mut out = Vec::new();
+    open_reader(data, "test.gz").read_to_end(&mut out).map_err(|err| GzTestError::from_io(err).unwrap())?;
+    Ok(out)
+}
+
+#[test]
+fn open_reader_decodes_every_member() {
+    let data = multi_member(&[b"first\n", b"", b"second\n"]);
+    assert_eq!(decode(&data).unwrap(), b"first\nsecond\n");
+    // gzip と同じく、空の入力はヘッダーの途中で終わったものとする。
+    assert!(matches!(decode(b"").unwrap_err(), GzTestError::Truncated { .. }));
+}
+
+#[test]
+fn errors_carry_the_member_and_offset() {
+    let first = gzip(b"first\n");
+    let mut second = gzip(b"second\n");
+    let crc = second.len() - 8;
+    second[crc] ^= 1;
+    let err = decode(&[first.clone(), second].concat()).unwrap_err();
+    assert!(matches!(err, GzTestError::Crc { .. }), "{}", err);
+    assert_eq!(err.exit_code(), 5);
+    assert_eq!((err.location().file.as_str(), err.location().member), ("test.gz", 1));
+    assert!(err.to_string().contains("'test.gz' (member 1,"), "{}", err);
+    let err = decode(&[first, b"junk".to_vec()].concat()).unwrap_err();
+    assert!(matches!(err, GzTestError::TrailingGarbage { .. }), "{}", err);
+}
+
+#[test]
+fn open_decoder_reads_a_file() {
+    let dir = TempDir::new();
+    let path = dir.write("in.gz", &multi_member(&[b"a\n", b"b\n"]));
+    let mut out = String::new();
+    open_decoder(path.to_str().unwrap()).unwrap().read_to_string(&mut out).unwrap();
+    assert_eq!(out, "a\nb\n");
+    let missing = dir.join("missing.gz");
+    let err = open_decoder(missing.to_str().unwrap()).err().unwrap();
+    assert!(matches!(err, GzTestError::Open { .. }), "{}", err);
+    assert_eq!(err.exit_code(), 3);
+}
+
+// map_input_files を呼んでいないので、開いたファイルが切り詰められてもマップした領域を読んで SIGBUS で止まることはない。
+#[test]
+fn openers_read_a_file_truncated_while_open() {
+    let dir = TempDir::new();
+    let path = dir.write("in.gz", &vec![b'x'; 1 << 16]);
+    let name = path.to_str().unwrap();
+    let mut buffered = open_buffered(name).unwrap();
+    let mut seekable = open_seekable(name).unwrap();
+    std::fs::File::create(&path).unwrap();
+    let mut out = Vec::new();
+    buffered.read_to_end(&mut out).unwrap();
+    seekable.read_to_end(&mut out).unwrap();
+    assert!(out.is_empty());
+}
+
+#[test]
+fn next_member_writes_one_member_at_a_time() {
+    let first = gzip(b"first\n");
+    let data = [first.clone(), gzip(b"second!\n")].concat();
+    let mut decoder = MultiMemberDecoder::new(&data[..], "test.gz");
+    let mut out = Vec::new();
+    let info = decoder.next_member(&mut out).unwrap().unwrap();
+    assert_eq!((info.index, info.offset, info.compressed_len, info.uncompressed_len), (0, 0, first.len() as u64, 6));
+    assert_eq!(out, b"first\n");
+    let info = decoder.next_member(&mut out).unwrap().unwrap();
+    assert_eq!((info.index, info.offset, info.trailer.isize), (1, first.len() as u64, 8));
+    assert_eq!(out, b"first\nsecond!\n");
+    assert!(decoder.next_member(&mut out).unwrap().is_none());
+}
+
+#[test]
+fn one_member_leaves_the_rest_unread() {
+    let first = gzip(b"first\n");
+    let data = [first.clone(), gzip(b"second\n")].concat();
+    let mut decoder = MultiMemberDecoder::new(Cursor::new(&data), "test.gz").one_member();
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+    assert_eq!(out, b"first\n");
+    assert_eq!(decoder.into_inner().position(), first.len() as u64);
+}
+
+#[test]
+fn verify_returns_every_member() {
+    let data = multi_member(&[b"abc", b"defg"]);
+    let infos = verify(&data[..], "test.gz").unwrap();
+    assert_eq!(infos.len(), 2);
+    assert_eq!(infos[1].offset, infos[0].compressed_len);
+    assert_eq!((infos[0].trailer.isize, infos[1].trailer.isize), (3, 4));
+    assert_eq!(infos[1].trailer.crc32, crc32(b"defg"));
+    let err = verify(&data[..data.len() - 1], "test.gz").unwrap_err();
+    assert!(matches!(err, GzTestError::Truncated { .. }), "{}", err);
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = flate2::Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+#[test]
+fn lines_are_counted_across_members() {
+    let data = multi_member(&[b"one\r\ntw", b"o\n\nthree"]);
+    let mut lines = Lines::new(open_reader(&data[..], "test.gz"));
+    let mut read = Vec::new();
+    while let Some(line) = lines.next() {
+        read.push((lines.line_number(), line.unwrap()));
+    }
+    let expected = [(1, "one"), (2, "two"), (3, ""), (4, "three")];
+    assert_eq!(read, expected.map(|(n, line)| (n, line.to_string())));
+}
+
+#[test]
+fn lines_stop_after_an_error() {
+    let data = gzip(b"ok\nbad \xff\nnot reached\n");
+    let mut lines = Lines::new(open_reader(&data[..], "test.gz"));
+    assert_eq!(lines.next().unwrap().unwrap(), "ok");
+    let err = lines.next().unwrap().unwrap_err();
+    assert!(matches!(err, GzTestError::Utf8 { line: 2, .. }), "{}", err);
+    assert_eq!(err.exit_code(), 9);
+    assert!(lines.next().is_none());
+}
+
+#[test]
+fn header_round_trip() {
+    let header = Header {
+        flags: 1,
+        mtime: 1_700_000_000,
+        xfl: 2,
+        os: 3,
+        extra: Some(b"AB\x02\x00hi".to_vec()),
+        filename: Some(b"name.txt".to_vec()),
+        comment: Some(b"a comment".to_vec()),
+        header_crc: Some(0),
+        len: 0,
+    };
+    let bytes = header.to_bytes().unwrap();
+    let read = read_header(&mut &bytes[..]).unwrap();
+    assert_eq!(read.flags, 0x1f);
+    assert_eq!(read.len, bytes.len() as u64);
+    assert_eq!((read.mtime, read.xfl, read.os), (1_700_000_000, 2, 3));
+    assert_eq!((&read.filename, &read.comment), (&header.filename, &header.comment));
+    let subfields = read.extra_subfields();
+    assert_eq!(subfields.len(), 1);
+    assert_eq!((subfields[0].si1, subfields[0].si2, subfields[0].data), (b'A', b'B', &b"hi"[..]));
+    let mut bad_crc = bytes.clone();
+    let last = bad_crc.len() - 1;
+    bad_crc[last] ^= 1;
+    assert!(read_header(&mut &bad_crc[..]).is_err());
+    assert!(read_header(&mut &b"plain text"[..]).is_err());
+}
+
+#[test]
+fn header_fields_that_do_not_fit_are_refused() {
+    let fits = Header { extra: Some(vec![0; 65535]), ..Header::default() };
+    let bytes = fits.to_bytes().unwrap();
+    assert_eq!(read_header(&mut &bytes[..]).unwrap().extra, fits.extra);
+    let too_long = Header { extra: Some(vec![0; 65536]), ..Header::default() };
+    assert_eq!(too_long.to_bytes().unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    let zero_in_name = Header { filename: Some(b"a\0b".to_vec()), ..Header::default() };
+    assert_eq!(zero_in_name.to_bytes().unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+}
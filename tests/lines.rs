@@ -0,0 +1,114 @@
+mod common;
+
+use common::{checkpoint_lines, gzip, multi_member, run, TempDir};
+
+// "\n" で終わらない最後の行を含む、3つのメンバーに分けた5行の入力。
+fn five_lines() -> TempDir {
+    let dir = TempDir::new();
+    dir.write("in.gz", &multi_member(&[b"one\ntw", b"o\nthree\n", b"four\nfive"]));
+    dir
+}
+
+fn decode(dir: &TempDir, args: &[&str]) -> String {
+    let run = run(dir, args);
+    assert_eq!(run.code, Some(0), "{:?}: {}", args, run.stderr);
+    run.stdout_text()
+}
+
+#[test]
+fn head_lines_and_tail() {
+    let dir = five_lines();
+    assert_eq!(decode(&dir, &["--head", "2", "in.gz"]), "one\ntwo\n");
+    assert_eq!(decode(&dir, &["--head", "9", "in.gz"]), "one\ntwo\nthree\nfour\nfive\n");
+    assert_eq!(decode(&dir, &["--lines", "2..4", "in.gz"]), "two\nthree\nfour\n");
+    assert_eq!(decode(&dir, &["--lines", "4..", "in.gz"]), "four\nfive\n");
+    assert_eq!(decode(&dir, &["--tail", "2", "in.gz"]), "four\nfive\n");
+    assert_eq!(decode(&dir, &["--tail", "9", "in.gz"]), "one\ntwo\nthree\nfour\nfive\n");
+    assert_eq!(decode(&dir, &["--tail", "0", "in.gz"]), "");
+}
+
+#[test]
+fn each_input_is_counted_separately() {
+    let dir = five_lines();
+    dir.write("b.gz", &gzip(b"b1\nb2\nb3\n"));
+    assert_eq!(decode(&dir, &["--head", "1", "in.gz", "b.gz"]), "one\nb1\n");
+    assert_eq!(decode(&dir, &["--tail", "1", "in.gz", "b.gz"]), "five\nb3\n");
+}
+
+#[test]
+fn head_stops_before_a_corrupt_part() {
+    let dir = TempDir::new();
+    let mut data = multi_member(&[b"one\ntwo\n", b"three\n"]);
+    data.extend_from_slice(b"garbage");
+    dir.write("in.gz", &data);
+    assert_eq!(decode(&dir, &["--head", "2", "in.gz"]), "one\ntwo\n");
+    assert_eq!(run(&dir, &["--tail", "2", "in.gz"]).code, Some(8));
+}
+
+#[test]
+fn only_one_selection_is_allowed() {
+    let dir = five_lines();
+    for args in [&["--head", "1", "--tail", "1", "in.gz"][..], &["--lines", "1..2", "--head", "1", "in.gz"], &["--tail", "1", "--recover", "in.gz"]] {
+        assert_eq!(run(&dir, args).code, Some(2), "{:?}", args);
+    }
+}
+
+// 10万行を3つのメンバーに分けた big.gz と、その索引を作る。
+fn indexed() -> TempDir {
+    let dir = TempDir::new();
+    let text: String = (1..=100_000).map(|i| format!("line {}\n", i)).collect();
+    let (head, tail) = text.split_at(text.len() / 3);
+    let (middle, tail) = tail.split_at(tail.len() / 2);
+    dir.write("big.gz", &multi_member(&[head.as_bytes(), middle.as_bytes(), tail.as_bytes()]));
+    assert_eq!(run(&dir, &["index", "--span", "64K", "big.gz"]).code, Some(0));
+    dir
+}
+
+#[test]
+fn tail_with_an_index() {
+    let dir = indexed();
+    let run = run(&dir, &["--tail", "3", "big.gz"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout_text(), "line 99998\nline 99999\nline 100000\n");
+    assert!(run.stderr.is_empty(), "{}", run.stderr);
+}
+
+#[test]
+fn tail_starting_next_to_every_checkpoint() {
+    let dir = indexed();
+    let checkpoints = checkpoint_lines(&dir.read("big.gz.gzidx"));
+    assert!(checkpoints.len() > 2, "{:?}", checkpoints);
+    // 最初の行がチェックポイントのある行 (cp + 1 行目) とその次の行になる N。
+    for count in checkpoints.iter().filter(|&&cp| cp > 0).flat_map(|&cp| [100_000 - cp, 100_000 - cp - 1]) {
+        let tail = run(&dir, &["--tail", &count.to_string(), "big.gz"]);
+        assert_eq!(tail.code, Some(0), "{}", tail.stderr);
+        let expected: String = (100_001 - count..=100_000).map(|i| format!("line {}\n", i)).collect();
+        assert!(tail.stdout_text() == expected, "--tail {}: starts with {:?}", count, tail.stdout_text().lines().next());
+    }
+}
+
+#[test]
+fn tail_with_a_stale_index_decodes_from_the_beginning() {
+    let dir = indexed();
+    let mut data = dir.read("big.gz");
+    data.extend(gzip(b"appended 1\nappended 2\n"));
+    dir.write("big.gz", &data);
+    let tail = run(&dir, &["--tail", "3", "big.gz"]);
+    assert_eq!(tail.code, Some(0), "{}", tail.stderr);
+    assert_eq!(tail.stdout_text(), "line 100000\nappended 1\nappended 2\n");
+    assert!(tail.stderr.contains("warning: index file 'big.gz.gzidx' is out of date"), "{}", tail.stderr);
+    // extract では古い索引をエラーにする。
+    let extract = run(&dir, &["extract", "--lines", "1..1", "big.gz"]);
+    assert_eq!(extract.code, Some(14), "{}", extract.stderr);
+}
+
+#[test]
+fn tail_with_a_corrupt_index_fails() {
+    let dir = indexed();
+    let mut index = dir.read("big.gz.gzidx");
+    index.truncate(index.len() / 2);
+    dir.write("big.gz.gzidx", &index);
+    let run = run(&dir, &["--tail", "1", "big.gz"]);
+    assert_eq!(run.code, Some(14), "{}", run.stderr);
+    assert!(run.stderr.contains("invalid index file 'big.gz.gzidx'"), "{}", run.stderr);
+}
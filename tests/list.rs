@@ -0,0 +1,77 @@
+mod common;
+
+use std::io::Write;
+
+use common::{gzip, run, TempDir};
+use flate2::{Compression, GzBuilder};
+
+fn with_header_fields(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzBuilder::new()
+        .filename("original.txt")
+        .comment("a comment")
+        .mtime(1_700_000_000)
+        .write(Vec::new(), Compression::best());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[test]
+fn lists_every_member_with_offsets_and_sizes() {
+    let dir = TempDir::new();
+    let first = with_header_fields(b"hello\n");
+    let second = gzip(b"world!\n");
+    dir.write("in.gz", &[first.clone(), second.clone()].concat());
+    let run = run(&dir, &["list", "in.gz"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let text = run.stdout_text();
+    assert!(text.contains("  member 0\n    offset             0\n"), "{}", text);
+    assert!(text.contains(&format!("  member 1\n    offset             {}\n", first.len())), "{}", text);
+    assert!(text.contains("FLG                0x18 (FNAME FCOMMENT)\n"), "{}", text);
+    assert!(text.contains("FNAME              original.txt\n"), "{}", text);
+    assert!(text.contains("FCOMMENT           a comment\n"), "{}", text);
+    assert!(text.contains("MTIME              1700000000 (2023-11-14 22:13:20 UTC)\n"), "{}", text);
+    assert!(text.contains("ISIZE              7\n"), "{}", text);
+    assert!(text.contains(&format!("2 member(s), {} bytes compressed, 13 bytes uncompressed", first.len() + second.len())), "{}", text);
+}
+
+#[test]
+fn inspect_is_an_alias_of_list() {
+    let dir = TempDir::new();
+    dir.write("in.gz", &gzip(b"x"));
+    assert_eq!(run(&dir, &["inspect", "in.gz"]).stdout, run(&dir, &["list", "in.gz"]).stdout);
+}
+
+#[test]
+fn json_output() {
+    let dir = TempDir::new();
+    let first = with_header_fields(b"hello\n");
+    dir.write("in.gz", &[first.clone(), gzip(b"world!\n")].concat());
+    let run = run(&dir, &["list", "--json", "in.gz"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let text = run.stdout_text();
+    assert!(text.starts_with("[\n  {\"file\":\"in.gz\""), "{}", text);
+    assert!(text.contains("\"filename\":\"original.txt\",\"comment\":\"a comment\""), "{}", text);
+    assert!(text.contains(&format!("{{\"index\":1,\"offset\":{},", first.len())), "{}", text);
+    assert!(text.ends_with("}\n]\n"), "{}", text);
+}
+
+#[test]
+fn corrupt_member_is_an_error() {
+    let dir = TempDir::new();
+    let mut data = gzip(b"hello\n");
+    let at = data.len() - 8;
+    data[at] ^= 1;
+    dir.write("in.gz", &data);
+    assert_eq!(run(&dir, &["list", "in.gz"]).code, Some(5));
+}
+
+#[test]
+fn json_is_not_left_open_on_an_error() {
+    let dir = TempDir::new();
+    dir.write("good.gz", &gzip(b"hello\n"));
+    dir.write("bad.gz", &gzip(b"hello\n")[..12]);
+    let run = run(&dir, &["list", "--json", "good.gz", "bad.gz"]);
+    assert_eq!(run.code, Some(7), "{}", run.stderr);
+    assert!(run.stderr.contains("'bad.gz'"), "{}", run.stderr);
+    assert!(run.stdout.is_empty(), "{}", run.stdout_text());
+}
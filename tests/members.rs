@@ -0,0 +1,134 @@
+use std::io::{Read, Write};
+
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::{Compression, Crc};
+use gzip_test::{GzTestError, Header, Members};
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+// header を先頭に置いたメンバーを作る。
+fn member_with(header: &Header, data: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(header.to_bytes().unwrap(), Compression::default());
+    encoder.write_all(data).unwrap();
+    let mut member = encoder.finish().unwrap();
+    let mut crc = Crc::new();
+    crc.update(data);
+    member.extend_from_slice(&crc.sum().to_le_bytes());
+    member.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    member
+}
+
+fn named_header() -> Header {
+    Header {
+        mtime: 1_234_567,
+        xfl: 4,
+        os: 11,
+        extra: Some(b"XY\x03\x00abcZZ\x00\x00".to_vec()),
+        filename: Some(b"first.txt".to_vec()),
+        comment: Some(b"note".to_vec()),
+        ..Header::default()
+    }
+}
+
+#[test]
+fn yields_the_header_range_and_data_of_each_member() {
+    let first = member_with(&named_header(), b"first\n");
+    let second = gzip(b"second member\n");
+    let data = [first.clone(), second.clone()].concat();
+    let mut members = Members::new(&data[..], "test.gz");
+
+    let mut member = members.next_member().unwrap().unwrap();
+    assert_eq!((member.index(), member.offset()), (0, 0));
+    let header = member.header();
+    assert_eq!((header.mtime, header.xfl, header.os), (1_234_567, 4, 11));
+    assert_eq!(header.filename.as_deref(), Some(&b"first.txt"[..]));
+    assert_eq!(header.comment.as_deref(), Some(&b"note"[..]));
+    let subfields: Vec<_> = header.extra_subfields().iter().map(|sub| (sub.si1, sub.si2, sub.data.to_vec())).collect();
+    assert_eq!(subfields, [(b'X', b'Y', b"abc".to_vec()), (b'Z', b'Z', Vec::new())]);
+    // 読み終えるまでは終わりもトレーラーも分からない。
+    assert!(member.compressed_range().is_none() && member.info().is_none());
+    let mut text = String::new();
+    member.read_to_string(&mut text).unwrap();
+    assert_eq!(text, "first\n");
+    assert_eq!(member.compressed_range(), Some(0..first.len() as u64));
+    let info = member.info().unwrap();
+    assert_eq!((info.trailer.isize, info.uncompressed_len), (6, 6));
+    assert_eq!(info.header.filename.as_deref(), Some(&b"first.txt"[..]));
+
+    let member = members.next_member().unwrap().unwrap();
+    assert_eq!((member.index(), member.offset()), (1, first.len() as u64));
+    assert_eq!(member.header().filename, None);
+    let info = member.finish().unwrap();
+    assert_eq!(info.offset..info.offset + info.compressed_len, first.len() as u64..data.len() as u64);
+    assert_eq!(info.uncompressed_len, 14);
+
+    assert!(members.next_member().is_none());
+    assert!(members.next_member().is_none());
+}
+
+#[test]
+fn unread_members_are_still_verified() {
+    let first = gzip(b"skipped\n");
+    let mut second = gzip(b"also skipped\n");
+    let crc = second.len() - 8;
+    second[crc] ^= 1;
+    let data = [first.clone(), second, gzip(b"third\n")].concat();
+    let mut members = Members::new(&data[..], "test.gz");
+    let member = members.next_member().unwrap().unwrap();
+    assert_eq!(member.index(), 0);
+    // 読まずに次へ進むと、残りを展開して確かめる。
+    assert_eq!(members.next_member().unwrap().unwrap().index(), 1);
+    let err = match members.next_member() {
+        Some(Err(err)) => err,
+        other => panic!("expected an error, got {:?}", other.map(|member| member.map(|member| member.index()))),
+    };
+    assert!(matches!(err, GzTestError::Crc { ref at, .. } if at.member == 1 && at.offset > first.len() as u64), "{}", err);
+    // エラーの後は何も返さない。
+    assert!(members.next_member().is_none());
+}
+
+#[test]
+fn partly_read_member_is_finished_before_the_next() {
+    let data = [gzip(&vec![b'x'; 100_000]), gzip(b"next\n")].concat();
+    let mut members = Members::new(&data[..], "test.gz");
+    let mut member = members.next_member().unwrap().unwrap();
+    let mut head = [0; 10];
+    member.read_exact(&mut head).unwrap();
+    assert_eq!(&head, b"xxxxxxxxxx");
+    let mut next = members.next_member().unwrap().unwrap();
+    let mut text = String::new();
+    next.read_to_string(&mut text).unwrap();
+    assert_eq!(text, "next\n");
+}
+
+#[test]
+fn errors_inside_a_member_are_returned_by_read() {
+    let mut data = gzip(b"hello\n");
+    data.truncate(data.len() - 3);
+    let mut members = Members::new(&data[..], "test.gz");
+    let member = members.next_member().unwrap().unwrap();
+    let err = member.finish().unwrap_err();
+    assert!(matches!(err, GzTestError::Truncated { .. }), "{}", err);
+    assert!(members.next_member().is_none());
+}
+
+#[test]
+fn not_gzip_and_empty_input() {
+    let mut members = Members::new(&b"plain text\n"[..], "test.gz");
+    assert!(matches!(members.next_member(), Some(Err(GzTestError::Header { .. }))));
+    let mut members = Members::new(&b""[..], "test.gz");
+    assert!(matches!(members.next_member(), Some(Err(GzTestError::Truncated { .. }))));
+}
+
+#[test]
+fn into_inner_returns_the_rest_of_the_input() {
+    let data = gzip(b"only\n");
+    let mut members = Members::new(&data[..], "test.gz");
+    members.next_member().unwrap().unwrap().finish().unwrap();
+    assert!(members.next_member().is_none());
+    assert!(members.into_inner().is_empty());
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use common::{gzip, run, TempDir};
+
+#[test]
+fn output_appears_only_when_complete() {
+    let dir = TempDir::new();
+    let data = gzip(b"complete\n");
+    dir.write("ok.gz", &data);
+    dir.write("short.gz", &data[..data.len() - 4]);
+    let failed = run(&dir, &["-o", "out.txt", "ok.gz", "short.gz"]);
+    assert_eq!(failed.code, Some(7), "{}", failed.stderr);
+    // 書きかけの出力も一時ファイルも残らない。
+    assert_eq!(dir.names(), ["ok.gz", "short.gz"]);
+    let done = run(&dir, &["-o", "out.txt", "--fsync", "ok.gz"]);
+    assert_eq!(done.code, Some(0), "{}", done.stderr);
+    assert_eq!(dir.read("out.txt"), b"complete\n");
+}
+
+#[test]
+fn force_overwrites_an_existing_output() {
+    let dir = TempDir::new();
+    dir.write("in.gz", &gzip(b"new\n"));
+    dir.write("out.txt", b"old\n");
+    let refused = run(&dir, &["-o", "out.txt", "in.gz"]);
+    assert_eq!(refused.code, Some(1), "{}", refused.stderr);
+    assert!(refused.stderr.contains("'out.txt' already exists; use --force to overwrite it"), "{}", refused.stderr);
+    assert_eq!(dir.read("out.txt"), b"old\n");
+    assert_eq!(run(&dir, &["-o", "out.txt", "-f", "in.gz"]).code, Some(0));
+    assert_eq!(dir.read("out.txt"), b"new\n");
+}
+
+#[test]
+fn gunzip_writes_next_to_each_input() {
+    let dir = TempDir::new();
+    dir.write("a.txt.gz", &gzip(b"a\n"));
+    dir.write("b.tgz", &gzip(b"tar\n"));
+    dir.write("c.z", &gzip(b"c\n"));
+    let run = run(&dir, &["-g", "a.txt.gz", "b.tgz", "c.z"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(run.stdout.is_empty());
+    assert_eq!(dir.names(), ["a.txt", "a.txt.gz", "b.tar", "b.tgz", "c", "c.z"]);
+    assert_eq!(dir.read("a.txt"), b"a\n");
+    assert_eq!(dir.read("b.tar"), b"tar\n");
+}
+
+#[test]
+fn gunzip_needs_a_known_suffix_and_force_to_overwrite() {
+    let dir = TempDir::new();
+    dir.write("data.bin", &gzip(b"x\n"));
+    let suffix = run(&dir, &["-g", "data.bin"]);
+    assert_eq!(suffix.code, Some(1));
+    assert!(suffix.stderr.contains("'data.bin' does not end in .gz or a similar suffix"), "{}", suffix.stderr);
+    dir.write("a.gz", &gzip(b"new\n"));
+    dir.write("a", b"old\n");
+    assert_eq!(run(&dir, &["-g", "a.gz"]).code, Some(1));
+    assert_eq!(dir.read("a"), b"old\n");
+    assert_eq!(run(&dir, &["-g", "-f", "a.gz"]).code, Some(0));
+    assert_eq!(dir.read("a"), b"new\n");
+}
+
+#[test]
+fn delete_removes_inputs_only_after_success() {
+    let dir = TempDir::new();
+    dir.write("a.gz", &gzip(b"a\n"));
+    dir.write("b.gz", &gzip(b"b\n"));
+    let joined = run(&dir, &["-o", "out.txt", "--delete", "a.gz", "b.gz"]);
+    assert_eq!(joined.code, Some(0), "{}", joined.stderr);
+    assert_eq!(dir.names(), ["out.txt"]);
+    assert_eq!(dir.read("out.txt"), b"a\nb\n");
+
+    dir.write("c.gz", &gzip(b"c\n"));
+    assert_eq!(run(&dir, &["-g", "--delete", "c.gz"]).code, Some(0));
+    assert!(!dir.exists("c.gz") && dir.exists("c"));
+
+    let data = gzip(b"short\n");
+    dir.write("short.gz", &data[..data.len() - 1]);
+    assert_eq!(run(&dir, &["-g", "--delete", "short.gz"]).code, Some(7));
+    assert!(dir.exists("short.gz") && !dir.exists("short"));
+}
+
+#[test]
+fn delete_refuses_an_output_that_is_an_input() {
+    let dir = TempDir::new();
+    dir.write("in.gz", &gzip(b"text\n"));
+    let run = run(&dir, &["-o", "in.gz", "--force", "--delete", "in.gz"]);
+    assert_eq!(run.code, Some(2), "{}", run.stderr);
+    assert!(run.stderr.contains("'--delete' would delete the output 'in.gz' along with the input file 'in.gz'"), "{}", run.stderr);
+    assert_eq!(dir.read("in.gz"), gzip(b"text\n"));
+    assert_eq!(dir.names(), ["in.gz"]);
+}
+
+#[test]
+fn output_option_conflicts() {
+    let dir = TempDir::new();
+    dir.write("a.gz", &gzip(b"a\n"));
+    for args in [&["--delete", "a.gz"][..], &["--fsync", "a.gz"], &["-g", "-"], &["--delete", "--keep", "-g", "a.gz"], &["-g", "--delete", "--head", "1", "a.gz"]] {
+        assert_eq!(run(&dir, args).code, Some(2), "{:?}", args);
+    }
+    assert!(dir.exists("a.gz"));
+}
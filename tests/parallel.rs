@@ -0,0 +1,91 @@
+mod common;
+
+use common::{gzip, run, run_in, TempDir};
+
+// 大きさの違うメンバーを並べた、行がメンバーをまたぐテキスト。
+fn members() -> (Vec<u8>, Vec<u8>) {
+    let text: Vec<u8> = (0..40_000).map(|i| format!("line {}\r\n", i)).collect::<String>().into_bytes();
+    let mut data = Vec::new();
+    let mut start = 0;
+    for (i, len) in [1, 0, 5_000, 77, 120_000, 3].into_iter().cycle().enumerate() {
+        let end = (start + len + i).min(text.len());
+        data.extend(gzip(&text[start..end]));
+        start = end;
+        if start == text.len() {
+            break;
+        }
+    }
+    (text, data)
+}
+
+type Corrupt = fn(&mut Vec<u8>);
+
+// -j 1 と同じ終了コード、標準出力、標準エラー出力になることを確かめる。
+fn assert_same_as_one_thread(dir: &TempDir, args: &[&str]) {
+    let one = run(dir, &[&["-j", "1"], args].concat());
+    for jobs in ["2", "4", "0"] {
+        let many = run(dir, &[&["-j", jobs], args].concat());
+        assert_eq!(many.code, one.code, "-j {} {:?}: {}", jobs, args, many.stderr);
+        assert!(many.stdout == one.stdout, "-j {} {:?}", jobs, args);
+        assert_eq!(many.stderr, one.stderr, "-j {} {:?}", jobs, args);
+    }
+}
+
+#[test]
+fn same_output_as_one_thread() {
+    let dir = TempDir::new();
+    let (text, data) = members();
+    dir.write("in.gz", &data);
+    let decoded = run(&dir, &["-j", "4", "in.gz"]);
+    assert_eq!(decoded.code, Some(0), "{}", decoded.stderr);
+    assert_eq!(decoded.stdout_text(), String::from_utf8(text.clone()).unwrap().replace("\r\n", "\n"));
+    let binary = run(&dir, &["-j", "3", "-b", "in.gz"]);
+    assert!(binary.stdout == text);
+    assert_same_as_one_thread(&dir, &["in.gz", "in.gz"]);
+    assert_same_as_one_thread(&dir, &["-b", "in.gz"]);
+}
+
+#[test]
+fn same_errors_as_one_thread() {
+    let dir = TempDir::new();
+    let (_, data) = members();
+    let corruptions: [(&str, Corrupt); 3] = [
+        ("crc", |data| {
+            let at = data.len() / 2;
+            data[at] ^= 0x55;
+        }),
+        ("truncated", |data| data.truncate(data.len() - 5)),
+        ("garbage", |data| data.extend_from_slice(b"garbage")),
+    ];
+    for (name, corrupt) in corruptions {
+        let mut corrupted = data.clone();
+        corrupt(&mut corrupted);
+        dir.write(name, &corrupted);
+        let one = run(&dir, &[name]);
+        assert_ne!(one.code, Some(0), "{}", name);
+        assert_same_as_one_thread(&dir, &[name]);
+    }
+    // UTF-8 の誤りの位置は行を確かめた時の読み込み位置なので、先読みしたメンバーではメンバーの終わりになる。
+    dir.write("utf8.gz", &[gzip(b"ok\n"), gzip(b"bad \xff\n")].concat());
+    let utf8 = run(&dir, &["-j", "2", "utf8.gz"]);
+    assert_eq!(utf8.code, Some(9));
+    assert_eq!(utf8.stdout_text(), "ok\n");
+    assert!(utf8.stderr.contains("Invalid UTF-8 in the 2th line of 'utf8.gz' (member 1,"), "{}", utf8.stderr);
+}
+
+#[test]
+fn reads_the_standard_input() {
+    let dir = TempDir::new();
+    let (text, data) = members();
+    let decoded = run_in(dir.path(), &["-j", "4", "-b", "-"], &data);
+    assert_eq!(decoded.code, Some(0), "{}", decoded.stderr);
+    assert!(decoded.stdout == text);
+}
+
+#[test]
+fn invalid_job_counts() {
+    let dir = TempDir::new();
+    for jobs in ["x", "-1", ""] {
+        assert_eq!(run(&dir, &["-j", jobs, "in.gz"]).code, Some(2), "-j {:?}", jobs);
+    }
+}
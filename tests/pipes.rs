@@ -0,0 +1,94 @@
+mod common;
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use common::{gzip, multi_member, run_in, Run, TempDir};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+// パイプのように、`data` を `chunk` バイトずつ間を空けて標準入力に書く。
+fn run_trickle(dir: &TempDir, args: &[&str], data: &[u8], chunk: usize) -> Run {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gzip_test"))
+        .args(args)
+        .current_dir(dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut input = child.stdin.take().unwrap();
+    let data = data.to_vec();
+    let writer = thread::spawn(move || {
+        for piece in data.chunks(chunk) {
+            if input.write_all(piece).and_then(|_| input.flush()).is_err() {
+                return;
+            }
+            thread::sleep(Duration::from_micros(200));
+        }
+    });
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap();
+    Run { code: output.status.code(), stdout: output.stdout, stderr: String::from_utf8_lossy(&output.stderr).into_owned() }
+}
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[test]
+fn detects_the_format_from_a_byte_at_a_time() {
+    let dir = TempDir::new();
+    let cases: [(&[&str], Vec<u8>, &str); 3] = [
+        (&["-"], multi_member(&[b"first\n", b"second\n"]), "first\nsecond\n"),
+        (&["-"], zlib(b"zlib\n"), "zlib\n"),
+        (&["-f", "-"], b"plain\n".to_vec(), "plain\n"),
+    ];
+    for (args, data, expected) in cases {
+        let run = run_trickle(&dir, args, &data, 1);
+        assert_eq!(run.code, Some(0), "{:?}: {}", expected, run.stderr);
+        assert_eq!(run.stdout_text(), expected);
+    }
+}
+
+#[test]
+fn errors_from_a_pipe() {
+    let dir = TempDir::new();
+    let data = multi_member(&[b"first\n", b"second\n"]);
+    let short = run_trickle(&dir, &["-"], &data[..data.len() - 3], 5);
+    assert_eq!(short.code, Some(7), "{}", short.stderr);
+    assert!(short.stderr.contains("Unexpected end of file in '-' (member 1,"), "{}", short.stderr);
+    // ISIZE が欠けるまでに展開したデータは書き出している。
+    assert_eq!(short.stdout_text(), "first\nsecond\n");
+    assert_eq!(run_trickle(&dir, &["-"], b"plain text\n", 2).code, Some(4));
+}
+
+#[test]
+fn commands_read_a_pipe() {
+    let dir = TempDir::new();
+    let text: Vec<u8> = (1..=3000).map(|i| format!("line {}\n", i)).collect::<String>().into_bytes();
+    let data = multi_member(&[&text[..10_000], &text[10_000..]]);
+    let listed = run_trickle(&dir, &["list", "-"], &data, 100);
+    assert!(listed.stdout_text().contains("2 member(s)"), "{}", listed.stdout_text());
+    assert_eq!(run_trickle(&dir, &["test", "-"], &data, 100).code, Some(0));
+    let extract = run_trickle(&dir, &["extract", "--lines", "1500..1501", "-"], &data, 100);
+    assert_eq!(extract.stdout_text(), "line 1500\nline 1501\n", "{}", extract.stderr);
+    let tail = run_trickle(&dir, &["--tail", "2", "-"], &data, 100);
+    assert_eq!(tail.stdout_text(), "line 2999\nline 3000\n", "{}", tail.stderr);
+    let split = run_trickle(&dir, &["split", "-"], &data, 100);
+    assert_eq!(split.code, Some(0), "{}", split.stderr);
+    assert_eq!(dir.names().len(), 2, "{:?}", dir.names());
+}
+
+#[test]
+fn extract_bytes_from_a_pipe() {
+    let dir = TempDir::new();
+    // 索引のない入力は先頭から順に展開するので、標準入力からも読める。
+    let run = run_in(dir.path(), &["extract", "--bytes", "3..8", "-"], &gzip(b"0123456789\n"));
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout_text(), "34567");
+}
@@ -0,0 +1,206 @@
+use std::io::{Read, Write};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use gzip_test::{open_reader, GzTestError, Lines, PushDecoder, PushLines};
+
+// 各テキストを1つずつのメンバーに圧縮してつなげる。
+fn multi_member(parts: &[&str]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for part in parts {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(part.as_bytes()).unwrap();
+        data.extend(encoder.finish().unwrap());
+    }
+    data
+}
+
+// ソケットから少しずつ届くように、chunk バイトずつ渡して展開する。
+fn push_decode(data: &[u8], chunk: usize) -> Result<Vec<u8>, GzTestError> {
+    let mut decoder = PushDecoder::new("test.gz");
+    let mut out = Vec::new();
+    for piece in data.chunks(chunk) {
+        decoder.feed(piece, &mut out)?;
+    }
+    decoder.finish()?;
+    Ok(out)
+}
+
+fn push_lines(data: &[u8], chunk: usize) -> Result<Vec<String>, GzTestError> {
+    let mut lines = PushLines::new("test.gz");
+    let mut out = Vec::new();
+    for piece in data.chunks(chunk) {
+        lines.feed(piece, &mut out)?;
+    }
+    lines.finish(&mut out)?;
+    Ok(out)
+}
+
+fn pull_decode(data: &[u8]) -> Result<Vec<u8>, GzTestError> {
+    let mut out = Vec::new();
+    open_reader(data, "test.gz").read_to_end(&mut out).map_err(|err| GzTestError::from_io(err).unwrap())?;
+    Ok(out)
+}
+
+fn pull_lines(data: &[u8]) -> Result<Vec<String>, GzTestError> {
+    Lines::new(open_reader(data, "test.gz")).collect()
+}
+
+const CHUNKS: [usize; 4] = [1, 7, 4096, usize::MAX];
+
+#[test]
+fn decodes_every_member() {
+    let data = multi_member(&["first member\n", "", "second member\nno newline", " continues\n"]);
+    for chunk in CHUNKS {
+        assert_eq!(push_decode(&data, chunk).unwrap(), b"first member\nsecond member\nno newline continues\n");
+    }
+}
+
+#[test]
+fn reports_member_infos() {
+    let data = multi_member(&["abc", "defg"]);
+    let mut decoder = PushDecoder::new("test.gz");
+    decoder.feed(&data, &mut Vec::new()).unwrap();
+    decoder.finish().unwrap();
+    let members = decoder.take_members();
+    assert_eq!(members.len(), 2);
+    assert_eq!(members[1].offset, members[0].compressed_len);
+    assert_eq!(members[0].compressed_len + members[1].compressed_len, data.len() as u64);
+    assert_eq!((members[0].uncompressed_len, members[1].uncompressed_len), (3, 4));
+}
+
+#[test]
+fn lines_match_pull_lines() {
+    let text: String = (0..2000).map(|i| format!("line {}\r\n", i)).collect();
+    let (head, tail) = text.split_at(7777);
+    let data = multi_member(&[head, tail, "last line without newline"]);
+    let expected = pull_lines(&data).unwrap();
+    assert_eq!(expected.len(), 2001);
+    for chunk in CHUNKS {
+        assert_eq!(push_lines(&data, chunk).unwrap(), expected);
+    }
+}
+
+// 壊れた入力でも、エラーの種類と位置が MultiMemberDecoder と同じになることを確かめる。
+fn assert_same_error(data: &[u8]) {
+    let expected = pull_decode(data).unwrap_err().to_string();
+    for chunk in CHUNKS {
+        assert_eq!(push_decode(data, chunk).unwrap_err().to_string(), expected, "chunk {}", chunk);
+    }
+}
+
+#[test]
+fn truncated() {
+    let data = multi_member(&["hello\n", "world\n"]);
+    for len in [0, 1, 5, 12, data.len() - 1, data.len() - 8] {
+        let err = push_decode(&data[..len], 3).unwrap_err();
+        assert!(matches!(err, GzTestError::Truncated { .. }), "len {}: {}", len, err);
+    }
+    assert_same_error(&data[..data.len() - 3]);
+}
+
+#[test]
+fn trailing_garbage() {
+    let mut data = multi_member(&["hello\n"]);
+    let len = data.len() as u64;
+    data.extend_from_slice(b"garbage");
+    let err = push_decode(&data, 4).unwrap_err();
+    assert!(matches!(err, GzTestError::TrailingGarbage { ref at } if at.offset == len && at.member == 1), "{}", err);
+    assert_same_error(&data);
+}
+
+#[test]
+fn not_gzip() {
+    assert_same_error(b"plain text\n");
+}
+
+#[test]
+fn crc_mismatch() {
+    let mut data = multi_member(&["hello\n", "world\n"]);
+    let crc = data.len() - 8;
+    data[crc] ^= 0xff;
+    let err = push_decode(&data, 5).unwrap_err();
+    assert!(matches!(err, GzTestError::Crc { ref at, .. } if at.member == 1), "{}", err);
+    assert_same_error(&data);
+}
+
+#[test]
+fn invalid_utf8_line() {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b"ok\nbad \xff line\nok\n").unwrap();
+    let data = encoder.finish().unwrap();
+    for chunk in CHUNKS {
+        let err = push_lines(&data, chunk).unwrap_err();
+        assert!(matches!(err, GzTestError::Utf8 { line: 2, .. }), "{}", err);
+    }
+}
+
+#[test]
+fn lines_stop_after_an_error() {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b"ok\nbad \xff line\nafter\nmore\n").unwrap();
+    let data = encoder.finish().unwrap();
+    let mut lines = PushLines::new("test.gz");
+    let mut out = Vec::new();
+    let (head, tail) = data.split_at(data.len() / 2);
+    let err = lines.feed(head, &mut out).and_then(|()| lines.feed(tail, &mut out)).unwrap_err();
+    assert!(matches!(err, GzTestError::Utf8 { line: 2, .. }), "{}", err);
+    assert_eq!(out, ["ok"]);
+    // Lines と同じく、エラーの後の行は返さず、続けて渡した入力も無視する。
+    lines.feed(tail, &mut out).unwrap();
+    lines.feed(b"garbage", &mut out).unwrap();
+    lines.finish(&mut out).unwrap();
+    assert_eq!(out, ["ok"]);
+}
+
+#[test]
+fn decoder_error_stops_lines() {
+    let mut data = multi_member(&["one\ntwo\n"]);
+    data.extend_from_slice(b"garbage");
+    let mut lines = PushLines::new("test.gz");
+    let mut out = Vec::new();
+    assert!(matches!(lines.feed(&data, &mut out), Err(GzTestError::TrailingGarbage { .. })));
+    lines.feed(&multi_member(&["three\n"]), &mut out).unwrap();
+    lines.finish(&mut out).unwrap();
+    assert!(!out.contains(&"three".to_string()), "{:?}", out);
+}
+
+// 長い FNAME と FCOMMENT を持つメンバー。
+fn member_with_long_header(name_len: usize) -> Vec<u8> {
+    let mut encoder = flate2::GzBuilder::new()
+        .filename(vec![b'n'; name_len])
+        .comment(vec![b'c'; name_len])
+        .extra(vec![b'x'; 300])
+        .write(Vec::new(), Compression::default());
+    encoder.write_all(b"payload\n").unwrap();
+    encoder.finish().unwrap()
+}
+
+#[test]
+fn long_header_fed_byte_by_byte() {
+    // 届くたびにヘッダーを先頭から読み直すと、バイト数の2乗に比例する時間がかかる。
+    let data = [member_with_long_header(200_000), member_with_long_header(10)].concat();
+    let mut decoder = PushDecoder::new("test.gz");
+    let mut out = Vec::new();
+    for byte in data.chunks(1) {
+        decoder.feed(byte, &mut out).unwrap();
+    }
+    decoder.finish().unwrap();
+    assert_eq!(out, b"payload\npayload\n");
+    let members = decoder.take_members();
+    assert_eq!(members[0].header.filename.as_ref().map(Vec::len), Some(200_000));
+    assert_eq!(members[1].header.comment.as_deref(), Some(&b"cccccccccc"[..]));
+}
+
+#[test]
+fn header_split_at_every_point() {
+    let data = member_with_long_header(3);
+    for at in 0..data.len() {
+        let mut decoder = PushDecoder::new("test.gz");
+        let mut out = Vec::new();
+        decoder.feed(&data[..at], &mut out).unwrap();
+        decoder.feed(&data[at..], &mut out).unwrap();
+        decoder.finish().unwrap();
+        assert_eq!(out, b"payload\n", "split at {}", at);
+    }
+}
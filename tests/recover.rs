@@ -0,0 +1,79 @@
+mod common;
+
+use common::{gzip, run, TempDir};
+
+fn corrupt_crc(mut member: Vec<u8>) -> Vec<u8> {
+    let at = member.len() - 8;
+    member[at] ^= 1;
+    member
+}
+
+#[test]
+fn intact_input_is_decoded_unchanged() {
+    let dir = TempDir::new();
+    dir.write("in.gz", &[gzip(b"a\r\n"), gzip(b"b")].concat());
+    let run = run(&dir, &["--recover", "in.gz"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, b"a\r\nb");
+    assert!(run.stderr.is_empty());
+}
+
+#[test]
+fn drops_a_member_that_fails_its_crc() {
+    let dir = TempDir::new();
+    let first = gzip(b"first\n");
+    let bad = corrupt_crc(gzip(b"second\n"));
+    dir.write("in.gz", &[first.clone(), bad.clone(), gzip(b"third\n")].concat());
+    let run = run(&dir, &["--recover", "in.gz"]);
+    assert_eq!(run.code, Some(13), "{}", run.stderr);
+    assert_eq!(run.stdout, b"first\nthird\n");
+    let range = format!("skipped bytes {}..{} of 'in.gz': CRC32 mismatch", first.len(), first.len() + bad.len());
+    assert!(run.stderr.contains(&range), "{}", run.stderr);
+}
+
+#[test]
+fn resynchronizes_after_corrupt_data() {
+    let dir = TempDir::new();
+    let first = gzip(b"first\n");
+    let mut bad = gzip(&[b'x'; 1000]);
+    // 最初のブロックの種類を、使われていない 11 にする。
+    bad[10] = 0x07;
+    let data = [first.clone(), b"garbage".to_vec(), bad, gzip(b"last\n")].concat();
+    dir.write("in.gz", &data);
+    let run = run(&dir, &["--recover", "in.gz"]);
+    assert_eq!(run.code, Some(13), "{}", run.stderr);
+    assert_eq!(run.stdout, b"first\nlast\n");
+    assert!(run.stderr.contains(&format!("skipped bytes {}..", first.len())), "{}", run.stderr);
+}
+
+#[test]
+fn writes_the_partial_data_of_a_truncated_last_member() {
+    let dir = TempDir::new();
+    let last = gzip(b"partial line\n");
+    dir.write("in.gz", &[gzip(b"first\n"), last[..last.len() - 4].to_vec()].concat());
+    let run = run(&dir, &["--recover", "in.gz"]);
+    assert_eq!(run.code, Some(13), "{}", run.stderr);
+    assert_eq!(run.stdout, b"first\npartial line\n");
+    assert!(run.stderr.contains("the partial data was written"), "{}", run.stderr);
+}
+
+#[test]
+fn large_members_are_checked_before_writing() {
+    let dir = TempDir::new();
+    // メモリーに置く上限より大きなメンバーも、CRC32 を確かめてから書き出す。
+    let big = vec![b'z'; 70 << 20];
+    let data = [gzip(&big), corrupt_crc(gzip(&big)), gzip(b"end")].concat();
+    dir.write("in.gz", &data);
+    let run = run(&dir, &["--recover", "-o", "out", "in.gz"]);
+    assert_eq!(run.code, Some(13), "{}", run.stderr);
+    let out = dir.read("out");
+    assert_eq!(out.len(), big.len() + 3);
+    assert!(out.starts_with(&big) && out.ends_with(b"zend"));
+}
+
+#[test]
+fn usage() {
+    let dir = TempDir::new();
+    assert_eq!(run(&dir, &["--recover", "-j", "2", "in.gz"]).code, Some(2));
+    assert_eq!(run(&dir, &["--recover", "--raw", "in.gz"]).code, Some(2));
+}
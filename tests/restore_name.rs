@@ -0,0 +1,73 @@
+mod common;
+
+use std::io::Write;
+use std::time::{Duration, UNIX_EPOCH};
+
+use common::{gzip, run, TempDir};
+use flate2::{Compression, GzBuilder};
+
+const MTIME: u32 = 1_700_000_000;
+
+fn named(fname: &[u8], mtime: u32, data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzBuilder::new().filename(fname).mtime(mtime).write(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn mtime(dir: &TempDir, name: &str) -> Duration {
+    std::fs::metadata(dir.join(name)).unwrap().modified().unwrap().duration_since(UNIX_EPOCH).unwrap()
+}
+
+#[test]
+fn gunzip_restores_the_name_and_mtime() {
+    let dir = TempDir::new();
+    dir.write("renamed.gz", &named(b"original.txt", MTIME, b"text\n"));
+    let run = run(&dir, &["-g", "-N", "renamed.gz"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(dir.names(), ["original.txt", "renamed.gz"]);
+    assert_eq!(dir.read("original.txt"), b"text\n");
+    assert_eq!(mtime(&dir, "original.txt"), Duration::from_secs(u64::from(MTIME)));
+}
+
+#[test]
+fn output_gets_the_mtime_of_the_first_input() {
+    let dir = TempDir::new();
+    dir.write("a.gz", &named(b"a", MTIME, b"a\n"));
+    dir.write("b.gz", &named(b"b", MTIME + 100, b"b\n"));
+    assert_eq!(run(&dir, &["-o", "out.txt", "-N", "a.gz", "b.gz"]).code, Some(0));
+    assert_eq!(dir.read("out.txt"), b"a\nb\n");
+    assert_eq!(mtime(&dir, "out.txt"), Duration::from_secs(u64::from(MTIME)));
+}
+
+#[test]
+fn unsafe_fnames_are_stripped_or_ignored() {
+    let dir = TempDir::new();
+    dir.write("escape.gz", &named(b"../../etc/passwd", 0, b"x\n"));
+    assert_eq!(run(&dir, &["-g", "-N", "escape.gz"]).code, Some(0));
+    assert!(dir.exists("passwd"));
+
+    dir.write("dots.gz", &named(b"..", 0, b"y\n"));
+    let dots = run(&dir, &["-g", "-N", "dots.gz"]);
+    assert_eq!(dots.code, Some(0), "{}", dots.stderr);
+    assert!(dots.stderr.contains("the FNAME of 'dots.gz' cannot be used as a file name; ignoring it"), "{}", dots.stderr);
+    assert_eq!(dir.read("dots"), b"y\n");
+}
+
+#[test]
+fn fname_naming_the_input_is_refused() {
+    let dir = TempDir::new();
+    let data = named(b"self.gz", 0, b"z\n");
+    dir.write("self.gz", &data);
+    let run = run(&dir, &["-g", "-N", "--delete", "--force", "self.gz"]);
+    assert_eq!(run.code, Some(1));
+    assert!(run.stderr.contains("the FNAME of 'self.gz' names the input file itself"), "{}", run.stderr);
+    assert_eq!(dir.read("self.gz"), data);
+}
+
+#[test]
+fn without_mtime_the_output_keeps_the_current_time() {
+    let dir = TempDir::new();
+    dir.write("plain.txt.gz", &gzip(b"p\n"));
+    assert_eq!(run(&dir, &["-g", "-N", "plain.txt.gz"]).code, Some(0));
+    assert!(mtime(&dir, "plain.txt") > Duration::from_secs(u64::from(MTIME)));
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use std::io::Write;
+
+use common::{gzip, run, run_in, TempDir};
+use flate2::{Compression, GzBuilder};
+
+fn named(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzBuilder::new().filename(name).write(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[test]
+fn writes_each_member_to_its_own_file() {
+    let dir = TempDir::new();
+    let a = named("a.txt", b"a\n");
+    let b = named("b.txt", b"b\n");
+    let bare = gzip(b"bare\n");
+    dir.write("all.gz", &[a.clone(), b.clone(), bare.clone()].concat());
+    let run = run(&dir, &["split", "all.gz"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    // FNAME があればその名前、なければ入力の名前と番号を使う。メンバーのバイト列はそのまま。
+    assert_eq!(dir.read("a.txt.gz"), a);
+    assert_eq!(dir.read("b.txt.gz"), b);
+    assert_eq!(dir.read("all.0002.gz"), bare);
+    assert!(run.stdout_text().contains(&format!("all.gz: member 1 (offset {}, {} bytes) -> ./b.txt.gz", a.len(), b.len())));
+}
+
+#[test]
+fn numbers_duplicate_names_and_strips_directories() {
+    let dir = TempDir::new();
+    dir.write("in.gz", &[named("x.txt", b"1"), named("x.txt", b"2"), named("../../evil.txt", b"3")].concat());
+    assert_eq!(run(&dir, &["split", "-d", ".", "in.gz"]).code, Some(0));
+    assert_eq!(dir.names(), ["evil.txt.gz", "in.gz", "x.txt.0001.gz", "x.txt.gz"]);
+}
+
+#[test]
+fn directory_and_force() {
+    let dir = TempDir::new();
+    std::fs::create_dir(dir.join("out")).unwrap();
+    dir.write("in.gz", &named("x.txt", b"new"));
+    dir.write("out/x.txt.gz", b"old");
+    let refused = run(&dir, &["split", "-d", "out", "in.gz"]);
+    assert_eq!(refused.code, Some(1), "{}", refused.stderr);
+    assert_eq!(dir.read("out/x.txt.gz"), b"old");
+    assert_eq!(run(&dir, &["split", "-d", "out", "-f", "in.gz"]).code, Some(0));
+    assert_eq!(dir.read("out/x.txt.gz"), dir.read("in.gz"));
+}
+
+#[test]
+fn numbers_inputs_with_the_same_name_apart() {
+    for force in [false, true] {
+        let dir = TempDir::new();
+        std::fs::create_dir(dir.join("a")).unwrap();
+        std::fs::create_dir(dir.join("b")).unwrap();
+        std::fs::create_dir(dir.join("out")).unwrap();
+        dir.write("a/x.gz", &gzip(b"a\n"));
+        dir.write("b/x.gz", &gzip(b"b\n"));
+        let args: &[&str] = if force { &["split", "-f", "-d", "out", "a/x.gz", "b/x.gz"] } else { &["split", "-d", "out", "a/x.gz", "b/x.gz"] };
+        let split = run(&dir, args);
+        assert_eq!(split.code, Some(0), "{}", split.stderr);
+        assert_eq!(dir.read("out/x.0000.gz"), gzip(b"a\n"));
+        assert_eq!(dir.read("out/x.0000.1.gz"), gzip(b"b\n"));
+    }
+}
+
+#[test]
+fn splits_the_standard_input() {
+    let dir = TempDir::new();
+    let data = [gzip(b"one\n"), gzip(b"two\n")].concat();
+    let run = run_in(dir.path(), &["split", "-"], &data);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(dir.read("stdin.0000.gz"), gzip(b"one\n"));
+    assert_eq!(dir.read("stdin.0001.gz"), gzip(b"two\n"));
+}
+
+#[test]
+fn writes_the_members_before_a_corrupt_one() {
+    let dir = TempDir::new();
+    let good = gzip(b"good\n");
+    let mut bad = gzip(b"bad\n");
+    bad.truncate(bad.len() - 2);
+    dir.write("in.gz", &[good.clone(), bad].concat());
+    let run = run(&dir, &["split", "in.gz"]);
+    assert_eq!(run.code, Some(7), "{}", run.stderr);
+    assert!(run.stderr.contains("wrote 1 member(s) of 'in.gz' before the error"), "{}", run.stderr);
+    assert_eq!(dir.read("in.0000.gz"), good);
+    assert!(!dir.exists("in.0001.gz"));
+}
+
+#[test]
+fn never_writes_over_the_input() {
+    let dir = TempDir::new();
+    // FNAME から作った名前が入力ファイル自身になる。
+    let data = [named("in", b"first\n"), named("in", b"second\n")].concat();
+    dir.write("in.gz", &data);
+    let run = run(&dir, &["split", "-f", "in.gz"]);
+    assert_eq!(run.code, Some(2), "{}", run.stderr);
+    assert_eq!(dir.read("in.gz"), data);
+}
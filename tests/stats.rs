@@ -0,0 +1,67 @@
+mod common;
+
+use common::{gzip, run, TempDir};
+
+// "hello world\nfoo" と " bar\n" と空のメンバー。"foo bar" はメンバーをまたぐが、単語は4つ。
+fn three_members() -> (TempDir, [Vec<u8>; 3]) {
+    let dir = TempDir::new();
+    let members = [gzip(b"hello world\nfoo"), gzip(b" bar\n"), gzip(b"")];
+    dir.write("in.gz", &members.concat());
+    (dir, members)
+}
+
+#[test]
+fn text_output() {
+    let (dir, members) = three_members();
+    let run = run(&dir, &["stats", "in.gz"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let text = run.stdout_text();
+    let (a, b, c) = (members[0].len(), members[1].len(), members[2].len());
+    assert!(text.starts_with("in.gz\n"), "{}", text);
+    assert!(text.contains(&format!("  member 0: offset 0, {} -> 15 bytes, ratio ", a)), "{}", text);
+    assert!(text.contains("1 lines, 3 words\n"), "{}", text);
+    assert!(text.contains(&format!("  member 1: offset {}, {} -> 5 bytes,", a, b)), "{}", text);
+    // 展開後が空のメンバーには、減った割合を表示しない。
+    assert!(text.contains(&format!("  member 2: offset {}, {} -> 0 bytes, ratio 0.00:1, 0 lines, 0 words\n", a + b, c)), "{}", text);
+    assert!(text.contains("  members            3\n"), "{}", text);
+    assert!(text.contains(&format!("  compressed size    {} bytes\n", a + b + c)), "{}", text);
+    assert!(text.contains("  lines              2\n  words              4\n  bytes              20\n"), "{}", text);
+}
+
+#[test]
+fn json_output() {
+    let (dir, members) = three_members();
+    dir.write("b.gz", &gzip(b"x\n"));
+    let run = run(&dir, &["stats", "--json", "in.gz", "b.gz"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let text = run.stdout_text();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", text);
+    assert_eq!((lines[0], lines[3]), ("[", "]"));
+    assert!(lines[1].starts_with("  {\"file\":\"in.gz\",\"member_count\":3,"), "{}", text);
+    assert!(lines[1].ends_with("},"), "{}", text);
+    assert!(lines[1].contains("\"lines\":2,\"words\":4,\"bytes\":20,"), "{}", text);
+    assert!(lines[1].contains(&format!("{{\"index\":1,\"offset\":{},", members[0].len())), "{}", text);
+    assert!(lines[1].contains("\"uncompressed_length\":0,\"ratio\":0.0000,\"space_saving\":null,"), "{}", text);
+    assert!(lines[2].starts_with("  {\"file\":\"b.gz\",\"member_count\":1,"), "{}", text);
+    assert!(lines[2].ends_with("}]}"), "{}", text);
+}
+
+#[test]
+fn counts_match_wc() {
+    let dir = TempDir::new();
+    let text = "  leading\tand trailing  \n\nx\u{b}y\u{c}z\r\nno newline";
+    dir.write("in.gz", &gzip(text.as_bytes()));
+    let run = run(&dir, &["stats", "in.gz"]);
+    assert!(run.stdout_text().contains("  lines              3\n  words              8\n"), "{}", run.stdout_text());
+}
+
+#[test]
+fn json_is_not_left_open_on_an_error() {
+    let (dir, _) = three_members();
+    dir.write("bad.gz", &gzip(b"data\n")[..12]);
+    let run = run(&dir, &["stats", "--json", "in.gz", "bad.gz"]);
+    assert_eq!(run.code, Some(7), "{}", run.stderr);
+    assert!(run.stderr.contains("'bad.gz'"), "{}", run.stderr);
+    assert!(run.stdout.is_empty(), "{}", run.stdout_text());
+}